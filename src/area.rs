@@ -1,12 +1,13 @@
-use std::{collections::VecDeque, path::PathBuf, str::FromStr};
+use std::{collections::{HashSet, VecDeque}, path::PathBuf, str::FromStr};
 
-use glamx::{Pose2, Vec2, vec2};
-use macroquad::{camera::Camera2D, color::{RED, WHITE}, input::{KeyCode, is_key_released}, math::Rect, miniquad::TextureId, prelude::{gl_use_default_material, gl_use_material}, shapes::{draw_circle, draw_rectangle}, time::get_time, ui::Drag::No, window::{clear_background, screen_height, screen_width}};
+use glamx::{IVec2, Pose2, Vec2, vec2};
+use macroquad::{camera::Camera2D, color::{RED, WHITE}, input::KeyCode, math::Rect, miniquad::TextureId, prelude::{gl_use_default_material, gl_use_material}, shapes::{draw_circle, draw_rectangle}, time::get_time, ui::Drag::No, window::{clear_background, screen_height, screen_width}};
 use noise::{NoiseFn, Perlin};
+use rapier2d::prelude::{SharedShape, VoxelData};
 use serde::{Deserialize, Serialize, de};
 
 use crate::{
-    ClientId, ClientTickContext, Owner, Prefabs, ServerIO, SwapIter, TextureLoader, TickContext, ambiance::{Ambiance, AmbianceSave}, background::{self, Background, BackgroundSave}, base_prop::{BaseProp, NewProp, PropId}, base_prop_save::BasePropSave, bullet_trail::BulletTrail, clip::{Clip, ClipSave}, compound_test::CompoundTest, computer::Computer, decoration::{Decoration, DecorationSave}, dissolved_pixel::DissolvedPixel, drawable::{DrawContext, Drawable}, dropped_item::{DroppedItem, DroppedItemSave}, enemy::{Enemy, EnemySave, NewEnemyUpdate}, font_loader::FontLoader, material_loader::MaterialLoader, player::{Facing, NewPlayer, Player, PlayerSave}, prop::Prop, prop_save::PropSave, rapier_mouse_world_pos, rapier_to_macroquad, selectable_object_id::{SelectableObject, SelectableObjectId}, sound_loader::SoundLoader, space::Space, texture_loader::ClientTextureLoader, tile::{Tile, TileSave}, updates::NetworkPacket, uuid_u64, weapons::{bullet_impact_data::BulletImpactData, smg::weapon::SMG, weapon::weapon::WeaponOwner}};
+    ClientId, ClientTickContext, Owner, Prefabs, ServerIO, SwapIter, TextureLoader, TickContext, ambiance::{Ambiance, AmbianceSave}, background::{self, Background, BackgroundSave}, base_prop::{BaseProp, NewProp, PropId}, base_prop_save::BasePropSave, bullet_trail::BulletTrail, clip::{Clip, ClipSave}, compound_test::CompoundTest, computer::Computer, decoration::{Decoration, DecorationSave}, dissolved_pixel::DissolvedPixel, drawable::{DrawContext, Drawable}, dropped_item::{DroppedItem, DroppedItemSave}, enemy::{Enemy, EnemySave}, enemy_perception::Noise, font_loader::FontLoader, interpolation::InterpolationSettings, material_loader::MaterialLoader, nav_graph::NavGraph, player::{Facing, NewPlayer, Player, PlayerSave}, portal::{Portal, PortalSave}, prop::Prop, prop_save::PropSave, rapier_mouse_world_pos, rapier_to_macroquad, selectable_object_id::{SelectableObject, SelectableObjectId}, sound_loader::SoundLoader, space::Space, texture_loader::ClientTextureLoader, tile::{Tile, TileSave}, updates::NetworkPacket, uuid_u64, voxel_diff::{PropVoxelDiff, PropVoxelDiffs, VoxelDiff, VoxelDiffError}, wave_director::{Wave, WaveDirector}, weapons::{bullet_impact_data::BulletImpactData, smg::weapon::SMG, weapon::weapon::WeaponOwner}};
 
 macro_rules! test {
     ($s:ident) => {
@@ -46,40 +47,16 @@ pub struct Area {
     pub despawn_y: f32,
     pub master: Option<ClientId>,
     pub ambiance: Vec<Ambiance>,
-    pub wave_data: WaveData,
+    pub wave_director: WaveDirector,
     pub compound_test: Vec<CompoundTest>,
     pub tiles: Vec<Vec<Option<Tile>>>,
     pub impact_points: Vec<glamx::Vec2>,
-    pub bullet_impact_queue: Vec<BulletImpactData>
-}
-
-pub struct WaveData {
-    wave: i32,
-    wave_end: web_time::Instant,
-    wave_start: web_time::Instant,
-    last_batch_spawn: web_time::Instant,
-    batch_size: u32,
-    total_size: u32,
-    spawned_this_wave: u32,
-    batch_interval: web_time::Duration,
-    active: bool
-}
-
-impl WaveData {
-    pub fn default() -> Self {
-        WaveData {
-            wave: 1,
-            wave_end: web_time::Instant::now(),
-            wave_start: web_time::Instant::now(),
-            last_batch_spawn: web_time::Instant::now(),
-            batch_size: 2,
-            total_size: 10,
-            spawned_this_wave: 0,
-            batch_interval: web_time::Duration::from_secs_f32(5.),
-            active: false
-
-        }
-    }
+    pub bullet_impact_queue: Vec<BulletImpactData>,
+    pub portals: Vec<Portal>,
+    /// Where enemies can go. Built from the tiles, clips and props
+    pub nav_graph: NavGraph,
+    /// Recent loud things for enemies to hear
+    pub noises: Vec<Noise>
 }
 
 impl Area { 
@@ -96,13 +73,19 @@ impl Area {
             ctx.debug_strings.push(format!("Space tick: {:?}", then.elapsed()));
         }
 
+        // replays are just for watching, we dont get a player and can't spawn stuff
+        if let TickContext::Client(ctx) = ctx && !ctx.network_io.is_offline() {
+            self.spawn_player_if_not_in_game(ctx);
+
+            if !ctx.typing {
+                self.debug_spawn_prop(ctx);
+            }
+        }
+
         if let TickContext::Client(ctx) = ctx {
             self.start_ambiance(ctx.sounds);
-            self.spawn_player_if_not_in_game(ctx);
-            self.debug_spawn_prop(ctx);
-            self.debug_spawn_enemy(ctx);
 
-            if is_key_released(KeyCode::C) {
+            if ctx.input.is_key_released(KeyCode::C) && !ctx.typing {
 
                 let mos_pos = rapier_mouse_world_pos(ctx.camera_rect);
                 let pixel_pos = Pose2::new(mos_pos, 0.);
@@ -120,17 +103,49 @@ impl Area {
             }
         };
 
+        if let TickContext::Server(_) = ctx {
+            let players_present = self.players.iter().any(|player| !player.despawn);
+
+            self.wave_director.tick(ctx, self.id, &mut self.enemies, &mut self.space, players_present);
+        }
+
         self.handle_bullet_impacts(ctx);
+
+        self.update_nav_graph(ctx);
         
         let then = web_time::Instant::now();
         self.tick_entities(ctx);
         ctx.push_debug_string(format!("Tick entities: {:?}", then.elapsed()));
+        self.send_voxel_diffs(ctx);
         self.despawn_entities(ctx);
+
+        self.noises.retain(|noise| !noise.expired());
         
     }
 
 
 
+    /// Only whoever is moving the enemies around needs this, and clients that want to look at it
+    pub fn update_nav_graph(&mut self, ctx: &mut TickContext) {
+
+        let showing = match ctx {
+            TickContext::Client(client_ctx) => client_ctx.show_nav_graph,
+            _ => false,
+        };
+
+        let wanted = showing || self.enemies.iter().any(|enemy| enemy.owner == ctx.id());
+
+        if !wanted || !self.nav_graph.needs_rebuild() {
+            return;
+        }
+
+        let then = web_time::Instant::now();
+
+        self.nav_graph.rebuild(&self.tiles, &self.clips, &self.props, &self.space);
+
+        ctx.push_debug_string(format!("Nav graph rebuild: {:?}", then.elapsed()));
+    }
+
     pub fn tick_entities(&mut self, ctx: &mut TickContext) {
         self.tick_enemies(ctx);
         let then = web_time::Instant::now();
@@ -195,10 +210,18 @@ impl Area {
             clip.draw(ctx, &self.space)
         }
 
+        for portal in &self.portals {
+            portal.draw(ctx)
+        }
+
         for dissolved_pixel in &self.dissolved_pixels {
             dissolved_pixel.draw(ctx, &self.space);
         }
 
+        if let TickContext::Client(client_ctx) = ctx && client_ctx.show_nav_graph {
+            self.nav_graph.draw(ctx);
+        }
+
     
 
     
@@ -259,11 +282,13 @@ impl Area {
                 despawn_y: &mut self.despawn_y,
                 master: &mut self.master,
                 ambiance: &mut self.ambiance,
-                wave_data: &mut self.wave_data,
+                wave_director: &mut self.wave_director,
                 compound_test: &mut self.compound_test,
                 tiles: &mut self.tiles,
                 impact_points: &mut self.impact_points,
                 bullet_impact_queue: &mut self.bullet_impact_queue,
+                nav_graph: &mut self.nav_graph,
+                noises: &mut self.noises,
             };
 
             enemy.tick(
@@ -305,11 +330,13 @@ impl Area {
                 despawn_y: &mut self.despawn_y,
                 master: &mut self.master,
                 ambiance: &mut self.ambiance,
-                wave_data: &mut self.wave_data,
+                wave_director: &mut self.wave_director,
                 compound_test: &mut self.compound_test,
                 tiles: &mut self.tiles,
                 impact_points: &mut self.impact_points,
                 bullet_impact_queue: &mut self.bullet_impact_queue,
+                nav_graph: &mut self.nav_graph,
+                noises: &mut self.noises,
             };
 
             prop.tick(&mut area_context, ctx);
@@ -358,11 +385,13 @@ impl Area {
                 despawn_y: &mut self.despawn_y,
                 master: &mut self.master,
                 ambiance: &mut self.ambiance,
-                wave_data: &mut self.wave_data,
+                wave_director: &mut self.wave_director,
                 compound_test: &mut self.compound_test,
                 tiles: &mut self.tiles,
                 impact_points: &mut self.impact_points,
-                bullet_impact_queue: &mut self.bullet_impact_queue
+                bullet_impact_queue: &mut self.bullet_impact_queue,
+                nav_graph: &mut self.nav_graph,
+                noises: &mut self.noises
             };
             player.client_tick(
                 ctx, 
@@ -373,6 +402,163 @@ impl Area {
         }
     }
 
+    /// Send all the voxels our props lost this tick in one packet
+    pub fn send_voxel_diffs(&mut self, ctx: &mut TickContext) {
+
+        let diffs: Vec<PropVoxelDiff> = self.props
+            .iter_mut()
+            .filter_map(|prop| {
+                let removed_voxels = prop.take_pending_removed_voxels();
+
+                if removed_voxels.is_empty() {
+                    return None;
+                }
+
+                Some(
+                    PropVoxelDiff {
+                        prop_id: prop.id(),
+                        removed: VoxelDiff::from_cells(&removed_voxels),
+                    }
+                )
+            })
+            .collect();
+
+        if diffs.is_empty() {
+            return;
+        }
+
+        ctx.send_network_packet(
+            PropVoxelDiffs {
+                area_id: self.id,
+                diffs,
+            }.into()
+        );
+    }
+
+    /// Remove the voxels in the diff from the prop. Returns false if we don't have the prop
+    pub fn apply_prop_voxel_diff(&mut self, diff: &PropVoxelDiff) -> Result<bool, VoxelDiffError> {
+
+        let Some(prop) = self.props.iter_mut().find(|prop| prop.id() == diff.prop_id) else {
+            return Ok(false);
+        };
+
+        let collider = self.space.collider_set
+            .get_mut(prop.collider_handle())
+            .unwrap();
+
+        let cos = collider.rotation().cos();
+        let sin = collider.rotation().sin();
+
+        let body = self.space.rigid_body_set.get(prop.rigid_body_handle()).unwrap();
+
+        let body_rotation = body.rotation().clone();
+        let body_vels = body.vels().clone();
+
+        let current_voxels: Vec<VoxelData> = collider.shape()
+            .as_voxels()
+            .unwrap()
+            .voxels()
+            .filter(|voxel| !voxel.state.is_empty())
+            .collect();
+
+        // can't take off more than the prop started with
+        let max_cells = current_voxels.len() + prop.removed_voxels().len();
+
+        let removed_voxels: HashSet<IVec2> = diff.removed.cells(max_cells)?.into_iter().collect();
+
+        let removed_voxels_positions: Vec<Vec2> = current_voxels
+            .iter()
+            .filter(|voxel| removed_voxels.contains(&voxel.grid_coords))
+            .map(
+                |voxel|
+                {
+                    let rotated_x = voxel.center.x * cos - voxel.center.y * sin;
+                    let rotated_y = voxel.center.x * sin + voxel.center.y * cos;
+
+                    Vec2::new(rotated_x + collider.translation().x, rotated_y + collider.translation().y)
+                }
+            )
+            .collect();
+
+        let new_voxels: Vec<IVec2> = current_voxels
+            .iter()
+            .map(|voxel| voxel.grid_coords)
+            .filter(|grid_coords| !removed_voxels.contains(grid_coords))
+            .collect();
+
+        collider.set_shape(
+            SharedShape::voxels(vec2(8., 8.), &new_voxels)
+        );
+
+        for removed_voxel in removed_voxels_positions {
+            self.dissolved_pixels.push(
+                DissolvedPixel::new(
+                    Pose2::new(removed_voxel, body_rotation.angle()),
+                    &mut self.space,
+                    WHITE,
+                    8.,
+                    Some(10.),
+                    Some(body_vels)
+                )
+            );
+        }
+
+        // props can have a lot of removed voxels so dont scan the whole list for every new one
+        let mut already_removed: HashSet<IVec2> = prop.removed_voxels().iter().copied().collect();
+
+        for removed_voxel in removed_voxels {
+            if already_removed.insert(removed_voxel) {
+                prop.removed_voxels_mut().push(removed_voxel);
+            }
+        }
+
+        *prop.voxels_modified_mut() = true;
+
+        Ok(true)
+    }
+
+    /// Move everything we dont own to where its owner said it was a little while ago
+    pub fn interpolate_remote_entities(&mut self, client_id: ClientId, settings: &InterpolationSettings) {
+
+        let us = Owner::ClientId(client_id);
+
+        for player in &mut self.players {
+
+            // we predict our own player instead
+            if player.owner == us {
+                continue;
+            }
+
+            let body = self.space.rigid_body_set.get_mut(player.body.body_handle).unwrap();
+
+            player.interpolation.apply(body, settings);
+        }
+
+        for enemy in &mut self.enemies {
+
+            if enemy.owner == us {
+                enemy.interpolation.clear();
+                continue;
+            }
+
+            let body = self.space.rigid_body_set.get_mut(enemy.body.body_handle).unwrap();
+
+            enemy.interpolation.apply(body, settings);
+        }
+
+        for prop in &mut self.props {
+
+            if prop.owner() == Some(us) {
+                prop.interpolation_mut().clear();
+                continue;
+            }
+
+            let body = self.space.rigid_body_set.get_mut(prop.rigid_body_handle()).unwrap();
+
+            prop.interpolation_mut().apply(body, settings);
+        }
+    }
+
     pub fn tick_computer(&mut self, ctx: &mut ClientTickContext) {
         if let Some(computer) = &mut self.computer {
             computer.tick(ctx, &mut self.players, &self.space);
@@ -496,11 +682,14 @@ impl Area {
             despawn_y: 0.,
             master: None,
             ambiance: Vec::new(),
-            wave_data: WaveData::default(),
+            wave_director: WaveDirector::new(Vec::new(), Vec::new()),
             compound_test: Vec::new(),
             tiles: vec![vec![None; world_height]; world_width],
             impact_points: vec![],
-            bullet_impact_queue: Vec::new()
+            bullet_impact_queue: Vec::new(),
+            portals: Vec::new(),
+            nav_graph: NavGraph::new(),
+            noises: Vec::new()
         }
     }
 
@@ -657,7 +846,7 @@ impl Area {
 
     pub fn debug_spawn_prop(&mut self, ctx: &mut ClientTickContext) {
 
-        if is_key_released(KeyCode::E) {
+        if ctx.input.is_key_released(KeyCode::E) {
             
             let prefab_save: BasePropSave = serde_json::from_str(&ctx.prefabs.get_prefab_data("prefabs\\generic_physics_props\\box2.json")).unwrap();
 
@@ -690,33 +879,6 @@ impl Area {
         }
     }
 
-    pub fn debug_spawn_enemy(&mut self, ctx: &mut ClientTickContext) {
-
-        if !is_key_released(KeyCode::T) {
-            return;
-        }
-        let mouse_pos = rapier_mouse_world_pos(&ctx.camera_rect);
-        
-        let enemy = Enemy::new( 
-            Pose2::new(
-                mouse_pos,
-                0.
-            ), 
-            crate::Owner::Server, 
-            &mut self.space, 
-            None
-        );
-
-        ctx.network_io.send_network_packet(crate::updates::NetworkPacket::NewEnemyUpdate(
-            NewEnemyUpdate {
-                area_id: self.id,
-                enemy: enemy.save(&mut self.space),
-            }
-        ));
-
-        self.enemies.push(enemy);
-    }
-
     // the player should be spawned by the server - this is temporary
     pub fn spawn_player_if_not_in_game(
         &mut self, ctx: &mut ClientTickContext
@@ -734,6 +896,8 @@ impl Area {
                     &mut self.space, 
                     Owner::ClientId(*ctx.client_id)
                 );
+
+                player.name = ctx.network_io.name.clone();
             
                 ctx.network_io.send_network_packet(
                     NetworkPacket::NewPlayer(
@@ -781,67 +945,6 @@ impl Area {
         }
     }
 
-    pub fn wave_logic(&mut self, ctx: &mut ClientTickContext) {
-
-        // end wave
-        if self.wave_data.spawned_this_wave >= self.wave_data.total_size {
-            self.wave_data.wave_end = web_time::Instant::now();
-
-            self.wave_data.spawned_this_wave = 0;
-
-            self.wave_data.active = false;
-
-            
-        }
-
-        // start new wave
-        if self.wave_data.wave_end.elapsed().as_secs_f32() > 5. && self.wave_data.active == false  {
-
-            self.wave_data.wave += 1;
-
-            self.wave_data.batch_size += 2;
-
-            self.wave_data.total_size += 10;
-
-            self.wave_data.active = true;
-
-            self.wave_data.wave_start = web_time::Instant::now();
-        }
-
-        // spawn batch
-        if self.wave_data.active && self.wave_data.last_batch_spawn.elapsed() > self.wave_data.batch_interval && self.enemies.len() == 0 {
-
-            for i in 0..self.wave_data.batch_size {
-
-                let enemy = Enemy::new(
-                    Pose2::new(
-                        glamx::vec2(2400. + (i as f32 * 50.), 200.),
-                        0.
-                    ), 
-                    crate::Owner::Server, 
-                    &mut self.space, 
-                    None
-                
-                );
-
-                ctx.network_io.send_network_packet(
-                    NetworkPacket::NewEnemyUpdate(
-                        NewEnemyUpdate {
-                            area_id: self.id,
-                            enemy: enemy.save(&mut self.space),
-                        }
-                    )
-                );
-                self.enemies.push(
-                    enemy
-                );
-            }
-
-            self.wave_data.last_batch_spawn = web_time::Instant::now();
-          
-        }
-    }
-
     pub fn despawn_entities(&mut self, ctx: &mut TickContext) {
         self.dropped_items.retain_mut(
             |dropped_item|
@@ -864,6 +967,9 @@ impl Area {
                     return true;
                 }
 
+                // enemies might have been planning to go through it
+                self.nav_graph.mark_dirty();
+
                 prop.despawn_callback(&mut self.space);
 
                 false
@@ -911,6 +1017,8 @@ impl Area {
                     return true;
                 }
 
+                self.nav_graph.mark_dirty();
+
                 clip.despawn_callback(&mut self.space);
                 false
             }
@@ -947,11 +1055,13 @@ impl Area {
                 despawn_y: &mut self.despawn_y,
                 master: &mut self.master,
                 ambiance: &mut self.ambiance,
-                wave_data: &mut self.wave_data,
+                wave_director: &mut self.wave_director,
                 compound_test: &mut self.compound_test,
                 tiles: &mut self.tiles,
                 impact_points: &mut self.impact_points,
                 bullet_impact_queue: &mut self.bullet_impact_queue,
+                nav_graph: &mut self.nav_graph,
+                noises: &mut self.noises,
             };
 
             player.despawn_callback(ctx, &mut area_context);
@@ -1014,11 +1124,13 @@ impl Area {
                 despawn_y: &mut self.despawn_y,
                 master: &mut self.master,
                 ambiance: &mut self.ambiance,
-                wave_data: &mut self.wave_data,
+                wave_director: &mut self.wave_director,
                 compound_test: &mut self.compound_test,
                 tiles: &mut self.tiles,
                 impact_points: &mut self.impact_points,
                 bullet_impact_queue: &mut self.bullet_impact_queue,
+                nav_graph: &mut self.nav_graph,
+                noises: &mut self.noises,
             };
 
             let body_collider = enemy.body.collider_handle;
@@ -1069,11 +1181,13 @@ impl Area {
                 despawn_y: &mut self.despawn_y,
                 master: &mut self.master,
                 ambiance: &mut self.ambiance,
-                wave_data: &mut self.wave_data,
+                wave_director: &mut self.wave_director,
                 compound_test: &mut self.compound_test,
                 tiles: &mut self.tiles,
                 impact_points: &mut self.impact_points,
                 bullet_impact_queue: &mut self.bullet_impact_queue,
+                nav_graph: &mut self.nav_graph,
+                noises: &mut self.noises,
             };
             for impact in bullet_impact_queue.iter().filter(|impact| {impact.impacted_collider == collider}) {
                 prop.handle_bullet_impact(
@@ -1090,6 +1204,13 @@ impl Area {
 
     }
 
+    /// The portal the player is standing in, if any
+    pub fn find_portal(&self, player: &Player) -> Option<&Portal> {
+        let pos = self.space.rigid_body_set.get(player.body.body_handle).unwrap().position().translation;
+
+        self.portals.iter().find(|portal| portal.contains(pos))
+    }
+
     pub fn find_prop_mut(&mut self, id: PropId) -> Option<&mut Box<dyn Prop>> {
         if let Some(p) = self.props.iter_mut().find(|p| p.id() == id) {
             return Some(p);
@@ -1117,6 +1238,7 @@ impl Area {
         let mut enemies: Vec<Enemy> = Vec::new();
         let mut dropped_items: Vec<DroppedItem> = Vec::new();
         let mut ambiance: Vec<Ambiance> = Vec::new();  
+        let mut portals: Vec<Portal> = Vec::new();
         let mut tiles: Vec<Vec<Option<Tile>>> = vec![vec![None; 100]; 10_000];
         
         for decoration_save in save.decorations {
@@ -1152,7 +1274,7 @@ impl Area {
 
         for enemy_save in save.enemies {
             enemies.push(
-                Enemy::from_save(enemy_save, &mut space)
+                Enemy::from_save(enemy_save, &mut space, prefabs)
             );
         }
 
@@ -1168,6 +1290,12 @@ impl Area {
             );
         }
 
+        for portal_save in save.portals {
+            portals.push(
+                Portal::from_save(portal_save)
+            );
+        }
+
         for tile_save in save.tiles {
             let tile = Tile::from_save(tile_save.clone());
 
@@ -1208,12 +1336,14 @@ impl Area {
             despawn_y: save.despawn_y,
             master: save.master,
             ambiance,
-            wave_data: WaveData::default(),
+            wave_director: WaveDirector::new(save.waves, save.enemy_spawn_points),
             compound_test: Vec::new(),
             tiles,
             impact_points: Vec::new(),
-            bullet_impact_queue: vec![]
-
+            bullet_impact_queue: vec![],
+            portals,
+            nav_graph: NavGraph::new(),
+            noises: Vec::new()
         }
     }
 
@@ -1228,6 +1358,7 @@ impl Area {
         let mut enemies: Vec<EnemySave> = Vec::new();
         let mut dropped_items: Vec<DroppedItemSave> = Vec::new();
         let mut ambiances: Vec<AmbianceSave> = Vec::new();
+        let mut portals: Vec<PortalSave> = Vec::new();
         let tiles: Vec<TileSave> = vec![];
     
         for decoration in &self.decorations {
@@ -1285,6 +1416,12 @@ impl Area {
             dropped_items.push(dropped_item.save(&self.space))
         }
 
+        for portal in &self.portals {
+            portals.push(
+                portal.save()
+            );
+        }
+
         AreaSave {
             spawn_point: self.spawn_point,
             decorations,
@@ -1301,7 +1438,10 @@ impl Area {
             despawn_y: self.despawn_y,
             master: self.master,
             ambiance: ambiances,
-            tiles
+            tiles,
+            portals,
+            enemy_spawn_points: self.wave_director.spawn_points.clone(),
+            waves: self.wave_director.waves().clone()
 
         }
     }
@@ -1329,11 +1469,13 @@ pub struct AreaContext<'a> {
     pub despawn_y: &'a mut f32,
     pub master: &'a mut Option<ClientId>,
     pub ambiance: &'a mut Vec<Ambiance>,
-    pub wave_data: &'a mut WaveData,
+    pub wave_director: &'a mut WaveDirector,
     pub compound_test: &'a mut Vec<CompoundTest>,
     pub tiles: &'a mut Vec<Vec<Option<Tile>>>,
     pub impact_points: &'a mut Vec<glamx::Vec2>,
     pub bullet_impact_queue: &'a mut Vec<BulletImpactData>,
+    pub nav_graph: &'a mut NavGraph,
+    pub noises: &'a mut Vec<Noise>,
 }
 
 
@@ -1362,5 +1504,16 @@ pub struct AreaSave {
     #[serde[default]]
     ambiance: Vec<AmbianceSave>,
     #[serde[default]]
-    pub tiles: Vec<TileSave>
-}
\ No newline at end of file
+    pub tiles: Vec<TileSave>,
+    #[serde(default)]
+    portals: Vec<PortalSave>,
+    #[serde(default)]
+    enemy_spawn_points: Vec<Vec2>,
+    #[serde(default)]
+    waves: Vec<Wave>
+}
+impl AreaSave {
+    pub fn spawn_point(&self) -> Vec2 {
+        self.spawn_point
+    }
+}
@@ -8,7 +8,7 @@ use macroquad::{audio::play_sound_once, camera::{Camera2D, set_camera}, color::{
 use rapier2d::prelude::{AxisMask, ColliderBuilder, ColliderHandle, RigidBodyBuilder, RigidBodyHandle, RigidBodyType, RigidBodyVelocity, SharedShape, VoxelData};
 use serde::{Deserialize, Serialize};
 use strum::{Display, EnumIter};
-use crate::{ClearBackgroundParameters, ClientId, ClientTickContext, DrawCommand, DrawRectangleParameters, DrawTextureParameters, Owner, Prefabs, SetCameraParameters, SetMaterialTextureParameters, TextureLoader, TickContext, UseMaterialParameters, area::{self, Area, AreaContext, AreaId}, base_prop_save::BasePropSave, dissolved_pixel::DissolvedPixel, draw_preview, drawable::Drawable, editor_context_menu::{EditorContextMenu, EditorContextMenuData}, flood_fill, get_preview_resolution, material_loader::ExclusiveMaterialHandle, prop::Prop, prop_save::PropSave, rapier_to_macroquad, space::Space, texture_loader::ClientTextureLoader, updates::NetworkPacket, uuid_u64, weapons::bullet_impact_data::BulletImpactData};
+use crate::{ClearBackgroundParameters, ClientId, ClientTickContext, DrawCommand, DrawRectangleParameters, DrawTextureParameters, Owner, Prefabs, SetCameraParameters, SetMaterialTextureParameters, TextureLoader, TickContext, UseMaterialParameters, area::{self, Area, AreaContext, AreaId}, base_prop_save::BasePropSave, dissolved_pixel::{DissolvedPixel, dissolve_texture}, draw_preview, drawable::Drawable, editor_context_menu::{EditorContextMenu, EditorContextMenuData}, enemy_perception::{Noise, PROP_BREAK_NOISE_RADIUS, make_noise}, flood_fill, get_preview_resolution, interpolation::{InterpolationBuffer, snapshot_timestamp}, material_loader::ExclusiveMaterialHandle, prop::Prop, prop_ownership::PropInteraction, prop_save::PropSave, rapier_to_macroquad, space::Space, texture_loader::ClientTextureLoader, updates::NetworkPacket, uuid_u64, voxel_diff::VoxelDiff, weapons::bullet_impact_data::BulletImpactData};
 
 
 
@@ -87,6 +87,8 @@ pub struct BaseProp {
     pub voxels_modified_last_tick: bool,
     pub last_tick_removed_voxels: Vec<glamx::IVec2>,
     pub removed_voxels: Vec<glamx::IVec2>,
+    /// Voxels removed since we last sent a diff
+    pub pending_removed_voxels: Vec<glamx::IVec2>,
     pub spawned: web_time::Instant,
     // how long this prop should live before being automatically despawned
     pub lifespan: Option<web_time::Duration>,
@@ -94,7 +96,9 @@ pub struct BaseProp {
     pub sync_physics: bool,
     pub last_received_position_update: web_time::Instant,
     pub last_sent_position_update: web_time::Instant,
-    pub destruction_material_handle: Option<ExclusiveMaterialHandle>
+    pub destruction_material_handle: Option<ExclusiveMaterialHandle>,
+    /// Position updates from the owner when someone else owns this prop
+    pub interpolation: InterpolationBuffer
 
 }
 
@@ -325,6 +329,14 @@ impl Prop for BaseProp {
     fn last_received_position_update_mut(&mut self) -> &mut web_time::Instant {
         &mut self.last_ownership_change
     }
+
+    fn interpolation_mut(&mut self) -> &mut InterpolationBuffer {
+        &mut self.interpolation
+    }
+
+    fn take_pending_removed_voxels(&mut self) -> Vec<glamx::IVec2> {
+        std::mem::take(&mut self.pending_removed_voxels)
+    }
     
     fn mark_despawn(&mut self) {
         self.despawn = true;
@@ -360,23 +372,20 @@ impl BaseProp {
         self.despawn = true;
     }
 
-    pub fn force_owner_update_with_networking(
-        &mut self,
-        new_owner: Owner,
+    /// Let the server know we are messing with this prop. It decides who owns what, we just get told
+    pub fn request_ownership(
+        &self,
         area_context: &AreaContext,
         ctx: &mut TickContext
     ) {
-        self.owner = Some(new_owner);
-
-        self.last_ownership_change = web_time::Instant::now();
-
-        ctx.send_network_packet(
-            PropUpdateOwner {
-                owner: Some(new_owner),
-                id: self.id,
-                area_id: *area_context.id,
-            }.into()
-        );
+        if let TickContext::Client(ctx) = ctx {
+            ctx.network_io.send_network_packet(
+                PropInteraction {
+                    area_id: *area_context.id,
+                    prop_id: self.id,
+                }.into()
+            );
+        }
     }
 
     pub fn draw_preview(&self, ctx: &mut TickContext, size: f32, draw_pos: Vec2, _prefabs: &Prefabs, color: Option<Color>, rotation: f32) {
@@ -543,6 +552,7 @@ impl BaseProp {
                 }
                 new_voxels.remove(&voxel_index);
                 self.removed_voxels.push(voxel_index);
+                self.pending_removed_voxels.push(voxel_index);
             }
         }
 
@@ -633,9 +643,9 @@ impl BaseProp {
         let rotation = rigid_body.rotation().clone();
         let vels = rigid_body.vels().clone();
 
-        // BECOME the owner if arent the owner!!!!!!!!!!!!! RARGHHHHHH
-        if let Some(owner) = self.owner && ctx.id() != owner {
-            self.force_owner_update_with_networking(ctx.id(), area_context, ctx);
+        // the server hands props to whoever is shooting them
+        if self.owner != Some(ctx.id()) {
+            self.request_ownership(area_context, ctx);
         }
 
         let impacted_voxels = self.get_impacted_voxels(area_context.space, impact);
@@ -643,6 +653,9 @@ impl BaseProp {
         // this will probably never be zero
         if impacted_voxels.len() != 0 {
             self.voxels_modified = true;
+
+            // bits breaking off are loud enough for enemies to come and look
+            make_noise(ctx, area_context.noises, *area_context.id, Noise::new(impact.intersection_point, PROP_BREAK_NOISE_RADIUS, None));
         }
 
         
@@ -660,7 +673,7 @@ impl BaseProp {
             .collect();
 
 
-        let mut new_voxels: Vec<glamx::IVec2> = collider_voxels.voxels()
+        let new_voxels: Vec<glamx::IVec2> = collider_voxels.voxels()
             .filter(|voxel| !impacted_voxels_grid_coords.contains(&voxel.grid_coords))
             .map(|voxel| voxel.grid_coords)
             .collect();        
@@ -700,22 +713,13 @@ impl BaseProp {
             return;
         }
 
-        if let Some(break_apart_new_voxels) = self.break_apart(*area_context.id, ctx, area_context.space, &impacted_voxels_grid_coords, area_context.props) {
+        self.break_apart(*area_context.id, ctx, area_context.space, &impacted_voxels_grid_coords, area_context.props);
 
-            new_voxels = break_apart_new_voxels;
-        }
+        // the area sends these to everyone at the end of the tick
+        self.pending_removed_voxels.extend(&impacted_voxels_grid_coords);
 
         self.removed_voxels.append(&mut impacted_voxels_grid_coords);
         self.removed_voxels.dedup();
-
-        ctx.send_network_packet(
-            UpdatePropVoxels {
-                prop_id: self.id,
-                area_id: *area_context.id,
-                new_voxels: new_voxels,
-                removed_voxels: self.removed_voxels.clone(),
-            }.into()
-        );
         if impacted_voxels.len() > 0 {
 
 
@@ -751,84 +755,15 @@ impl BaseProp {
         area_id: AreaId
     ) {
 
-        let collider = space.collider_set.get(self.collider_handle).unwrap().clone();
-        let body = space.rigid_body_set.get(self.rigid_body_handle).unwrap().clone();
-        let body_translation = body.translation();
-        let texture = textures.get(&self.sprite_path);
-        let half_extents = collider.shape().as_cuboid().unwrap().half_extents;
-        let x_scale = (half_extents.x * 2.) / texture.width() ;
-        let y_scale = (half_extents.y * 2.) / texture.height();
-        let texture_data = texture.get_texture_data();
-        let total_pixel_count = texture.width() * texture.height();
-
-        for x in (0..texture.width() as u32).step_by(4) {
-            for y in (0..texture.height() as u32).step_by(4) {
-                // create an average of the 4 neighboring pixels
-                // start with bottom left
-                let mut color = texture_data.get_pixel(x, y);
-                let mut pixel_count = 1;
-                // bottom right
-                if x + 1 <= texture.width() as u32 {
-                    let bottom_right_color = texture_data.get_pixel(x + 1, y);
-
-                    color.r += bottom_right_color.r;
-                    color.g += bottom_right_color.g;
-                    color.b += bottom_right_color.b;
-
-                    pixel_count += 1;
-                }
-
-                // top left
-                if y + 1 <= texture.height() as u32 {
-                    let top_left_color = texture_data.get_pixel(x, y + 1);
-
-                    color.r += top_left_color.r;
-                    color.g += top_left_color.g;
-                    color.b += top_left_color.b;
-
-                    pixel_count += 1;
-                }
-
-                // top right
-                if x + 1 <= texture.width() as u32 && y + 1 <= texture.height() as u32 {
-                    let top_right_color = texture_data.get_pixel(x + 1, y + 1);
-
-                    color.r += top_right_color.r;
-                    color.g += top_right_color.g;
-                    color.b += top_right_color.b;
-
-                    pixel_count += 1;
-
-                }
-
-                color.r /= pixel_count as f32;
-                color.g /= pixel_count as f32;
-                color.b /= pixel_count as f32;
-
-                let translation = glamx::Vec2::new(
-                ((body_translation.x + (x as f32 * x_scale)) - half_extents.x) + 2.,
-                ((body_translation.y - (y as f32 * y_scale)) + half_extents.y) - 2.
-                );
-
-                let position = Pose2::new(
-                    translation,
-                    body.rotation().angle()
-                );
-
+        let collider = space.collider_set.get(self.collider_handle).unwrap();
+        let body = space.rigid_body_set.get(self.rigid_body_handle).unwrap();
 
-                dissolved_pixels.push(
-                    DissolvedPixel::new(
-                        position,
-                        space,
-                        color,
-                        x_scale,
-                        Some(collider.mass() / total_pixel_count),
-                        Some(*body.vels()),
-                    )
-                );
+        let half_extents = collider.shape().as_cuboid().unwrap().half_extents;
+        let mass = collider.mass();
+        let pose = *body.position();
+        let velocity = *body.vels();
 
-            }
-        }
+        dissolve_texture(textures.get(&self.sprite_path), half_extents, mass, pose, velocity, space, dissolved_pixels);
 
         if let Some(ctx) = ctx {
             ctx.network_io.send_network_packet(
@@ -892,6 +827,7 @@ impl BaseProp {
                     area_id,
                     pos: *current_position,
                     prop_id: self.id,
+                    timestamp: snapshot_timestamp(),
                 }
             );
 
@@ -1050,11 +986,13 @@ impl BaseProp {
             shader_material: other.shader_material.clone(),
             mask: None,
             removed_voxels,
+            pending_removed_voxels: Vec::new(),
             spawned: web_time::Instant::now(),
             lifespan: lifespan,
             sync_physics,
             last_sent_position_update: web_time::Instant::now(),
             destruction_material_handle: None,
+            interpolation: InterpolationBuffer::new(),
 
 
         }
@@ -1080,20 +1018,6 @@ impl BaseProp {
         let mass = collider.mass();
 
 
-        let voxels = if self.voxels_modified {
-            let coords: Vec<IVec2> = collider
-                .shape()
-                .as_voxels().unwrap()
-                .voxels()
-                .filter(|v| !v.state.is_empty())
-                .map(|v| v.grid_coords)
-                .collect();
-
-            coords.into()
-        } else {
-            None
-        };
-
         let save = BasePropSave {
             pos,
             mass,
@@ -1103,10 +1027,12 @@ impl BaseProp {
             material: self.material,
             name: self.name.clone(),
             layer: self.layer,
-            voxels,
+            // the voxels get regenerated from the sprite and then the damage gets removed from them
+            voxels: None,
             scale: self.scale,
             rigid_body_type: self.rigid_body_type,
-            removed_voxels: self.removed_voxels.clone(),
+            removed_voxels: Vec::new(),
+            damage: VoxelDiff::from_cells(&self.removed_voxels),
             lifespan: self.lifespan,
             sync_physics: self.sync_physics,
 
@@ -1371,7 +1297,9 @@ pub struct NewProp {
 pub struct PropPositionUpdate {
     pub area_id: AreaId,
     pub pos: Pose2,
-    pub prop_id: PropId
+    pub prop_id: PropId,
+    /// When the sender took this, see interpolation::snapshot_timestamp
+    pub timestamp: u64
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -1386,19 +1314,4 @@ pub struct DissolveProp {
     pub area_id: AreaId
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct UpdatePropVoxels {
-    pub prop_id: PropId,
-    pub area_id: AreaId,
-    pub new_voxels: Vec<glamx::IVec2>,
-    pub removed_voxels: Vec<glamx::IVec2> // there might be a better way to handle this
-}
 
-/// Set individual prop voxel
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct SetPropVoxel {
-    pub prop_id: PropId,
-    pub area_id: AreaId,
-    pub voxel_index: glamx::IVec2,
-    pub filled: bool
-}
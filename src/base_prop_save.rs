@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{collections::HashSet, path::PathBuf};
 
 use glamx::{IVec2, Pose2};
 use image::GenericImageView;
@@ -6,7 +6,7 @@ use macroquad::{color::BLACK, ui::Drag::No};
 use rapier2d::{na::base, prelude::{ColliderBuilder, RigidBodyBuilder, RigidBodyType, RigidBodyVelocity}};
 use serde::{Deserialize, Serialize};
 
-use crate::{Owner, TextureLoader, base_prop::{BaseProp, Material, PropId}, prop::Prop, prop_save::PropSave, space::Space};
+use crate::{Owner, TextureLoader, base_prop::{BaseProp, Material, PropId}, interpolation::InterpolationBuffer, prop::Prop, prop_save::PropSave, space::Space, voxel_diff::VoxelDiff};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct BasePropSave {
@@ -29,9 +29,12 @@ pub struct BasePropSave {
     pub voxels: Option<Vec<glamx::IVec2>>,
     #[serde(default="default_body_type")]
     pub rigid_body_type: RigidBodyType,
-    #[serde(default)]
+    /// Old saves list removed voxels like this, new ones use damage
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub removed_voxels: Vec<glamx::IVec2>,
     #[serde(default)]
+    pub damage: VoxelDiff,
+    #[serde(default)]
     pub lifespan: Option<web_time::Duration>,
     #[serde(default = "default_sync_physics")]
     pub sync_physics: bool,
@@ -230,6 +233,25 @@ impl BasePropSave {
             },
         };
 
+        let damage = self.damage.cells(voxels.len()).unwrap_or_else(|error| {
+            log::warn!("Ignoring damage on prop: {}", error);
+
+            Vec::new()
+        });
+
+        let removed_voxels: Vec<IVec2> = self.removed_voxels
+            .iter()
+            .cloned()
+            .chain(damage)
+            .collect();
+
+        let removed_voxels_set: HashSet<IVec2> = removed_voxels.iter().cloned().collect();
+
+        let voxels: Vec<IVec2> = voxels
+            .into_iter()
+            .filter(|voxel| !removed_voxels_set.contains(voxel))
+            .collect();
+
         let collider_handle = space.collider_set.insert_with_parent(
             ColliderBuilder::voxels(
                 glamx::Vec2::new(8., 8.,),
@@ -267,11 +289,12 @@ impl BasePropSave {
             name: self.name.clone(),
             context_menu_data: None,
             layer: self.layer,
-            voxels_modified: self.voxels.is_some(),
+            voxels_modified: self.voxels.is_some() || !removed_voxels.is_empty(),
             scale: self.scale,
             mask: None,
             shader_material: None,
-            removed_voxels: self.removed_voxels.clone(),
+            removed_voxels,
+            pending_removed_voxels: Vec::new(),
             last_velocity_update: web_time::Instant::now(),
             spawned: web_time::Instant::now(), // this could be an issue
             lifespan: self.lifespan,
@@ -279,6 +302,7 @@ impl BasePropSave {
             last_ownership_change: web_time::Instant::now(), // this could also be an issue
             last_sent_position_update: web_time::Instant::now(),
             destruction_material_handle: None,
+            interpolation: InterpolationBuffer::new(),
 
 
         };
@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use rapier2d::prelude::{ColliderBuilder, ColliderHandle, RigidBodyBuilder, RigidBodyHandle};
 
-use crate::{Owner, TickContext, draw_texture_onto_physics_body, space::Space, texture_loader::ClientTextureLoader};
+use crate::{Owner, TickContext, dissolved_pixel::{DissolvedPixel, dissolve_texture}, draw_texture_onto_physics_body, space::Space, texture_loader::ClientTextureLoader};
 
 #[derive(Debug, Clone)]
 pub struct BodyPart {
@@ -11,6 +11,8 @@ pub struct BodyPart {
     sprite_path: PathBuf,
     scale: u16, 
     owner: Owner,
+    /// Bullets that hit this part do this much of their damage. Headshots hurt more than body shots
+    pub damage_multiplier: f32,
 }
 
 impl BodyPart {
@@ -54,10 +56,32 @@ impl BodyPart {
             sprite_path: sprite_path,
             scale,
             owner,
+            damage_multiplier: 1.,
         }
 
     }
 
+    /// Turn the part into pixels and take its body out of the simulation. The handles stay valid so nothing else has to know
+    pub fn dissolve(&self, textures: &ClientTextureLoader, space: &mut Space, dissolved_pixels: &mut Vec<DissolvedPixel>) {
+
+        let collider = space.collider_set.get(self.collider_handle).unwrap();
+        let body = space.rigid_body_set.get(self.body_handle).unwrap();
+
+        let half_extents = collider.shape().as_cuboid().unwrap().half_extents;
+        let mass = collider.mass();
+        let pose = *body.position();
+        let velocity = *body.vels();
+
+        dissolve_texture(textures.get(&self.sprite_path), half_extents, mass, pose, velocity, space, dissolved_pixels);
+
+        self.disable(space);
+    }
+
+    /// Stops the body moving or colliding with anything without removing it
+    pub fn disable(&self, space: &mut Space) {
+        space.rigid_body_set.get_mut(self.body_handle).unwrap().set_enabled(false);
+    }
+
     pub fn draw(
         &self, 
         ctx: &mut TickContext,
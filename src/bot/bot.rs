@@ -0,0 +1,359 @@
+use std::{collections::HashMap, net::TcpStream};
+
+use glamx::{Pose2, Vec2};
+use interceptors_lib::{ClientHello, ClientId, Owner, area::AreaId, bullet_trail::{BulletTrail, SpawnBulletTrail}, codec::{self, PacketCodec}, interest::{ClientView, ClientViewUpdate}, player::{Facing, NewPlayer, Player, PlayerCursorUpdate, PlayerFacingUpdate, PlayerId}, player_movement::{PlayerInput, PlayerInputUpdate}, space::Space, updates::{LatencyUpdate, NetworkPacket, Ping}};
+use macroquad::rand::RandomRange;
+use tungstenite::{Message, WebSocket, stream::MaybeTlsStream};
+
+use crate::stats::BotStats;
+
+/// Same as the client
+const FLUSH_INTERVAL: web_time::Duration = web_time::Duration::from_millis(33);
+const PING_INTERVAL: web_time::Duration = web_time::Duration::from_secs(1);
+const VIEW_UPDATE_INTERVAL: web_time::Duration = web_time::Duration::from_millis(250);
+const CURSOR_UPDATE_INTERVAL: web_time::Duration = web_time::Duration::from_millis(50);
+
+/// Roughly what a 1280x720 window sees
+const VIEW_HALF_EXTENTS: Vec2 = Vec2::new(640., 360.);
+
+struct BotPlayer {
+    id: PlayerId,
+    pos: Vec2,
+    facing: Facing
+}
+
+/// A fake client that connects over a real websocket and sends what a player would
+pub struct Bot {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    client_id: ClientId,
+    codec: PacketCodec,
+    packet_queue: Vec<NetworkPacket>,
+    area_id: Option<AreaId>,
+    player: Option<BotPlayer>,
+    input: PlayerInput,
+    next_sequence: u32,
+    next_decision: web_time::Instant,
+    next_shot: web_time::Instant,
+    pings: HashMap<u64, web_time::Instant>,
+    last_ping: web_time::Instant,
+    last_view_update: web_time::Instant,
+    last_cursor_update: web_time::Instant,
+    last_flush: web_time::Instant,
+    pub stats: BotStats,
+    pub disconnected: bool
+}
+
+impl Bot {
+    pub fn connect(url: &str, name: String, codec: PacketCodec) -> Result<Self, String> {
+
+        let (mut socket, _response) = tungstenite::connect(url)
+            .map_err(|error| format!("failed to connect to {}: {}", url, error))?;
+
+        let client_id = ClientId::new();
+
+        // the server identifies us by the first message and replies in whatever codec it was in
+        socket.send(Message::Binary(codec::encode_value(codec, &ClientHello { client_id, name }).into()))
+            .map_err(|error| format!("failed to send hello: {}", error))?;
+
+        // we tick a lot of bots on one thread so none of them can block
+        match socket.get_mut() {
+            MaybeTlsStream::Plain(stream) => stream.set_nonblocking(true)
+                .map_err(|error| format!("failed to set socket as non blocking: {}", error))?,
+            _ => return Err("only ws:// urls are supported".to_string()),
+        }
+
+        let now = web_time::Instant::now();
+
+        Ok(Self {
+            socket,
+            client_id,
+            codec,
+            packet_queue: Vec::new(),
+            area_id: None,
+            player: None,
+            input: PlayerInput::default(),
+            next_sequence: 1,
+            next_decision: now,
+            next_shot: now,
+            pings: HashMap::new(),
+            last_ping: now,
+            last_view_update: now,
+            last_cursor_update: now,
+            last_flush: now,
+            stats: BotStats::default(),
+            disconnected: false,
+        })
+    }
+
+    pub fn receive_packets(&mut self) {
+
+        loop {
+            let bytes = match self.socket.read() {
+                Ok(Message::Binary(bytes)) => bytes,
+                Ok(Message::Close(_)) => {
+                    self.disconnect("server closed the connection".to_string());
+                    return;
+                },
+                Ok(_) => continue,
+                Err(tungstenite::Error::Io(error)) if error.kind() == std::io::ErrorKind::WouldBlock => return,
+                Err(error) => {
+                    self.disconnect(error.to_string());
+                    return;
+                },
+            };
+
+            self.stats.bytes_received += bytes.len() as u64;
+
+            let packets = match codec::decode_batch(&bytes) {
+                Ok((_codec, packets)) => packets,
+                Err(error) => {
+                    log::warn!("Bot {:?} failed to decode packets: {}", self.client_id, error);
+                    continue;
+                },
+            };
+
+            for packet in packets {
+                self.stats.packets_received += 1;
+
+                self.handle_packet(packet);
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, packet: NetworkPacket) {
+        match packet {
+            NetworkPacket::LoadArea(load_area) => {
+
+                self.area_id = Some(load_area.id);
+
+                // going through a portal keeps the same player, the server moves it for us
+                if self.player.is_none() {
+                    self.spawn_player(load_area.id, load_area.area.spawn_point());
+                }
+            },
+            NetworkPacket::PlayerStateUpdate(update) => {
+                if let Some(player) = &mut self.player {
+                    if player.id == update.player_id {
+                        player.pos = update.pos.translation;
+                    }
+                }
+            },
+            NetworkPacket::Ping(ping) => {
+                if let Some(sent) = self.pings.remove(&ping.id) {
+                    self.stats.round_trips.push(sent.elapsed());
+
+                    self.send(LatencyUpdate { latency: sent.elapsed() }.into());
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn spawn_player(&mut self, area_id: AreaId, spawn_point: Vec2) {
+
+        // the save needs real bodies in it so build the player in a throwaway space
+        let mut space = Space::new();
+
+        let player = Player::new(Pose2::new(spawn_point, 0.), &mut space, Owner::ClientId(self.client_id));
+
+        self.send(
+            NewPlayer {
+                player: player.save(&space),
+                area_id,
+            }.into()
+        );
+
+        self.player = Some(
+            BotPlayer {
+                id: player.id,
+                pos: spawn_point,
+                facing: Facing::Right,
+            }
+        );
+    }
+
+    /// One simulation tick worth of pretending to play
+    pub fn tick(&mut self) {
+
+        if self.last_ping.elapsed() >= PING_INTERVAL {
+            let ping = Ping::new();
+
+            self.pings.insert(ping.id, web_time::Instant::now());
+
+            self.send(ping.into());
+
+            self.last_ping = web_time::Instant::now();
+        }
+
+        let (Some(area_id), Some(player)) = (self.area_id, &self.player) else {
+            return;
+        };
+
+        let player_id = player.id;
+        let pos = player.pos;
+
+        let now = web_time::Instant::now();
+
+        if now >= self.next_decision {
+            self.decide_input(area_id, player_id);
+
+            self.next_decision = now + web_time::Duration::from_secs_f32(RandomRange::gen_range(0.5, 2.));
+        }
+
+        if !self.input.is_idle() {
+            self.input.sequence = self.next_sequence;
+            self.next_sequence += 1;
+
+            self.send(
+                PlayerInputUpdate {
+                    area_id,
+                    player_id,
+                    input: self.input,
+                }.into()
+            );
+
+            // holding jump just makes you jump once
+            self.input.jump = false;
+            self.input.dash = false;
+        }
+
+        let aim = self.aim();
+
+        if self.last_cursor_update.elapsed() >= CURSOR_UPDATE_INTERVAL {
+            self.send(
+                PlayerCursorUpdate {
+                    area_id,
+                    id: player_id,
+                    pos: pos + aim * 200.,
+                }.into()
+            );
+
+            self.last_cursor_update = now;
+        }
+
+        if now >= self.next_shot {
+            let bullet_trail = BulletTrail::new(
+                pos + Vec2::new(0., 10.),
+                pos + aim * 10000.,
+                None,
+                Owner::ClientId(self.client_id)
+            );
+
+            self.send(
+                SpawnBulletTrail {
+                    area_id,
+                    save: bullet_trail.save(),
+                }.into()
+            );
+
+            // short bursts with pauses in between like a person holding the mouse down
+            let delay = match RandomRange::gen_range(0, 5) {
+                0 => RandomRange::gen_range(0.5, 2.),
+                _ => 0.1,
+            };
+
+            self.next_shot = now + web_time::Duration::from_secs_f32(delay);
+        }
+
+        if self.last_view_update.elapsed() >= VIEW_UPDATE_INTERVAL {
+            self.send(
+                ClientViewUpdate {
+                    view: ClientView {
+                        center: pos,
+                        half_extents: VIEW_HALF_EXTENTS,
+                    },
+                }.into()
+            );
+
+            self.last_view_update = now;
+        }
+    }
+
+    fn decide_input(&mut self, area_id: AreaId, player_id: PlayerId) {
+
+        let direction = RandomRange::gen_range(0, 3);
+
+        self.input.left = direction == 0;
+        self.input.right = direction == 1;
+        self.input.jump = RandomRange::gen_range(0, 3) == 0;
+        self.input.dash = RandomRange::gen_range(0, 10) == 0;
+
+        let facing = match direction {
+            0 => Facing::Left,
+            1 => Facing::Right,
+            _ => return,
+        };
+
+        let Some(player) = &mut self.player else {
+            return;
+        };
+
+        if player.facing == facing {
+            return;
+        }
+
+        player.facing = facing;
+
+        self.send(
+            PlayerFacingUpdate {
+                area_id,
+                id: player_id,
+                facing,
+            }.into()
+        );
+    }
+
+    /// Which way we are pointing the gun, roughly forward with some wobble
+    fn aim(&self) -> Vec2 {
+        let forward = match self.player.as_ref().map(|player| player.facing) {
+            Some(Facing::Left) => -1.,
+            _ => 1.,
+        };
+
+        Vec2::new(forward, RandomRange::gen_range(-0.3, 0.3)).normalize()
+    }
+
+    fn send(&mut self, packet: NetworkPacket) {
+        self.packet_queue.push(packet);
+    }
+
+    pub fn flush(&mut self) {
+
+        if self.disconnected || self.last_flush.elapsed() < FLUSH_INTERVAL {
+            return;
+        }
+
+        self.last_flush = web_time::Instant::now();
+
+        self.stats.packets_sent += self.packet_queue.len() as u64;
+
+        for frame in codec::encode_batch(self.codec, &self.packet_queue) {
+
+            self.stats.bytes_sent += frame.len() as u64;
+
+            // would block just means it got buffered, it goes out on the next flush
+            if let Err(error) = self.socket.write(Message::Binary(frame.into())) {
+                if !matches!(&error, tungstenite::Error::Io(io_error) if io_error.kind() == std::io::ErrorKind::WouldBlock) {
+                    self.disconnect(error.to_string());
+                    return;
+                }
+            }
+        }
+
+        self.packet_queue.clear();
+
+        match self.socket.flush() {
+            Ok(()) => {},
+            Err(tungstenite::Error::Io(error)) if error.kind() == std::io::ErrorKind::WouldBlock => {},
+            Err(error) => self.disconnect(error.to_string()),
+        }
+    }
+
+    fn disconnect(&mut self, reason: String) {
+        if !self.disconnected {
+            log::warn!("Bot {:?} disconnected: {}", self.client_id, reason);
+        }
+
+        self.disconnected = true;
+    }
+}
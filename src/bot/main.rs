@@ -0,0 +1,117 @@
+use clap::Parser;
+use interceptors_lib::{codec::PacketCodec, fixed_timestep::{DEFAULT_MAX_CATCH_UP_STEPS, DEFAULT_TICK_RATE, FixedTimestep}, uuid_u64};
+
+use crate::{bot::Bot, stats::BotStats};
+
+mod bot;
+mod stats;
+
+/// Connects a bunch of fake players to a server to see how it holds up. Doesn't need a window
+#[derive(Parser)]
+struct Args {
+    /// Server url to connect to
+    #[arg(long, default_value = "ws://127.0.0.1:5560")]
+    server: String,
+    /// How many bots to connect
+    #[arg(long, default_value_t = 10)]
+    bots: usize,
+    /// Milliseconds between each bot connecting so they don't all hit the server at once
+    #[arg(long, default_value_t = 200)]
+    connect_interval: u64,
+    /// Seconds between stats reports
+    #[arg(long, default_value_t = 5.)]
+    report_interval: f32,
+    /// Stop after this many seconds. Runs forever by default
+    #[arg(long)]
+    duration: Option<f32>,
+    /// Simulation ticks per second. Should match the server
+    #[arg(long, default_value_t = DEFAULT_TICK_RATE)]
+    tick_rate: u32,
+    /// Send packets as json instead of bitcode
+    #[arg(long)]
+    json: bool,
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    let args = Args::parse();
+
+    // every bot shares the one generator so they would all do the same thing without this
+    macroquad::rand::srand(uuid_u64());
+
+    let codec = match args.json {
+        true => PacketCodec::Json,
+        false => PacketCodec::default(),
+    };
+
+    let connect_interval = web_time::Duration::from_millis(args.connect_interval);
+    let report_interval = web_time::Duration::from_secs_f32(args.report_interval);
+
+    let mut timestep = FixedTimestep::new(args.tick_rate, DEFAULT_MAX_CATCH_UP_STEPS);
+
+    let mut bots: Vec<Bot> = Vec::new();
+    let mut connect_attempts = 0;
+    let mut last_connect = web_time::Instant::now() - connect_interval;
+
+    let start = web_time::Instant::now();
+    let mut last_report = web_time::Instant::now();
+    let mut report_stats = BotStats::default();
+    let mut total_stats = BotStats::default();
+
+    log::info!("Connecting {} bots to {}", args.bots, args.server);
+
+    loop {
+
+        if connect_attempts < args.bots && last_connect.elapsed() >= connect_interval {
+            connect_attempts += 1;
+            last_connect = web_time::Instant::now();
+
+            match Bot::connect(&args.server, format!("Bot {}", connect_attempts), codec) {
+                Ok(bot) => bots.push(bot),
+                Err(error) => log::error!("Bot {} failed to connect: {}", connect_attempts, error),
+            }
+        }
+
+        for bot in &mut bots {
+            bot.receive_packets();
+        }
+
+        for _ in 0..timestep.advance() {
+            for bot in &mut bots {
+                bot.tick();
+            }
+        }
+
+        for bot in &mut bots {
+            bot.flush();
+
+            report_stats.add(std::mem::take(&mut bot.stats));
+        }
+
+        bots.retain(|bot| !bot.disconnected);
+
+        if last_report.elapsed() >= report_interval {
+            log::info!("{}", report_stats.report(last_report.elapsed(), bots.len(), args.bots));
+
+            total_stats.add(std::mem::take(&mut report_stats));
+
+            last_report = web_time::Instant::now();
+        }
+
+        if args.duration.is_some_and(|duration| start.elapsed().as_secs_f32() >= duration) {
+            break;
+        }
+
+        if connect_attempts == args.bots && bots.is_empty() {
+            log::error!("All bots disconnected");
+            break;
+        }
+
+        std::thread::sleep(web_time::Duration::from_millis(1));
+    }
+
+    total_stats.add(report_stats);
+
+    log::info!("Total: {}", total_stats.report(start.elapsed(), bots.len(), args.bots));
+}
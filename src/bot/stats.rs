@@ -0,0 +1,56 @@
+/// Traffic counters for one bot since the last report
+#[derive(Default)]
+pub struct BotStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Round trip times of the pings that came back
+    pub round_trips: Vec<web_time::Duration>
+}
+
+impl BotStats {
+    pub fn add(&mut self, other: BotStats) {
+        self.packets_sent += other.packets_sent;
+        self.packets_received += other.packets_received;
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.round_trips.extend(other.round_trips);
+    }
+
+    /// One line summary of everything that happened over `elapsed`
+    pub fn report(&mut self, elapsed: web_time::Duration, connected_bots: usize, total_bots: usize) -> String {
+
+        let seconds = elapsed.as_secs_f64().max(0.001);
+
+        let round_trips = match self.round_trips.is_empty() {
+            true => "no pings".to_string(),
+            false => {
+                self.round_trips.sort();
+
+                let average = self.round_trips.iter().sum::<web_time::Duration>() / self.round_trips.len() as u32;
+
+                let p95 = self.round_trips[(self.round_trips.len() * 95 / 100).min(self.round_trips.len() - 1)];
+
+                format!(
+                    "rtt min {:?} avg {:?} p95 {:?} max {:?}",
+                    self.round_trips[0],
+                    average,
+                    p95,
+                    self.round_trips[self.round_trips.len() - 1]
+                )
+            },
+        };
+
+        format!(
+            "{}/{} bots | sent {:.0} packets/s {:.1} KB/s | received {:.0} packets/s {:.1} KB/s | {}",
+            connected_bots,
+            total_bots,
+            self.packets_sent as f64 / seconds,
+            self.bytes_sent as f64 / seconds / 1000.,
+            self.packets_received as f64 / seconds,
+            self.bytes_received as f64 / seconds / 1000.,
+            round_trips
+        )
+    }
+}
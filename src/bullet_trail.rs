@@ -93,6 +93,12 @@ pub struct BulletTrailSave {
     id: BulletTrailId
 }
 
+impl BulletTrailSave {
+    pub fn start(&self) -> Vec2 {
+        self.start
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy)]
 pub struct SpawnBulletTrail {
     pub area_id: AreaId,
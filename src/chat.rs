@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ClientId, area::AreaId};
+
+pub const MAX_CHAT_MESSAGE_LENGTH: usize = 200;
+
+pub const MAX_NAME_LENGTH: usize = 20;
+
+/// How many messages a client can send within CHAT_RATE_LIMIT_WINDOW
+const CHAT_RATE_LIMIT: usize = 5;
+
+const CHAT_RATE_LIMIT_WINDOW: web_time::Duration = web_time::Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ChatSender {
+    Player {
+        client_id: ClientId,
+        name: String
+    },
+    /// Joins, leaves, deaths, that kind of thing
+    System
+}
+
+/// Clients send this with whatever sender they like, the server fills in the real one before passing it on
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChatMessage {
+    /// Only players in this area get it. None goes to everyone
+    pub area_id: Option<AreaId>,
+    pub sender: ChatSender,
+    pub text: String
+}
+
+impl ChatMessage {
+    pub fn system(area_id: Option<AreaId>, text: impl Into<String>) -> Self {
+        Self {
+            area_id,
+            sender: ChatSender::System,
+            text: text.into(),
+        }
+    }
+}
+
+/// Strip out anything that would mess up the chat log and cap the length
+pub fn sanitize_chat_text(text: &str, max_length: usize) -> String {
+    text.chars()
+        .filter(|character| !character.is_control())
+        .take(max_length)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Names go through the same cleanup as chat. People who dont pick one get a boring one
+pub fn sanitize_name(name: &str) -> String {
+    let name = sanitize_chat_text(name, MAX_NAME_LENGTH);
+
+    match name.is_empty() {
+        true => "Player".to_string(),
+        false => name,
+    }
+}
+
+/// Server side, one per client
+pub struct ChatRateLimiter {
+    recent_messages: VecDeque<web_time::Instant>
+}
+
+impl ChatRateLimiter {
+    pub fn new() -> Self {
+        Self {
+            recent_messages: VecDeque::new(),
+        }
+    }
+
+    /// Returns false if they've been sending too much and this message should be dropped
+    pub fn allow(&mut self) -> bool {
+        while self.recent_messages.front().is_some_and(|sent| sent.elapsed() > CHAT_RATE_LIMIT_WINDOW) {
+            self.recent_messages.pop_front();
+        }
+
+        if self.recent_messages.len() >= CHAT_RATE_LIMIT {
+            return false;
+        }
+
+        self.recent_messages.push_back(web_time::Instant::now());
+
+        true
+    }
+}
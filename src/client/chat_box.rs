@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+
+use interceptors_lib::chat::{ChatMessage, ChatSender, MAX_CHAT_MESSAGE_LENGTH, sanitize_chat_text};
+use macroquad::{color::{Color, WHITE, YELLOW}, input::{KeyCode, get_char_pressed, is_key_pressed}, shapes::draw_rectangle, text::draw_text, window::screen_height};
+
+/// How many messages we remember
+const MAX_LOG_LENGTH: usize = 50;
+
+/// How many of the newest messages are shown at once
+const VISIBLE_LINES: usize = 8;
+
+/// How long a message stays on screen when the chat isn't open
+const MESSAGE_LIFETIME: web_time::Duration = web_time::Duration::from_secs(10);
+
+/// How long it takes to fade out at the end of its lifetime
+const FADE_DURATION: web_time::Duration = web_time::Duration::from_secs(2);
+
+const FONT_SIZE: f32 = 22.;
+const LINE_HEIGHT: f32 = 24.;
+
+struct ChatLine {
+    text: String,
+    color: Color,
+    received: web_time::Instant
+}
+
+/// The chat log in the bottom left and the text box for typing into it
+pub struct ChatBox {
+    log: VecDeque<ChatLine>,
+    input: String,
+    pub open: bool
+}
+
+impl ChatBox {
+    pub fn new() -> Self {
+        Self {
+            log: VecDeque::new(),
+            input: String::new(),
+            open: false,
+        }
+    }
+
+    pub fn push(&mut self, message: ChatMessage) {
+        let (text, color) = match message.sender {
+            ChatSender::Player { name, .. } => (format!("{}: {}", name, message.text), WHITE),
+            ChatSender::System => (message.text, YELLOW),
+        };
+
+        self.log.push_back(
+            ChatLine {
+                text,
+                color,
+                received: web_time::Instant::now(),
+            }
+        );
+
+        while self.log.len() > MAX_LOG_LENGTH {
+            self.log.pop_front();
+        }
+    }
+
+    /// Enter opens the chat, Enter again sends, Escape gives up. Returns the text to send once they hit Enter
+    pub fn update(&mut self) -> Option<String> {
+
+        if !self.open {
+            // otherwise everything typed while playing shows up as soon as the chat opens
+            while get_char_pressed().is_some() {}
+
+            if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) {
+                self.open = true;
+            }
+
+            return None;
+        }
+
+        if is_key_pressed(KeyCode::Escape) {
+            self.open = false;
+            self.input.clear();
+
+            return None;
+        }
+
+        if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) {
+            self.open = false;
+
+            let text = sanitize_chat_text(&std::mem::take(&mut self.input), MAX_CHAT_MESSAGE_LENGTH);
+
+            return match text.is_empty() {
+                true => None,
+                false => Some(text),
+            };
+        }
+
+        if is_key_pressed(KeyCode::Backspace) {
+            self.input.pop();
+        }
+
+        while let Some(character) = get_char_pressed() {
+            if !character.is_control() && self.input.chars().count() < MAX_CHAT_MESSAGE_LENGTH {
+                self.input.push(character);
+            }
+        }
+
+        None
+    }
+
+    pub fn draw(&self) {
+
+        let input_y = screen_height() - 20.;
+
+        // newest message sits just above the input box
+        for (index, line) in self.log.iter().rev().take(VISIBLE_LINES).enumerate() {
+
+            let alpha = match self.open {
+                true => 1.,
+                false => {
+                    let remaining = MESSAGE_LIFETIME.saturating_sub(line.received.elapsed());
+
+                    (remaining.as_secs_f32() / FADE_DURATION.as_secs_f32()).min(1.)
+                },
+            };
+
+            if alpha <= 0. {
+                continue;
+            }
+
+            let mut color = line.color;
+            color.a = alpha;
+
+            draw_text(&line.text, 10., input_y - LINE_HEIGHT * (index + 1) as f32, FONT_SIZE, color);
+        }
+
+        if !self.open {
+            return;
+        }
+
+        draw_rectangle(5., input_y - LINE_HEIGHT + 6., 600., LINE_HEIGHT, Color::new(0., 0., 0., 0.6));
+
+        draw_text(&format!("> {}_", self.input), 10., input_y, FONT_SIZE, WHITE);
+    }
+}
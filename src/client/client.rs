@@ -1,12 +1,11 @@
 use std::{collections::HashMap, process::exit};
 
-use glamx::Pose2;
 use image::codecs::webp;
-use interceptors_lib::{Assets, ClearBackgroundParameters, ClientIO, ClientId, ClientTickContext, DrawCommand, DrawCommands, DrawTextParameters, Owner, Prefabs, SetCameraParameters, TickContext, area::Area, base_prop::BaseProp, bullet_trail::BulletTrail, button::Button, dissolved_pixel::DissolvedPixel, dropped_item::DroppedItem, enemy::Enemy, font_loader::FontLoader, get_intersections, material_loader::MaterialLoader, player::{ItemSlot, Player}, screen_shake::ScreenShakeParameters, sound_loader::SoundLoader, texture_loader::ClientTextureLoader, updates::{NetworkPacket, Ping}, world::World};
-use macroquad::{camera::{Camera2D, set_camera, set_default_camera}, color::{BLACK, WHITE}, input::{KeyCode, is_key_released, is_mouse_button_down, is_mouse_button_released, show_mouse}, math::{Rect, Vec2, vec2}, prelude::{Material, ShaderSource, gl_use_default_material, load_material}, text::draw_text, texture::{DrawTextureParams, RenderTarget, draw_texture_ex, render_target}, time::draw_fps, window::{clear_background, next_frame, screen_height, screen_width}};
-use rapier2d::{geometry::VoxelData, math::Vector, prelude::{ColliderBuilder, SharedShape}};
+use interceptors_lib::{Assets, ClearBackgroundParameters, ClientIO, ClientId, ClientTickContext, chat::{ChatMessage, ChatSender}, codec::PacketCodec, ConnectionState, fixed_timestep::FixedTimestep, frame_input::FrameInput, DrawCommand, DrawCommands, DrawTextParameters, Owner, Prefabs, SetCameraParameters, TickContext, area::Area, base_prop::BaseProp, bullet_trail::BulletTrail, button::Button, dropped_item::DroppedItem, enemy::Enemy, font_loader::FontLoader, get_intersections, interest::{ClientView, ClientViewUpdate}, interpolation::InterpolationSettings, lobby::{LobbyState, PlayerReadyUpdate}, macroquad_to_rapier, material_loader::MaterialLoader, network_conditions::NetworkConditions, player::{ItemSlot, Player}, rapier_to_macroquad, recording::Recording, screen_shake::ScreenShakeParameters, sound_loader::SoundLoader, texture_loader::ClientTextureLoader, updates::{LatencyUpdate, NetworkPacket, Ping}, wave_director::WaveStatus, world::World};
+use macroquad::{camera::{Camera2D, set_camera, set_default_camera}, color::{BLACK, Color, GRAY, GREEN, WHITE}, shapes::draw_rectangle, input::{KeyCode, is_key_released, is_mouse_button_down, is_mouse_button_released, show_mouse}, math::{Rect, Vec2, vec2}, prelude::{Material, ShaderSource, gl_use_default_material, load_material}, text::{draw_text, measure_text}, texture::{DrawTextureParams, RenderTarget, draw_texture_ex, render_target}, time::draw_fps, window::{clear_background, next_frame, screen_height, screen_width}};
+use rapier2d::{math::Vector, prelude::ColliderBuilder};
 
-use crate::{shaders::{CRT_FRAGMENT_SHADER, CRT_VERTEX_SHADER}};
+use crate::{chat_box::ChatBox, replay::Replay, shaders::{CRT_FRAGMENT_SHADER, CRT_VERTEX_SHADER}};
 
 
 pub struct Client {
@@ -34,74 +33,65 @@ pub struct Client {
     test_button: Button,
     material_loader: MaterialLoader,
     draw_commands: DrawCommands,
-    debug_strings: Vec<String>
+    debug_strings: Vec<String>,
+    interpolation: InterpolationSettings,
+    last_view_update: web_time::Instant,
+    lobby: LobbyState,
+    wave_status: WaveStatus,
+    timestep: FixedTimestep,
+    /// Some if we are watching a recording instead of playing
+    replay: Option<Replay>,
+    network_condition_preset: usize,
+    chat_box: ChatBox,
+    show_nav_graph: bool,
+    /// Input waiting for the next step to run
+    frame_input: FrameInput
 }
 
 impl Client {
-    pub async fn connect(assets: Assets) -> Self {
-
-
-        show_mouse(true);
-
-        let url = "ws://127.0.0.1:5560";
-
-        #[cfg(target_arch = "wasm32")]
-        let url = "wss://interceptors.voxany.net/ws/";
+    pub async fn connect(assets: Assets, url: String, name: String, interpolation: InterpolationSettings, timestep: FixedTimestep) -> Self {
 
         #[cfg(feature = "discord")]
         let url = format!("wss://{}.discordsays.com/ws/", client_id);
 
-        let (mut server_send, server_receive) = match ewebsock::connect(url, ewebsock::Options::default()) {
-            Ok(result) => result,
+        log::info!("Connecting to {}", url);
+
+        let client_id = ClientId::new();
+
+        // the server replies using whichever codec we use for the handshake
+        let server = match ClientIO::connect(url, client_id, name, PacketCodec::default()) {
+            Ok(server) => server,
             Err(error) => {
-                panic!("failed to connect to server: {}", error)
+                log::error!("Failed to connect to server: {}", error);
+                exit(1);
             },
         };
 
-        // wait for Opened event from server
-        loop {
-            match server_receive.try_recv() {
-                Some(event) => {
-                    match event {
-                        ewebsock::WsEvent::Opened => {
-                            log::info!("Received opened message from server");
-                            break;
-                        },
-                        ewebsock::WsEvent::Message(message) => {
-                            match message {
-                                _ => {
-                                    log::error!("Received a message from the server");
-                                    exit(1);
-                                }
-                            }
-                        },
-                        ewebsock::WsEvent::Error(error) => {
-                            log::error!("Received error when trying to connect to server: {}", error);
-                            exit(1);
-                        },
-                        ewebsock::WsEvent::Closed => {
-                            log::error!("Server closed when trying to connect");
-                            exit(1);
-                        }
-
-                    }
-                },
-                None => {
+        Self::new(assets, server, client_id, interpolation, timestep, None)
+    }
 
-                    macroquad::window::next_frame().await; // let js runtime main thread continue execution while we wait
+    /// Watch a recording made with the server's --record instead of connecting anywhere
+    pub async fn replay(assets: Assets, recording: Recording, interpolation: InterpolationSettings, mut timestep: FixedTimestep) -> Self {
 
-                    continue;
-                },
-            }
-        };
+        if recording.header.areas.is_empty() {
+            log::error!("Recording doesn't have any areas in it");
+            exit(1);
+        }
 
         let client_id = ClientId::new();
 
-        server_send.send(
-            ewebsock::WsMessage::Binary(
-                serde_json::to_string(&client_id).unwrap().as_bytes().to_vec()
-            )
-        );
+        timestep.set_tick_rate(recording.header.tick_rate);
+
+        let mut client = Self::new(assets, ClientIO::offline(client_id), client_id, interpolation, timestep, Some(Replay::new(recording)));
+
+        client.load_replay_area();
+
+        client
+    }
+
+    fn new(assets: Assets, server: ClientIO, client_id: ClientId, interpolation: InterpolationSettings, timestep: FixedTimestep, replay: Option<Replay>) -> Self {
+
+        show_mouse(true);
 
         let world_render_target = render_target(1280, 720);
 
@@ -115,12 +105,6 @@ impl Client {
             Default::default(),
         ).unwrap();
 
-        let server = ClientIO {
-            send: server_send,
-            receive: server_receive,
-            packet_queue: Vec::new()
-        };
-
         // create world camera
         let camera_rect = Rect {
             x: 0.,
@@ -145,7 +129,6 @@ impl Client {
             h: 100.,
         }, None);
 
-        log::debug!("Connected to server!");
         Self {
             debug_strings: Vec::new(),
             draw_commands: DrawCommands::new(),
@@ -172,12 +155,32 @@ impl Client {
             test_button,
             material_loader: assets.material_loader,
             last_network_flush: web_time::Instant::now(),
+            interpolation,
+            last_view_update: web_time::Instant::now(),
+            lobby: LobbyState::new(),
+            wave_status: WaveStatus::default(),
+            timestep,
+            replay,
+            network_condition_preset: 0,
+            chat_box: ChatBox::new(),
+            show_nav_graph: false,
+            frame_input: FrameInput::default(),
         }
 
 
     }
 }
 
+/// What F8 cycles through
+fn network_condition_presets() -> [NetworkConditions; 4] {
+    [
+        NetworkConditions::default(),
+        NetworkConditions::new(50, 10, 0.),
+        NetworkConditions::new(120, 30, 2.),
+        NetworkConditions::new(250, 80, 5.),
+    ]
+}
+
 impl Client {
 
     pub async fn run(&mut self) {
@@ -191,9 +194,21 @@ impl Client {
             self.tick();
 
             let then = web_time::Instant::now();
-            let packets = self.network_io.receive_packets();
+            let packets = match self.replay.is_some() {
+                true => self.replay_packets(),
+                false => self.network_io.receive_packets(),
+            };
             self.debug_strings.push(format!("Receive packets: {:?}", then.elapsed()));
 
+            self.network_io.update_connection();
+
+            // the server is going to send us a fresh LoadArea so the old world is useless now
+            if self.network_io.take_reconnected() {
+                self.world.areas.clear();
+                self.pings.clear();
+                self.lobby = LobbyState::new();
+            }
+
             let then = web_time::Instant::now();
             self.handle_packets(packets);
             self.debug_strings.push(format!("Handle packets: {:?}", then.elapsed()));
@@ -208,145 +223,105 @@ impl Client {
         }
     }
 
-    pub fn measure_latency(&mut self) {
-
-        let ping = Ping::new();
-        // sample ping every second
-        if self.last_ping_sample.elapsed().as_secs_f32() > 1. {
-            self.network_io.send_network_packet(NetworkPacket::Ping(ping));
+    fn replay_packets(&mut self) -> Vec<NetworkPacket> {
+        let Some(replay) = &mut self.replay else {
+            return Vec::new();
         };
 
-        self.pings.insert(ping.id.clone(), web_time::Instant::now());
+        if replay.controls() {
+            self.load_replay_area();
+        }
 
+        // controls might have swapped the area so this has to come after
+        match &mut self.replay {
+            Some(replay) => replay.due_packets(),
+            None => Vec::new(),
+        }
     }
 
-    pub fn handle_packets(&mut self, packets: Vec<NetworkPacket>) {
+    /// Throw away the world and start again from the area as it was at the start of the recording
+    fn load_replay_area(&mut self) {
+        let Some(replay) = &self.replay else {
+            return;
+        };
 
-        for packet in packets {
-            match packet {
+        let load_area = replay.initial_area();
 
-                NetworkPacket::SetPropVoxel(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+        let previous_area = self.world.areas.first().map(|area| area.id);
 
-                    let prop = area.props.iter_mut().find(|prop| {prop.id() == update.prop_id});
+        self.lobby = LobbyState::new();
 
-                    let Some(prop) = prop else {
-                        continue;
-                    };
+        self.handle_packets(vec![load_area.into()]);
 
-                    let voxels = area.space.collider_set
-                        .get_mut(prop.collider_handle())
-                        .unwrap()
-                        .shape_mut()
-                        .as_voxels_mut()
-                        .unwrap();
+        // seeking shouldn't move the camera, switching areas should
+        if previous_area != Some(self.world.areas[0].id) {
+            let spawn_point = rapier_to_macroquad(self.world.areas[0].spawn_point);
 
+            self.camera_rect.x = spawn_point.x - self.camera_rect.w / 2.;
+            self.camera_rect.y = spawn_point.y - self.camera_rect.h / 2.;
+        }
+    }
 
-                    let new_voxels: Vec<glamx::IVec2> = voxels.voxels()
-                        .filter(|voxel| voxel.grid_coords != update.voxel_index)
-                        .map(|voxel| voxel.grid_coords)
-                        .collect();
+    pub fn measure_latency(&mut self) {
+
+        let ping = Ping::new();
+        // sample ping every second
+        if self.last_ping_sample.elapsed().as_secs_f32() > 1. {
+            self.network_io.send_network_packet(NetworkPacket::Ping(ping));
+        };
 
-                    area.space.collider_set
-                        .get_mut(prop.collider_handle())
-                        .unwrap()
-                        .set_shape(
-                            SharedShape::voxels(glamx::vec2(8., 8.), &new_voxels)
-                        );
+        self.pings.insert(ping.id.clone(), web_time::Instant::now());
 
-                    prop.removed_voxels_mut().push(update.voxel_index);
-                },
+    }
 
-                NetworkPacket::UpdatePropVoxels(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+    pub fn handle_packets(&mut self, packets: Vec<NetworkPacket>) {
 
-                    let prop = area.props.iter_mut().find(|prop| {prop.id() == update.prop_id});
+        for packet in packets {
+            match packet {
 
-                    let Some(prop) = prop else {
+                NetworkPacket::PropVoxelDiffs(update) => {
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
                         continue;
                     };
 
-                    let collider = area.space.collider_set
-                        .get_mut(prop.collider_handle())
-                        .unwrap();
-
-                    let cos = collider.rotation().cos();
-                    let sin = collider.rotation().sin();
-                    
-                    let body = area.space.rigid_body_set.get(prop.rigid_body_handle()).unwrap();
-
-                    let body_rotation = body.rotation().clone();
-                    let body_vels = body.vels().clone();
-
-                    let voxel_collider = collider.shape().as_voxels().unwrap();
-
-                    let current_voxels: Vec<VoxelData> = voxel_collider.voxels()
-                        .filter(|x| !x.state.is_empty())
-                        .collect();
-
-
-                    let removed_voxels_positions: Vec<glamx::Vec2> = current_voxels
-                        .iter()
-                        .filter(|voxel| !update.new_voxels.contains(&voxel.grid_coords))
-                        .map(
-                            |voxel|
-                            {
-                                let rotated_x = voxel.center.x * cos - voxel.center.y * sin;
-                                let rotated_y = voxel.center.x * sin + voxel.center.y * cos;
-
-                                let world_x = rotated_x + collider.translation().x;
-                                let world_y = rotated_y + collider.translation().y;
-
-                                glamx::Vec2::new(world_x, world_y)
-                            }
-                        )
-                        .collect();
-
-                    collider.set_shape(
-                        SharedShape::voxels(glamx::vec2(8., 8.), &update.new_voxels)
-                    );
-                    
-
-
-                    for removed_voxel in removed_voxels_positions {
-
-
-                        area.dissolved_pixels.push(
-                            DissolvedPixel::new(
-                                Pose2::new(removed_voxel, body_rotation.angle()), 
-                                &mut area.space, 
-                                WHITE, 
-                                8., 
-                                Some(10.), 
-                                Some(body_vels)
-                            )
-                        );
+                    for diff in &update.diffs {
+                        if let Err(error) = area.apply_prop_voxel_diff(diff) {
+                            log::warn!("Skipping voxel diff for prop {:?}: {}", diff.prop_id, error);
+                        }
                     }
-
-
-                    *prop.removed_voxels_mut() = update.removed_voxels;
-                    *prop.voxels_modified_mut() = true;
-
                 }
 
 
                 NetworkPacket::MasterUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
 
                     area.master = Some(update.master.clone());
                 },
 
                 NetworkPacket::Ping(ping) => {
-                    self.latency = self.pings.remove(&ping.id).unwrap().elapsed();
+                    // pings sent before a reconnect are forgotten
+                    if let Some(sent) = self.pings.remove(&ping.id) {
+                        self.latency = sent.elapsed();
+
+                        self.network_io.send_network_packet(LatencyUpdate { latency: self.latency }.into());
+                    }
 
                 },
                 NetworkPacket::LoadArea(load_area) => {
 
+                    // we only ever have the area we are in loaded, so going through a portal replaces it
+                    self.world.areas.clear();
+                    self.wave_status = WaveStatus::default();
+
                     self.world.areas.push(Area::from_save(load_area.area, Some(load_area.id), &self.prefab_data, (&self.textures).into()));
                 }
 
                 NetworkPacket::PropVelocityUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id} ).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id} ) else {
+                        continue;
+                    };
 
                     let prop = area.props.iter_mut().find(|prop| {prop.id() == update.id});
 
@@ -365,7 +340,9 @@ impl Client {
                     }
                 },
                 NetworkPacket::PropUpdateOwner(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
 
 
                     let Some(prop) = area.props.iter_mut().find(|prop| {prop.id()} == update.id) else {
@@ -379,60 +356,77 @@ impl Client {
 
                 },
                 NetworkPacket::NewProp(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
 
                     area.props.push(update.prop.load(&mut area.space, (&self.textures).into()));
 
 
                 },
                 NetworkPacket::NewPlayer(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
 
                     area.players.push(Player::from_save(update.player, &mut area.space, (&self.textures).into()));
                 },
-                NetworkPacket::PlayerVelocityUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
-
-                    let player = area.players.iter_mut().find(|player| {player.id == update.id}).unwrap();
+                NetworkPacket::PlayerStateUpdate(update) => {
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
 
-                    let player_body = area.space.rigid_body_set.get(player.body.body_handle).unwrap();
+                    let Some(player) = area.players.iter_mut().find(|player| {player.id == update.player_id}) else {
+                        continue;
+                    };
 
-                    let _player_pos = player_body.position();
+                    // we predict our own player so only correct it if we got it wrong
+                    if player.owner == Owner::ClientId(self.client_id) {
+                        player.reconcile(&update, &mut area.space);
+                        continue;
+                    }
 
-                    player.set_velocity(update.velocity, &mut area.space);
+                    player.interpolation.push(update.pos, update.timestamp, &self.interpolation);
                 },
                 NetworkPacket::PlayerCursorUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
+
+                    let Some(player) = area.players.iter_mut().find(|player| {player.id == update.id}) else {
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.id}).unwrap();
+                        continue;
+
+                    };
 
                     player.set_cursor_pos(update.pos);
                 },
                 NetworkPacket::PlayerFacingUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.id}).unwrap();
+                    let Some(player) = area.players.iter_mut().find(|player| {player.id == update.id}) else {
+
+                        continue;
+
+                    };
 
                     player.facing = update.facing;
                 },
                 NetworkPacket::SpawnBulletTrail(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
 
                     area.bullet_trails.push(
                         BulletTrail::from_save(update.save)
                     );
-                },
-                NetworkPacket::PlayerPositionUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
-                    player.set_pos(update.pos, &mut area.space);
-
-
-
-
                 },
                 NetworkPacket::PropPositionUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
 
                     let prop = if let Some(prop) = area.props.iter_mut().find(|prop| {prop.id()} == update.prop_id) {prop} else {continue};
                     
@@ -441,7 +435,7 @@ impl Client {
                         continue;
                     }
 
-                    prop.set_pos(update.pos, &mut area.space);
+                    prop.interpolation_mut().push(update.pos, update.timestamp, &self.interpolation);
                     *prop.last_received_position_update_mut() = web_time::Instant::now();
 
 
@@ -455,66 +449,98 @@ impl Client {
                     // prop.dissolve(&self.textures, &mut area.space, &mut area.dissolved_pixels,None, area.id);
                 }
                 NetworkPacket::RemovePropUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
+
+                    let Some(prop) = area.props.iter_mut().find(|prop|{prop.id() == update.prop_id}) else {
 
-                    let prop = area.props.iter_mut().find(|prop|{prop.id() == update.prop_id}).unwrap();
+                        continue;
+
+                    };
 
                     prop.mark_despawn();
 
                 },
                 NetworkPacket::NewDroppedItemUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
+                        continue;
+                    };
 
                     area.dropped_items.push(
                         DroppedItem::from_save(update.dropped_item, &mut area.space, &self.prefab_data, (&self.textures).into())
                     );
                 },
                 NetworkPacket::RemoveDroppedItemUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
+                        continue;
+                    };
+
+                    let Some(dropped_item) = area.dropped_items.iter_mut().find(|dropped_item| {dropped_item.id == update.dropped_item_id}) else {
 
-                    let dropped_item = area.dropped_items.iter_mut().find(|dropped_item| {dropped_item.id == update.dropped_item_id}).unwrap();
+                        continue;
+
+                    };
 
                     dropped_item.mark_despawn();
                 }
                 NetworkPacket::DroppedItemVelocityUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
+                        continue;
+                    };
+
+                    let Some(dropped_item) = area.dropped_items.iter_mut().find(|dropped_item| {dropped_item.id == update.id}) else {
+
+                        continue;
 
-                    let dropped_item = area.dropped_items.iter_mut().find(|dropped_item| {dropped_item.id == update.id}).unwrap();
+                    };
 
                     dropped_item.set_velocity(&mut area.space, update.velocity);
                 },
                 NetworkPacket::ActiveItemSlotUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
+                        continue;
+                    };
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
+                    let Some(player) = area.players.iter_mut().find(|player| {player.id == update.player_id}) else {
+
+                        continue;
+
+                    };
 
                     player.selected_item = update.active_item_slot as usize;
 
                 },
                 NetworkPacket::ItemSlotQuantityUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
+                        continue;
+                    };
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
+                    let Some(player) = area.players.iter_mut().find(|player| {player.id == update.player_id}) else {
+
+                        continue;
+
+                    };
 
                     match &mut player.inventory.items[update.inventory_index] {
                         Some(item_slot) => {
@@ -528,13 +554,19 @@ impl Client {
 
                 },
                 NetworkPacket::ItemSlotUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
+                        continue;
+                    };
+
+                    let Some(player) = area.players.iter_mut().find(|player| {player.id == update.player_id}) else {
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
+                        continue;
+
+                    };
 
                     player.inventory.items[update.inventory_index] = match update.item_slot {
                         Some(item_slot_save) => {
@@ -547,38 +579,56 @@ impl Client {
                 },
 
                 NetworkPacket::EnemyPositionUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
+                        continue;
+                    };
 
-                    let enemy = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}).unwrap();
+                    let Some(enemy) = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}) else {
 
-                    area.space.rigid_body_set.get_mut(enemy.body.body_handle).unwrap().set_position(update.position, true);
+                        continue;
+
+                    };
+
+                    enemy.interpolation.push(update.position, update.timestamp, &self.interpolation);
                 },
 
                 NetworkPacket::EnemyVelocityUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
+                        continue;
+                    };
 
-                    let enemy = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}).unwrap();
+                    let Some(enemy) = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}) else {
+
+                        continue;
+
+                    };
 
                     area.space.rigid_body_set.get_mut(enemy.body.body_handle).unwrap().set_vels(update.velocity, true);
 
 
                 },
                 NetworkPacket::EnemyWeaponUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
+                        continue;
+                    };
+
+                    let Some(enemy) = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}) else {
 
-                    let enemy = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}).unwrap();
+                        continue;
+
+                    };
 
 
 
@@ -588,59 +638,90 @@ impl Client {
                 },
                 NetworkPacket::NewEnemyUpdate(update) => {
 
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
 
+                        continue;
+
+                    };
 
-                    let enemy = Enemy::from_save(update.enemy, &mut area.space);
+
+                    let enemy = Enemy::from_save(update.enemy, &mut area.space, &self.prefab_data);
 
                     area.enemies.push(enemy);
                 }
                 NetworkPacket::EnemyDespawnUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
+                        continue;
+                    };
+
+                    let Some(enemy) = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}) else {
+
+                        continue;
 
-                    let enemy = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}).unwrap();
+                    };
 
                     enemy.mark_despawn();
 
 
                 },
                 NetworkPacket::EnemyHealthUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
+                    let Some(area) = self.world.areas.iter_mut().find(
                         |area| {
                             area.id == update.area_id
                         }
-                    ).unwrap();
+                    ) else {
+                        continue;
+                    };
 
                     if let Some(enemy) = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}) {
                         enemy.last_health_update = web_time::Instant::now();
-                        enemy.health = update.health
+                        enemy.health = update.health;
+
+                        // the server picks the death so everyone plays the same one
+                        if update.death.is_some() {
+                            enemy.death = update.death;
+                        }
                     }
 
                 },
                 NetworkPacket::PlayerHealthUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
+                    let Some(player) = area.players.iter_mut().find(|player| {player.id == update.player_id}) else {
+
+                        continue;
+
+                    };
 
                     player.health = update.health
                 },
                 NetworkPacket::PlayerDespawnUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+                        continue;
+                    };
+                    let Some(player) = area.players.iter_mut().find(|player| {player.id == update.player_id}) else {
+                        continue;
+                    };
 
                     player.mark_despawn();
                 },
                 NetworkPacket::StupidDissolvedPixelVelocityUpdate(update) => {
 
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                    let Some(area) = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}) else {
+
+                        continue;
+
+                    };
 
                     let intersections = get_intersections(
                         update.weapon_pos,
@@ -667,6 +748,39 @@ impl Client {
                     }
 
                 }
+                NetworkPacket::PlayerInputUpdate(_update) => {
+                    // only the server simulates inputs
+                }
+                NetworkPacket::ClientViewUpdate(_update) => {
+                    // only the server cares what we can see
+                }
+                NetworkPacket::PlayerReadyUpdate(update) => {
+                    self.lobby.set_ready(update.client_id, update.ready);
+                }
+                NetworkPacket::PlayerLeftLobby(update) => {
+                    self.lobby.remove(update.client_id);
+                }
+                NetworkPacket::GamePhaseUpdate(update) => {
+                    self.lobby.set_phase(update.phase);
+                }
+                NetworkPacket::WaveUpdate(update) => {
+                    self.wave_status.apply(update.event);
+                }
+                NetworkPacket::TickRateUpdate(update) => {
+                    self.timestep.set_tick_rate(update.tick_rate);
+                }
+                NetworkPacket::ChatMessage(message) => {
+                    self.chat_box.push(message);
+                }
+                NetworkPacket::LatencyUpdate(_update) => {
+                    // only the server cares about this
+                }
+                NetworkPacket::PropInteraction(_update) => {
+                    // only the server cares about this
+                }
+                NetworkPacket::NoiseUpdate(_update) => {
+                    // only the server cares about this
+                }
             }
         }
 
@@ -686,6 +800,88 @@ impl Client {
     }
 
 
+    pub fn toggle_ready(&mut self) {
+        if !self.lobby.in_lobby() || !is_key_released(KeyCode::R) {
+            return;
+        }
+
+        let ready = !self.lobby.is_ready(self.client_id);
+
+        // the server echoes this back but we dont want the text to lag
+        self.lobby.set_ready(self.client_id, ready);
+
+        self.network_io.send_network_packet(
+            PlayerReadyUpdate {
+                client_id: self.client_id,
+                ready,
+            }.into()
+        );
+    }
+
+    /// Let the server know where our camera is so it can stop sending us stuff we cant see
+    pub fn send_view_update(&mut self) {
+        if self.last_view_update.elapsed().as_millis() < 250 {
+            return;
+        }
+
+        self.network_io.send_network_packet(
+            ClientViewUpdate {
+                view: ClientView {
+                    center: macroquad_to_rapier(&self.camera_rect.center()),
+                    half_extents: glamx::vec2(self.camera_rect.w / 2., self.camera_rect.h / 2.),
+                },
+            }.into()
+        );
+
+        self.last_view_update = web_time::Instant::now();
+    }
+
+    /// Send whatever was typed once they hit enter. The server fills in who we are
+    pub fn update_chat(&mut self) {
+
+        // nobody to talk to in a replay
+        if self.network_io.is_offline() {
+            return;
+        }
+
+        if let Some(text) = self.chat_box.update() {
+            self.network_io.send_network_packet(
+                ChatMessage {
+                    area_id: None,
+                    sender: ChatSender::System,
+                    text,
+                }.into()
+            );
+        }
+    }
+
+    pub fn set_network_conditions(&mut self, conditions: NetworkConditions) {
+        self.network_io.network_simulator.conditions = conditions;
+    }
+
+    /// Lets us switch between a few bad connections without restarting
+    pub fn cycle_network_conditions(&mut self) {
+        if is_key_released(KeyCode::F8) {
+            let presets = network_condition_presets();
+
+            self.network_condition_preset = (self.network_condition_preset + 1) % presets.len();
+
+            self.set_network_conditions(presets[self.network_condition_preset]);
+        }
+
+        let conditions = self.network_io.network_simulator.conditions;
+
+        if conditions.is_active() {
+            self.debug_strings.push(format!("Simulated network: {} (F8 to change)", conditions));
+        }
+    }
+
+    pub fn toggle_nav_graph(&mut self) {
+        if is_key_released(KeyCode::F9) {
+            self.show_nav_graph = !self.show_nav_graph;
+        }
+    }
+
     pub fn update_camera_to_match_screen_size(&mut self) {
         self.camera_rect.w = screen_width();
         self.camera_rect.h = screen_height();
@@ -714,26 +910,16 @@ impl Client {
 
         self.phone();
         self.measure_latency();
-        self.ping();
-
-        let ctx = ClientTickContext {
-            
-            start: &self.start,
-            draw_commands: &mut self.draw_commands,
-            material_loader: &mut self.material_loader,
-            network_io: &mut self.network_io,
-            last_tick_duration: &self.last_tick_duration,
-            client_id: &self.client_id,
-            camera_rect: &mut self.camera_rect,
-            prefabs: &self.prefab_data,
-            screen_shake: &mut self.screen_shake,
-            sounds: &mut self.sounds,
-            textures: &self.textures,
-            camera: &self.camera,
-            fonts: &self.fonts,
-            debug_strings: &mut self.debug_strings,
-
-        };
+        self.update_chat();
+        self.send_view_update();
+        self.cycle_network_conditions();
+        self.toggle_nav_graph();
+
+        // keys are going into the chat box
+        if !self.chat_box.open {
+            self.ping();
+            self.toggle_ready();
+        }
 
         // if !self.spawned {
         //     self.world.areas[0].spawn_player(&mut ctx);
@@ -743,9 +929,61 @@ impl Client {
 
         let among_us = web_time::Instant::now();
 
-        self.world.tick(&mut interceptors_lib::TickContext::Client(ctx));
+        let step = self.timestep.step();
+
+        // replays run on their own clock so they can be paused and sped up
+        let steps = match &mut self.replay {
+            Some(replay) => {
+                replay.update_free_camera(&mut self.camera_rect);
+
+                replay.take_steps(step)
+            },
+            None => self.timestep.advance(),
+        };
+
+        // hold onto input edges until a step actually runs, and only give them to the first one so they dont fire twice
+        self.frame_input.sample();
+
+        let no_input = FrameInput::default();
+
+        // the world always moves forward by the same amount so physics comes out the same as on the server
+        for step_index in 0..steps {
+            let ctx = ClientTickContext {
+            
+                start: &self.start,
+                draw_commands: &mut self.draw_commands,
+                material_loader: &mut self.material_loader,
+                network_io: &mut self.network_io,
+                last_tick_duration: &step,
+                client_id: &self.client_id,
+                camera_rect: &mut self.camera_rect,
+                prefabs: &self.prefab_data,
+                screen_shake: &mut self.screen_shake,
+                sounds: &mut self.sounds,
+                textures: &self.textures,
+                camera: &self.camera,
+                fonts: &self.fonts,
+                debug_strings: &mut self.debug_strings,
+                input: match step_index {
+                    0 => &self.frame_input,
+                    _ => &no_input,
+                },
+                typing: self.chat_box.open,
+                show_nav_graph: self.show_nav_graph,
+            };
+
+            self.world.tick(&mut interceptors_lib::TickContext::Client(ctx));
+
+            for area in &mut self.world.areas {
+                area.interpolate_remote_entities(self.client_id, &self.interpolation);
+            }
+        }
 
-        self.debug_strings.push(format!("world tick: {:?}", among_us.elapsed()));
+        if steps > 0 {
+            self.frame_input.clear();
+        }
+
+        self.debug_strings.push(format!("world tick ({} steps): {:?}", steps, among_us.elapsed()));
         if self.last_network_flush.elapsed().as_millis() >= 33 {
             self.packets_sent += 1;
             let then = web_time::Instant::now();
@@ -872,7 +1110,10 @@ impl Client {
                 sounds: &mut self.sounds,
                 textures: &mut self.textures,
                 camera: &mut self.camera,
-                fonts: &self.fonts
+                fonts: &self.fonts,
+                input: &self.frame_input,
+                typing: self.chat_box.open,
+                show_nav_graph: self.show_nav_graph
             }
         ).into();
 
@@ -880,10 +1121,24 @@ impl Client {
 
         let then = web_time::Instant::now();
 
+        // draw in between the last two physics steps
+        let alpha = match &self.replay {
+            Some(replay) => replay.alpha(self.timestep.step()),
+            None => self.timestep.alpha(),
+        };
+
+        for area in &mut self.world.areas {
+            area.space.apply_render_interpolation(alpha);
+        }
+
         self.world.draw(
             &mut ctx
         );
 
+        for area in &mut self.world.areas {
+            area.space.restore_simulated_poses();
+        }
+
         let draw_commands_time = then.elapsed();
 
        
@@ -927,7 +1182,10 @@ impl Client {
                 sounds: &mut self.sounds,
                 textures: &mut self.textures,
                 camera: &mut self.camera,
-                fonts: &self.fonts
+                fonts: &self.fonts,
+                input: &self.frame_input,
+                typing: self.chat_box.open,
+                show_nav_graph: self.show_nav_graph
             }
         ).into();
         let then = web_time::Instant::now();
@@ -936,6 +1194,18 @@ impl Client {
         // hud stuff needs to be here for now and use the native draw functions until i add a draw_hud function
         draw_fps();
 
+        self.draw_lobby_overlay();
+
+        self.draw_wave_status();
+
+        self.chat_box.draw();
+
+        self.draw_connection_overlay();
+
+        if let Some(replay) = &self.replay {
+            replay.draw_overlay();
+        }
+
         for (i, debug_string) in self.debug_strings.iter().enumerate() {
             draw_text(debug_string, 0., 180. + (i * 20) as f32, 20., WHITE);
         }
@@ -956,6 +1226,108 @@ impl Client {
 
 
 
+    }
+
+    fn draw_wave_status(&self) {
+
+        if self.wave_status.total_waves == 0 {
+            return;
+        }
+
+        let status = match (self.wave_status.finished, self.wave_status.in_progress) {
+            (true, _) => "All waves cleared!".to_string(),
+            (false, true) => format!("Wave {}/{} - {} enemies remaining", self.wave_status.wave, self.wave_status.total_waves, self.wave_status.enemies_remaining),
+            (false, false) => format!("Wave {}/{} cleared", self.wave_status.wave, self.wave_status.total_waves),
+        };
+
+        let text_size = measure_text(&status, None, 30, 1.);
+
+        draw_text(
+            &status,
+            (screen_width() - text_size.width) / 2.,
+            30.,
+            30.,
+            WHITE
+        );
+    }
+
+    fn draw_lobby_overlay(&self) {
+
+        // we aren't actually in the lobby so there is nothing to ready up for
+        if !self.lobby.in_lobby() || self.replay.is_some() {
+            return;
+        }
+
+        let ready_count = self.lobby.ready.iter().filter(|(_, ready)| *ready).count();
+
+        let status = match self.lobby.countdown_remaining() {
+            Some(remaining) => format!("Match starting in {:.1}s", remaining.as_secs_f32()),
+            None => match self.lobby.is_ready(self.client_id) {
+                true => format!("Ready! Waiting for everyone else ({}/{})", ready_count, self.lobby.ready.len()),
+                false => format!("Press R to ready up ({}/{})", ready_count, self.lobby.ready.len()),
+            },
+        };
+
+        let text_size = measure_text(&status, None, 30, 1.);
+
+        draw_text(
+            &status,
+            (screen_width() - text_size.width) / 2.,
+            60.,
+            30.,
+            WHITE
+        );
+
+        for (index, (client_id, ready)) in self.lobby.ready.iter().enumerate() {
+
+            let name = match *client_id == self.client_id {
+                true => "You".to_string(),
+                false => self.player_name(*client_id).unwrap_or_else(|| format!("Player {}", index + 1)),
+            };
+
+            let (text, color) = match ready {
+                true => (format!("{}: ready", name), GREEN),
+                false => (format!("{}: not ready", name), GRAY),
+            };
+
+            draw_text(&text, screen_width() - 250., 100. + (index * 25) as f32, 24., color);
+        }
+    }
+
+    /// The name on their player, if they have spawned one yet
+    fn player_name(&self, client_id: ClientId) -> Option<String> {
+        self.world.areas.iter()
+            .flat_map(|area| area.players.iter())
+            .find(|player| player.owner == Owner::ClientId(client_id) && !player.name.is_empty())
+            .map(|player| player.name.clone())
+    }
+
+    fn draw_connection_overlay(&self) {
+
+        let status = match &self.network_io.state {
+            ConnectionState::Connected | ConnectionState::Offline => return,
+            ConnectionState::Connecting { attempt: 0, .. } => format!("Connecting to {}...", self.network_io.url),
+            ConnectionState::Connecting { attempt, .. } => format!("Reconnecting to {} (attempt {})...", self.network_io.url, attempt),
+            ConnectionState::Lost { reason } => format!("Connection lost: {}", reason),
+            ConnectionState::Reconnecting { attempt, next_attempt } => {
+                let remaining = next_attempt.saturating_duration_since(web_time::Instant::now());
+
+                format!("Connection lost. Reconnecting in {:.1}s (attempt {})", remaining.as_secs_f32(), attempt)
+            },
+            ConnectionState::Failed { reason } => format!("Could not connect to server: {}", reason),
+        };
+
+        draw_rectangle(0., 0., screen_width(), screen_height(), Color::new(0., 0., 0., 0.6));
+
+        let text_size = measure_text(&status, None, 30, 1.);
+
+        draw_text(
+            &status,
+            (screen_width() - text_size.width) / 2.,
+            screen_height() / 2.,
+            30.,
+            WHITE
+        );
     }
 
     pub fn apply_screen_shake_decays(&mut self) {
@@ -1,6 +1,9 @@
 
 
-use interceptors_lib::load_assets;
+use std::path::PathBuf;
+
+use clap::Parser;
+use interceptors_lib::{chat::sanitize_name, fixed_timestep::{DEFAULT_MAX_CATCH_UP_STEPS, DEFAULT_TICK_RATE, FixedTimestep}, interpolation::InterpolationSettings, load_assets, network_conditions::NetworkConditions, recording::Recording};
 use macroquad::{input::show_mouse, miniquad::{conf::Platform, window::request_quit}, window::Conf};
 use wasm_logger::Config;
 
@@ -8,8 +11,67 @@ use crate::{client::{Client}, main_menu::{MainMenu, MainMenuResult}};
 
 
 mod client;
+mod chat_box;
 mod main_menu;
+mod replay;
 mod shaders;
+mod recent_servers;
+mod server_select;
+
+const SERVER_ENV_VAR: &str = "INTERCEPTORS_SERVER";
+
+#[derive(Parser)]
+struct Args {
+    /// Server url to connect to. Skips the main menu. Can also be set with the INTERCEPTORS_SERVER environment variable
+    #[arg(long)]
+    server: Option<String>,
+    /// How far in the past other players, enemies and props are rendered, in milliseconds
+    #[arg(long)]
+    interpolation_delay: Option<u64>,
+    /// Most ticks we will run in one frame to catch up after falling behind
+    #[arg(long)]
+    max_catch_up_steps: Option<u32>,
+    /// Name other players see. Only used with --server, the main menu asks for one otherwise
+    #[arg(long)]
+    name: Option<String>,
+    /// Watch a recording made with the server's --record instead of connecting
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// Fake latency added to every packet in each direction, in milliseconds. F8 cycles through presets in game
+    #[arg(long)]
+    sim_latency: Option<u64>,
+    /// How much the fake latency varies either way, in milliseconds
+    #[arg(long)]
+    sim_jitter: Option<u64>,
+    /// Percent of position and velocity updates to drop in each direction
+    #[arg(long)]
+    sim_loss: Option<f32>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_args() -> Args {
+    let mut args = Args::parse();
+
+    args.server = args.server
+        .or_else(|| std::env::var(SERVER_ENV_VAR).ok())
+        .map(|server| server_select::normalize_server_url(&server));
+
+    args
+}
+
+#[cfg(target_arch = "wasm32")]
+fn parse_args() -> Args {
+    Args {
+        server: Some("wss://interceptors.voxany.net/ws/".to_string()),
+        interpolation_delay: None,
+        max_catch_up_steps: None,
+        name: None,
+        replay: None,
+        sim_latency: None,
+        sim_jitter: None,
+        sim_loss: None,
+    }
+}
 
 #[cfg(target_family = "wasm")]
 unsafe extern "C" {
@@ -55,22 +117,68 @@ async fn main() {
     }
 
     let assets = load_assets().await;
-    let mut main_menu = MainMenu::new(assets.clone()).await;
 
     show_mouse(false);
 
-    let mut client = Client::connect(assets).await;
+    let args = parse_args();
+
+    let mut interpolation = InterpolationSettings::default();
+
+    if let Some(interpolation_delay) = args.interpolation_delay {
+        interpolation.delay = web_time::Duration::from_millis(interpolation_delay);
+    }
+
+    // the server tells us its tick rate once we connect
+    let timestep = FixedTimestep::new(
+        DEFAULT_TICK_RATE,
+        args.max_catch_up_steps.unwrap_or(DEFAULT_MAX_CATCH_UP_STEPS)
+    );
 
-    client.run().await;
+    if let Some(replay_path) = args.replay {
+        let recording = match Recording::load(&replay_path) {
+            Ok(recording) => recording,
+            Err(error) => {
+                log::error!("Failed to load replay: {}", error);
+                return;
+            },
+        };
 
-    return;
+        let mut client = Client::replay(assets, recording, interpolation, timestep).await;
+
+        client.run().await;
+
+        return;
+    }
+
+    let network_conditions = NetworkConditions::new(
+        args.sim_latency.unwrap_or(0),
+        args.sim_jitter.unwrap_or(0),
+        args.sim_loss.unwrap_or(0.)
+    );
+
+    // skip the main menu if we were told where to connect
+    if let Some(url) = args.server {
+        let name = sanitize_name(&args.name.unwrap_or_default());
+
+        let mut client = Client::connect(assets, url, name, interpolation, timestep).await;
+
+        client.set_network_conditions(network_conditions);
+
+        client.run().await;
+
+        return;
+    }
+
+    let mut main_menu = MainMenu::new(assets.clone()).await;
 
     match main_menu.run().await {
         MainMenuResult::Quit => {
             request_quit();
         },
-        MainMenuResult::Connect => {
-            let mut client = Client::connect(assets).await;
+        MainMenuResult::Connect { url, name } => {
+            let mut client = Client::connect(assets, url, name, interpolation, timestep).await;
+
+            client.set_network_conditions(network_conditions);
 
             client.run().await;
         },
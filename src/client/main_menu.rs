@@ -2,7 +2,13 @@
 use interceptors_lib::{Assets, ambiance::Ambiance, button::Button, font_loader::FontLoader, load_assets, macroquad_to_rapier, sound_loader::SoundLoader, texture_loader::ClientTextureLoader};
 use macroquad::{audio::{PlaySoundParams, Sound, play_sound, stop_sound}, camera::{Camera2D, set_camera, set_default_camera}, color::{BLACK, WHITE}, input::mouse_position, math::{Rect, Vec2, vec2}, prelude::{Material, ShaderSource, gl_use_default_material, gl_use_material, load_material}, rand::RandomRange, text::{TextParams, draw_text, draw_text_ex}, texture::{DrawTextureParams, RenderTarget, draw_texture_ex, render_target}, window::{clear_background, next_frame, screen_height, screen_width}};
 
-use crate::{shaders::{CRT_FRAGMENT_SHADER, CRT_VERTEX_SHADER}};
+use crate::{recent_servers::RecentServers, server_select::{ServerSelectResult, ServerSelectUI}, shaders::{CRT_FRAGMENT_SHADER, CRT_VERTEX_SHADER}};
+
+#[derive(PartialEq, Clone, Copy)]
+enum MainMenuScreen {
+    Title,
+    ServerSelect
+}
 
 pub struct MainMenu {
     material: Material,
@@ -12,6 +18,9 @@ pub struct MainMenu {
     textures: ClientTextureLoader,
     camera_rect: Rect,
     ui: MainMenuUI,
+    server_select_ui: ServerSelectUI,
+    recent_servers: RecentServers,
+    screen: MainMenuScreen,
     ambiance: Vec<Ambiance>,
     played_radio_chatter: bool,
     start: web_time::Instant,
@@ -150,6 +159,9 @@ impl MainMenu {
 
         let ui = MainMenuUI::new();
 
+        let recent_servers = RecentServers::load();
+        let server_select_ui = ServerSelectUI::new(&recent_servers);
+
         let ambiance = vec![
 
         ];
@@ -162,6 +174,9 @@ impl MainMenu {
             textures: assets.textures,
             camera_rect: camera_rect,
             ui,
+            server_select_ui,
+            recent_servers,
+            screen: MainMenuScreen::Title,
             ambiance,
             played_radio_chatter: false,
             start: web_time::Instant::now(),
@@ -191,17 +206,44 @@ impl MainMenu {
         }
 
     }
+    fn tick_server_select(&mut self) -> Option<MainMenuResult> {
+        match self.server_select_ui.tick(&self.sound_loader)? {
+            ServerSelectResult::Connect { url, name } => {
+                self.recent_servers.push(url.clone());
+                self.recent_servers.name = name.clone();
+                self.recent_servers.save();
+
+                self.stop_sounds();
+                Some(MainMenuResult::Connect { url, name })
+            },
+            ServerSelectResult::Back => {
+                self.screen = MainMenuScreen::Title;
+                None
+            },
+        }
+    }
+
     pub fn tick(&mut self) -> Option<MainMenuResult> {
-        self.ui.tick(&self.sound_loader);
 
-        if self.ui.play_button.down {
-            self.stop_sounds();
-            return Some(MainMenuResult::Connect) 
-        }
+        match self.screen {
+            MainMenuScreen::Title => {
+                self.ui.tick(&self.sound_loader);
 
-        if self.ui.quit_button.released {
-            self.stop_sounds();
-            return Some(MainMenuResult::Quit);
+                if self.ui.play_button.released {
+                    self.server_select_ui = ServerSelectUI::new(&self.recent_servers);
+                    self.screen = MainMenuScreen::ServerSelect;
+                }
+
+                if self.ui.quit_button.released {
+                    self.stop_sounds();
+                    return Some(MainMenuResult::Quit);
+                }
+            },
+            MainMenuScreen::ServerSelect => {
+                if let Some(result) = self.tick_server_select() {
+                    return Some(result);
+                }
+            },
         }
         self.start_ambiance();
         //self.play_radio_chatter();
@@ -235,7 +277,11 @@ impl MainMenu {
         camera.zoom.y = -camera.zoom.y; 
         set_camera(&camera);
         clear_background(BLACK);
-        self.ui.draw(&self.fonts);
+
+        match self.screen {
+            MainMenuScreen::Title => self.ui.draw(&self.fonts),
+            MainMenuScreen::ServerSelect => self.server_select_ui.draw(&self.fonts),
+        }
 
 
         self.draw_title();
@@ -269,7 +315,10 @@ impl MainMenu {
 
 pub enum MainMenuResult {
     Quit,
-    Connect
+    Connect {
+        url: String,
+        name: String
+    }
 }
 
 pub struct MainMenuUI {
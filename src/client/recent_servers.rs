@@ -0,0 +1,46 @@
+use std::fs::{read_to_string, write};
+
+use serde::{Deserialize, Serialize};
+
+const RECENT_SERVERS_PATH: &str = "recent_servers.json";
+const MAX_RECENT_SERVERS: usize = 5;
+
+/// Servers we connected to recently, most recent first. Also remembers the name we used
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct RecentServers {
+    pub servers: Vec<String>,
+    #[serde(default)]
+    pub name: String
+}
+
+impl RecentServers {
+
+    pub fn load() -> Self {
+        let json = match read_to_string(RECENT_SERVERS_PATH) {
+            Ok(json) => json,
+            Err(_) => return Self::default(), // we probably just havent connected to anything yet
+        };
+
+        match serde_json::from_str(&json) {
+            Ok(recent_servers) => recent_servers,
+            Err(error) => {
+                log::warn!("Failed to parse recent servers: {}", error);
+                Self::default()
+            },
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(error) = write(RECENT_SERVERS_PATH, serde_json::to_string_pretty(self).unwrap()) {
+            log::warn!("Failed to save recent servers: {}", error);
+        }
+    }
+
+    pub fn push(&mut self, url: String) {
+        self.servers.retain(|server| *server != url);
+
+        self.servers.insert(0, url);
+
+        self.servers.truncate(MAX_RECENT_SERVERS);
+    }
+}
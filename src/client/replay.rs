@@ -0,0 +1,248 @@
+use interceptors_lib::{area::AreaId, fixed_timestep::DEFAULT_MAX_CATCH_UP_STEPS, recording::Recording, updates::{LoadArea, NetworkPacket}};
+use macroquad::{color::WHITE, input::{KeyCode, MouseButton, is_key_down, is_key_pressed, is_mouse_button_down, mouse_delta_position, mouse_wheel}, math::Rect, text::{draw_text, measure_text}, window::{screen_height, screen_width}};
+
+const SEEK_STEP: web_time::Duration = web_time::Duration::from_secs(5);
+
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 8.;
+
+/// Plays a recording back into the client as if the packets were coming from the server
+pub struct Replay {
+    recording: Recording,
+    /// Which of the recorded areas we are watching
+    area_index: usize,
+    /// Index of the next packet to hand out
+    next_packet: usize,
+    /// How far into the recording we are
+    time: web_time::Duration,
+    /// Replay time that hasn't been simulated yet
+    accumulator: web_time::Duration,
+    last_update: web_time::Instant,
+    pub paused: bool,
+    pub speed: f32
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            area_index: 0,
+            next_packet: 0,
+            time: web_time::Duration::ZERO,
+            accumulator: web_time::Duration::ZERO,
+            last_update: web_time::Instant::now(),
+            paused: false,
+            speed: 1.,
+        }
+    }
+
+    /// The area we are watching as it was at the start of the recording
+    pub fn initial_area(&self) -> LoadArea {
+        self.recording.header.areas[self.area_index].clone()
+    }
+
+    fn area_id(&self) -> AreaId {
+        self.recording.header.areas[self.area_index].id
+    }
+
+    /// Handle the playback keys. Returns true if we jumped backwards and the world needs to be rebuilt from the start
+    pub fn controls(&mut self) -> bool {
+
+        if is_key_pressed(KeyCode::Space) {
+            self.paused = !self.paused;
+        }
+
+        if is_key_pressed(KeyCode::Equal) || is_key_pressed(KeyCode::KpAdd) {
+            self.speed = (self.speed * 2.).min(MAX_SPEED);
+        }
+
+        if is_key_pressed(KeyCode::Minus) || is_key_pressed(KeyCode::KpSubtract) {
+            self.speed = (self.speed / 2.).max(MIN_SPEED);
+        }
+
+        if is_key_pressed(KeyCode::Right) {
+            self.time = (self.time + SEEK_STEP).min(self.recording.duration());
+        }
+
+        if is_key_pressed(KeyCode::Left) {
+            self.restart(self.time.saturating_sub(SEEK_STEP));
+
+            return true;
+        }
+
+        // cycle through the areas, everything before now has to be replayed for the new one
+        if is_key_pressed(KeyCode::Tab) && self.recording.header.areas.len() > 1 {
+            self.area_index = (self.area_index + 1) % self.recording.header.areas.len();
+
+            self.restart(self.time);
+
+            return true;
+        }
+
+        false
+    }
+
+    /// Go back to the first packet. The next call to due_packets catches up to time all at once
+    fn restart(&mut self, time: web_time::Duration) {
+        self.next_packet = 0;
+        self.time = time;
+        self.accumulator = web_time::Duration::ZERO;
+    }
+
+    /// Move the playback forward by however much real time passed and return the packets that happened in that time
+    pub fn due_packets(&mut self) -> Vec<NetworkPacket> {
+
+        let elapsed = self.last_update.elapsed();
+        self.last_update = web_time::Instant::now();
+
+        if !self.paused {
+            let elapsed = elapsed.mul_f32(self.speed);
+
+            self.time += elapsed;
+            self.accumulator += elapsed;
+        }
+
+        if self.time >= self.recording.duration() {
+            self.time = self.recording.duration();
+            self.paused = true;
+        }
+
+        let area_id = self.area_id();
+
+        let mut packets = Vec::new();
+
+        while let Some(recorded) = self.recording.packets.get(self.next_packet) {
+
+            if recorded.time > self.time {
+                break;
+            }
+
+            self.next_packet += 1;
+
+            // the client only has one area loaded and panics on packets for areas it doesnt have
+            let relevant = match &recorded.packet {
+                NetworkPacket::LoadArea(load_area) => load_area.id == area_id,
+                packet => packet.relevance().area_id.is_none_or(|packet_area| packet_area == area_id),
+            };
+
+            if relevant {
+                packets.push(recorded.packet.clone());
+            }
+        }
+
+        packets
+    }
+
+    /// How many simulation steps the world needs to keep up with the playback
+    pub fn take_steps(&mut self, step: web_time::Duration) -> u32 {
+
+        // sped up playback needs more steps per frame than normal
+        let max_steps = DEFAULT_MAX_CATCH_UP_STEPS * MAX_SPEED as u32;
+
+        let mut steps = 0;
+
+        while self.accumulator >= step {
+
+            // dont try to simulate a huge seek, the packets will put everything where it should be anyway
+            if steps == max_steps {
+                self.accumulator = web_time::Duration::ZERO;
+                break;
+            }
+
+            self.accumulator -= step;
+            steps += 1;
+        }
+
+        steps
+    }
+
+    /// How far we are between the last step and the next one
+    pub fn alpha(&self, step: web_time::Duration) -> f32 {
+        (self.accumulator.as_secs_f32() / step.as_secs_f32()).clamp(0., 1.)
+    }
+
+    pub fn update_free_camera(&self, camera_rect: &mut Rect) {
+
+        let center = camera_rect.center();
+
+        if mouse_wheel().1 < 0. {
+            camera_rect.w *= 1.1;
+        }
+
+        if mouse_wheel().1 > 0. {
+            camera_rect.w /= 1.1;
+        }
+
+        let ratio = screen_height() / screen_width();
+
+        camera_rect.h = camera_rect.w * ratio;
+
+        // zoom around the middle of the screen instead of the corner
+        camera_rect.x = center.x - camera_rect.w / 2.;
+        camera_rect.y = center.y - camera_rect.h / 2.;
+
+        let camera_speed = match is_key_down(KeyCode::LeftShift) {
+            true => 30.,
+            false => 5.,
+        };
+
+        if is_key_down(KeyCode::W) {
+            camera_rect.y -= camera_speed;
+        }
+
+        if is_key_down(KeyCode::S) {
+            camera_rect.y += camera_speed;
+        }
+
+        if is_key_down(KeyCode::A) {
+            camera_rect.x -= camera_speed;
+        }
+
+        if is_key_down(KeyCode::D) {
+            camera_rect.x += camera_speed;
+        }
+
+        if is_mouse_button_down(MouseButton::Middle) || is_mouse_button_down(MouseButton::Right) {
+            camera_rect.x += mouse_delta_position().x * camera_rect.w / 2.;
+            camera_rect.y += mouse_delta_position().y * camera_rect.h / 2.;
+        }
+    }
+
+    pub fn draw_overlay(&self) {
+
+        let status = format!(
+            "{} {:.1}s / {:.1}s  {}x  area {}/{}",
+            match self.paused {
+                true => "Paused",
+                false => "Playing",
+            },
+            self.time.as_secs_f32(),
+            self.recording.duration().as_secs_f32(),
+            self.speed,
+            self.area_index + 1,
+            self.recording.header.areas.len()
+        );
+
+        let text_size = measure_text(&status, None, 30, 1.);
+
+        draw_text(
+            &status,
+            (screen_width() - text_size.width) / 2.,
+            screen_height() - 50.,
+            30.,
+            WHITE
+        );
+
+        let controls = "Space: pause  +/-: speed  Left/Right: seek  Tab: next area  WASD/right drag: move  Scroll: zoom";
+
+        let text_size = measure_text(controls, None, 20, 1.);
+
+        draw_text(
+            controls,
+            (screen_width() - text_size.width) / 2.,
+            screen_height() - 20.,
+            20.,
+            WHITE
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use interceptors_lib::{button::Button, chat::{MAX_NAME_LENGTH, sanitize_name}, font_loader::FontLoader, sound_loader::SoundLoader};
+use macroquad::{audio::{PlaySoundParams, play_sound}, color::{GRAY, WHITE}, input::{KeyCode, clear_input_queue, get_char_pressed, is_key_pressed, mouse_position}, math::Rect, text::{TextParams, draw_text_ex}};
+
+use crate::recent_servers::RecentServers;
+
+pub const DEFAULT_SERVER_URL: &str = "ws://127.0.0.1:5560";
+
+pub enum ServerSelectResult {
+    Connect {
+        url: String,
+        name: String
+    },
+    Back
+}
+
+/// Which text box the keyboard is typing into. Tab switches
+#[derive(PartialEq, Clone, Copy)]
+enum Field {
+    Name,
+    Address
+}
+
+/// Let people type in "127.0.0.1:5560" instead of the full websocket url
+pub fn normalize_server_url(address: &str) -> String {
+    let address = address.trim();
+
+    if address.starts_with("ws://") || address.starts_with("wss://") {
+        return address.to_string();
+    }
+
+    format!("ws://{}", address)
+}
+
+pub struct ServerSelectUI {
+    name: String,
+    address: String,
+    focused: Field,
+    connect_button: Button,
+    back_button: Button,
+    recent_server_buttons: Vec<(String, Button)>,
+    should_beep_on_button_hover: bool
+}
+
+impl ServerSelectUI {
+    pub fn new(recent_servers: &RecentServers) -> Self {
+
+        let recent_server_buttons = recent_servers.servers
+            .iter()
+            .enumerate()
+            .map(|(index, server)| {
+                (
+                    server.clone(),
+                    Button::new(
+                        Rect::new(
+                            65.,
+                            275. + (index as f32 * 35.),
+                            500.,
+                            30.
+                        ),
+                        None
+                    )
+                )
+            })
+            .collect();
+
+        // pick a name first if we havent got one yet
+        let focused = match recent_servers.name.is_empty() {
+            true => Field::Name,
+            false => Field::Address,
+        };
+
+        Self {
+            name: recent_servers.name.clone(),
+            address: recent_servers.servers.first().cloned().unwrap_or(DEFAULT_SERVER_URL.to_string()),
+            focused,
+            connect_button: Button::new(
+                Rect::new(
+                    65.,
+                    470.,
+                    100.,
+                    40.
+                ),
+                None
+            ),
+            back_button: Button::new(
+                Rect::new(
+                    65.,
+                    520.,
+                    100.,
+                    40.
+                ),
+                None
+            ),
+            recent_server_buttons,
+            should_beep_on_button_hover: true
+        }
+    }
+
+    fn buttons(&self) -> impl Iterator<Item = &Button> {
+        [&self.connect_button, &self.back_button]
+            .into_iter()
+            .chain(self.recent_server_buttons.iter().map(|(_, button)| button))
+    }
+
+    fn edit_fields(&mut self) {
+        if is_key_pressed(KeyCode::Tab) {
+            self.focused = match self.focused {
+                Field::Name => Field::Address,
+                Field::Address => Field::Name,
+            };
+        }
+
+        while let Some(character) = get_char_pressed() {
+            match self.focused {
+                Field::Name => {
+                    if !character.is_control() && self.name.chars().count() < MAX_NAME_LENGTH {
+                        self.name.push(character);
+                    }
+                },
+                Field::Address => {
+                    if character.is_ascii_graphic() {
+                        self.address.push(character);
+                    }
+                },
+            }
+        }
+
+        if is_key_pressed(KeyCode::Backspace) {
+            match self.focused {
+                Field::Name => self.name.pop(),
+                Field::Address => self.address.pop(),
+            };
+        }
+    }
+
+    fn connect(&self, url: String) -> ServerSelectResult {
+        ServerSelectResult::Connect {
+            url,
+            name: sanitize_name(&self.name),
+        }
+    }
+
+    pub fn tick(&mut self, sounds: &SoundLoader) -> Option<ServerSelectResult> {
+
+        self.edit_fields();
+
+        self.connect_button.update(mouse_position().into());
+        self.back_button.update(mouse_position().into());
+
+        for (_, button) in &mut self.recent_server_buttons {
+            button.update(mouse_position().into());
+        }
+
+        let any_hovered = self.buttons().any(|button| button.hovered);
+
+        if any_hovered && self.should_beep_on_button_hover {
+            play_sound(sounds.get(
+                "assets/sounds/menu_navigation_beep.wav".into()),
+                PlaySoundParams {
+                    looped: false,
+                    volume: 0.5,
+                }
+            );
+
+            self.should_beep_on_button_hover = false
+        }
+
+        if !any_hovered {
+            self.should_beep_on_button_hover = true
+        }
+
+        for (server, button) in &self.recent_server_buttons {
+            if button.released {
+                return Some(self.connect(server.clone()));
+            }
+        }
+
+        if (self.connect_button.released || is_key_pressed(KeyCode::Enter)) && !self.address.trim().is_empty() {
+            return Some(self.connect(normalize_server_url(&self.address)));
+        }
+
+        if self.back_button.released || is_key_pressed(KeyCode::Escape) {
+            clear_input_queue();
+            return Some(ServerSelectResult::Back);
+        }
+
+        None
+    }
+
+    fn draw_selection_arrow(button: &Button, fonts: &FontLoader) {
+        if !button.hovered {
+            return;
+        }
+
+        draw_text_ex(
+            ">",
+            button.rect.x - 20.,
+            button.rect.y + button.rect.h - 10.,
+            TextParams {
+                font: Some(&fonts.get("assets/fonts/FuturaHeavy.ttf".into())),
+                font_size: 30,
+                color: WHITE,
+                ..Default::default()
+            }
+        );
+    }
+
+    pub fn draw(&self, fonts: &FontLoader) {
+
+        let font = fonts.get("assets/fonts/FuturaHeavy.ttf".into());
+
+        let fields = [
+            ("name (tab to switch)", &self.name, Field::Name, 140.),
+            ("server address", &self.address, Field::Address, 200.),
+        ];
+
+        for (label, value, field, y) in fields {
+            draw_text_ex(label, 70., y, TextParams {
+                font: Some(&font),
+                font_size: 20,
+                color: GRAY,
+                ..Default::default()
+            });
+
+            // only the box we are typing in gets a cursor
+            let text = match self.focused == field {
+                true => format!("{}_", value),
+                false => value.clone(),
+            };
+
+            draw_text_ex(&text, 70., y + 30., TextParams {
+                font: Some(&font),
+                font_size: 30,
+                color: WHITE,
+                ..Default::default()
+            });
+        }
+
+        if !self.recent_server_buttons.is_empty() {
+            draw_text_ex("recent", 70., 265., TextParams {
+                font: Some(&font),
+                font_size: 20,
+                color: GRAY,
+                ..Default::default()
+            });
+        }
+
+        for (server, button) in &self.recent_server_buttons {
+            Self::draw_selection_arrow(button, fonts);
+
+            draw_text_ex(server, 70., button.rect.y + 22., TextParams {
+                font: Some(&font),
+                font_size: 20,
+                color: WHITE,
+                ..Default::default()
+            });
+        }
+
+        Self::draw_selection_arrow(&self.connect_button, fonts);
+        Self::draw_selection_arrow(&self.back_button, fonts);
+
+        draw_text_ex("connect", 70., 500., TextParams {
+            font: Some(&font),
+            font_size: 30,
+            color: WHITE,
+            ..Default::default()
+        });
+
+        draw_text_ex("back", 70., 550., TextParams {
+            font: Some(&font),
+            font_size: 30,
+            color: WHITE,
+            ..Default::default()
+        });
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::updates::NetworkPacket;
+
+/// Bumped whenever the frame layout or the packet definitions change in a way that old clients can't read
+pub const WIRE_VERSION: u8 = 10;
+
+/// Every websocket frame starts with [WIRE_VERSION, codec tag] followed by the encoded payload
+const HEADER_LENGTH: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PacketCodec {
+    Json,
+    Bitcode
+}
+
+impl Default for PacketCodec {
+    fn default() -> Self {
+        // json is much easier to read when debugging packets, so we keep it around behind a feature flag
+        #[cfg(feature = "json-packets")]
+        return PacketCodec::Json;
+
+        #[cfg(not(feature = "json-packets"))]
+        return PacketCodec::Bitcode;
+    }
+}
+
+impl PacketCodec {
+    pub fn tag(&self) -> u8 {
+        match self {
+            PacketCodec::Json => 0,
+            PacketCodec::Bitcode => 1,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(PacketCodec::Json),
+            1 => Some(PacketCodec::Bitcode),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    FrameTooShort,
+    VersionMismatch(u8),
+    UnknownCodec(u8),
+    Json(serde_json::Error),
+    Bitcode(bitcode::Error)
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecError::FrameTooShort => write!(f, "frame is too short to contain a header"),
+            CodecError::VersionMismatch(version) => write!(f, "frame has wire version {} but we expected {}", version, WIRE_VERSION),
+            CodecError::UnknownCodec(tag) => write!(f, "frame has unknown codec tag {}", tag),
+            CodecError::Json(error) => write!(f, "failed to decode json payload: {}", error),
+            CodecError::Bitcode(error) => write!(f, "failed to decode bitcode payload: {}", error),
+        }
+    }
+}
+
+fn encode_payload<T: Serialize + ?Sized>(codec: PacketCodec, value: &T) -> Vec<u8> {
+
+    let mut frame = vec![WIRE_VERSION, codec.tag()];
+
+    match codec {
+        PacketCodec::Json => {
+            frame.extend_from_slice(serde_json::to_string(value).unwrap().as_bytes());
+        },
+        PacketCodec::Bitcode => {
+            frame.extend_from_slice(&bitcode::serialize(value).unwrap());
+        },
+    }
+
+    frame
+}
+
+fn read_header(frame: &[u8]) -> Result<(PacketCodec, &[u8]), CodecError> {
+    if frame.len() < HEADER_LENGTH {
+        return Err(CodecError::FrameTooShort);
+    }
+
+    if frame[0] != WIRE_VERSION {
+        return Err(CodecError::VersionMismatch(frame[0]));
+    }
+
+    let codec = PacketCodec::from_tag(frame[1]).ok_or(CodecError::UnknownCodec(frame[1]))?;
+
+    Ok((codec, &frame[HEADER_LENGTH..]))
+}
+
+/// Encode a single value (like the client id handshake) into a frame
+pub fn encode_value<T: Serialize>(codec: PacketCodec, value: &T) -> Vec<u8> {
+    encode_payload(codec, value)
+}
+
+/// Decode a single value frame, returning the codec that the sender used
+pub fn decode_value<T: DeserializeOwned>(frame: &[u8]) -> Result<(PacketCodec, T), CodecError> {
+    let (codec, payload) = read_header(frame)?;
+
+    let value = match codec {
+        PacketCodec::Json => serde_json::from_slice(payload).map_err(CodecError::Json)?,
+        PacketCodec::Bitcode => bitcode::deserialize(payload).map_err(CodecError::Bitcode)?,
+    };
+
+    Ok((codec, value))
+}
+
+/// Encode a batch of packets into one or more frames.
+///
+/// Some packets carry typetag trait objects which need a self describing format, so those are split out into json frames.
+/// Runs of packets are kept in their original order so the receiver handles them in the same order they were sent.
+pub fn encode_batch(codec: PacketCodec, packets: &[NetworkPacket]) -> Vec<Vec<u8>> {
+
+    let mut frames = Vec::new();
+
+    if packets.is_empty() {
+        return frames;
+    }
+
+    if codec == PacketCodec::Json {
+        frames.push(encode_payload(PacketCodec::Json, packets));
+
+        return frames;
+    }
+
+    let packet_codec = |packet: &NetworkPacket| {
+        match packet.requires_self_describing() {
+            true => PacketCodec::Json,
+            false => codec,
+        }
+    };
+
+    let mut run_start = 0;
+
+    for index in 1..=packets.len() {
+        let run_codec = packet_codec(&packets[run_start]);
+
+        if index < packets.len() && packet_codec(&packets[index]) == run_codec {
+            continue;
+        }
+
+        frames.push(encode_payload(run_codec, &packets[run_start..index]));
+
+        run_start = index;
+    }
+
+    frames
+}
+
+/// Decode a batch frame, returning the codec that the sender used
+pub fn decode_batch(frame: &[u8]) -> Result<(PacketCodec, Vec<NetworkPacket>), CodecError> {
+    decode_value(frame)
+}
@@ -328,6 +328,7 @@ fn asset_to_prop(relative_path: &PathBuf, scale: Option<f32>) -> Result<String,
         voxels: None,
         rigid_body_type: *body_type,
         removed_voxels: vec![],
+        damage: Default::default(),
         lifespan: None,
         sync_physics: true,
         
@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 use glamx::{Pose2,};
-use macroquad::{color::Color, math::vec2, shapes::{DrawRectangleParams, draw_rectangle_ex}};
+use macroquad::{color::Color, math::vec2, shapes::{DrawRectangleParams, draw_rectangle_ex}, texture::Texture2D};
 use rapier2d::prelude::{ColliderBuilder, ColliderHandle, RigidBodyBuilder, RigidBodyHandle, RigidBodyVelocity};
 use serde::{Deserialize, Serialize};
 
@@ -126,3 +126,87 @@ impl DissolvedPixel {
     }
 }
 
+/// Break a texture up into pixels laid over a body. Every 4th pixel gets averaged with its neighbours so there aren't thousands of them
+pub fn dissolve_texture(
+    texture: &Texture2D,
+    half_extents: glamx::Vec2,
+    mass: f32,
+    pose: Pose2,
+    velocity: RigidBodyVelocity<f32>,
+    space: &mut Space,
+    dissolved_pixels: &mut Vec<DissolvedPixel>
+) {
+    let x_scale = (half_extents.x * 2.) / texture.width() ;
+    let y_scale = (half_extents.y * 2.) / texture.height();
+    let texture_data = texture.get_texture_data();
+    let total_pixel_count = texture.width() * texture.height();
+
+    for x in (0..texture.width() as u32).step_by(4) {
+        for y in (0..texture.height() as u32).step_by(4) {
+            // create an average of the 4 neighboring pixels
+            // start with bottom left
+            let mut color = texture_data.get_pixel(x, y);
+            let mut pixel_count = 1;
+            // bottom right
+            if x + 1 <= texture.width() as u32 {
+                let bottom_right_color = texture_data.get_pixel(x + 1, y);
+
+                color.r += bottom_right_color.r;
+                color.g += bottom_right_color.g;
+                color.b += bottom_right_color.b;
+
+                pixel_count += 1;
+            }
+
+            // top left
+            if y + 1 <= texture.height() as u32 {
+                let top_left_color = texture_data.get_pixel(x, y + 1);
+
+                color.r += top_left_color.r;
+                color.g += top_left_color.g;
+                color.b += top_left_color.b;
+
+                pixel_count += 1;
+            }
+
+            // top right
+            if x + 1 <= texture.width() as u32 && y + 1 <= texture.height() as u32 {
+                let top_right_color = texture_data.get_pixel(x + 1, y + 1);
+
+                color.r += top_right_color.r;
+                color.g += top_right_color.g;
+                color.b += top_right_color.b;
+
+                pixel_count += 1;
+
+            }
+
+            color.r /= pixel_count as f32;
+            color.g /= pixel_count as f32;
+            color.b /= pixel_count as f32;
+
+            let translation = glamx::Vec2::new(
+            ((pose.translation.x + (x as f32 * x_scale)) - half_extents.x) + 2.,
+            ((pose.translation.y - (y as f32 * y_scale)) + half_extents.y) - 2.
+            );
+
+            let position = Pose2::new(
+                translation,
+                pose.rotation.angle()
+            );
+
+
+            dissolved_pixels.push(
+                DissolvedPixel::new(
+                    position,
+                    space,
+                    color,
+                    x_scale,
+                    Some(mass / total_pixel_count),
+                    Some(velocity),
+                )
+            );
+
+        }
+    }
+}
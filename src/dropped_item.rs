@@ -114,6 +114,12 @@ impl DroppedItem {
 
         let preview_size = item.get_preview_resolution(textures, size);
 
+        Self::with_size(item, pos, vel, space, glamx::Vec2::new(preview_size.x, preview_size.y))
+    }
+
+    /// For when there are no textures to work the size out from, like on the server
+    pub fn with_size(item: Box<dyn Item>, pos: Pose2, vel: RigidBodyVelocity<f32>, space: &mut Space, size: glamx::Vec2) -> Self {
+
         let rigid_body = space.rigid_body_set.insert(
             RigidBodyBuilder::dynamic()
                 .pose(pos)
@@ -122,7 +128,7 @@ impl DroppedItem {
         );
 
         let collider = space.collider_set.insert_with_parent(
-            ColliderBuilder::cuboid(preview_size.x, preview_size.y)
+            ColliderBuilder::cuboid(size.x, size.y)
                 .mass(2000.), 
             rigid_body, 
             &mut space.rigid_body_set
@@ -134,7 +140,7 @@ impl DroppedItem {
             collider,
             id: DroppedItemId::new(),
             previous_velocity: RigidBodyVelocity::zero(),
-            size: glamx::Vec2::new(preview_size.x, preview_size.y),
+            size,
             despawn: false
         }
     }
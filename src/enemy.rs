@@ -1,11 +1,23 @@
-use std::{f32::consts::PI, path::PathBuf};
+use std::f32::consts::PI;
 
 use glamx::{Pose2, vec2};
-use macroquad::{color::{BLACK, GREEN}, math::Vec2, shapes::{draw_rectangle, draw_rectangle_lines}};
+use macroquad::{color::{BLACK, GREEN}, math::Vec2, rand::RandomRange, shapes::{draw_rectangle, draw_rectangle_lines}};
 use rapier2d::{parry::query::Ray, prelude::{ColliderHandle, Group, ImpulseJointHandle, InteractionGroups, QueryFilter, RevoluteJointBuilder, RigidBodyVelocity}};
 use serde::{Deserialize, Serialize};
 
-use crate::{ClientTickContext, Owner, TickContext, angle_weapon_to_mouse, area::{self, AreaContext, AreaId}, base_prop::BaseProp, body_part::BodyPart, bullet_trail::BulletTrail, collider_groups::{BODY_PART_GROUP, DETACHED_BODY_PART_GROUP}, dissolved_pixel::DissolvedPixel, drawable::{DrawContext, Drawable}, get_angle_between_rapier_points, items::{Item, item_save::ItemSave}, player::{Facing, Player, PlayerId}, prop::Prop, rapier_to_macroquad, space::Space, updates::NetworkPacket, uuid_u64, weapons::{bullet_impact_data::BulletImpactData, weapon::weapon::WeaponOwner, weapon_fire_context::WeaponFireContext, weapon_type_save::WeaponTypeSave}};
+use crate::{ClientTickContext, Owner, Prefabs, TickContext, angle_weapon_to_mouse, area::{self, AreaContext, AreaId}, base_prop::BaseProp, body_part::BodyPart, bullet_trail::BulletTrail, collider_groups::{BODY_PART_GROUP, DETACHED_BODY_PART_GROUP}, dissolved_pixel::DissolvedPixel, drawable::{DrawContext, Drawable}, dropped_item::{DroppedItem, NewDroppedItemUpdate}, enemy_ai::{AiProfile, AiWorld, Brain, Senses, TargetSense}, enemy_archetype::{DEFAULT_ENEMY_ARCHETYPE, EnemyArchetype}, enemy_death::{EnemyDeath, HitLocation, KillingBlow}, enemy_perception::Perception, get_angle_between_rapier_points, interpolation::{InterpolationBuffer, snapshot_timestamp}, items::{Item, item_save::ItemSave}, nav_graph::{NAV_CELL_SIZE, NavEdgeKind, NavGraph, NavStep}, player::{Facing, Player, PlayerId}, prop::Prop, rapier_to_macroquad, space::Space, updates::NetworkPacket, uuid_u64, weapons::{bullet_impact_data::BulletImpactData, weapon::weapon::WeaponOwner, weapon_fire_context::WeaponFireContext, weapon_type_save::WeaponTypeSave}};
+
+/// Same as the physics
+const GRAVITY: f32 = 998.;
+
+/// Sideways speed once a jump clears whatever it was jumping over
+const JUMP_SPEED: f32 = 250.;
+
+/// Plan again this often in case we got knocked off the path
+const REPLAN_INTERVAL: web_time::Duration = web_time::Duration::from_secs(1);
+
+/// Loot doesn't have a texture on the server so it gets a fixed size
+const LOOT_SIZE: glamx::Vec2 = glamx::Vec2::new(20., 20.);
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub struct EnemyId {
@@ -20,17 +32,11 @@ impl EnemyId {
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum Task {
-    BreakingProps,
-    AttackPlayer,
-    ChasePlayer
-}
-
 pub struct Enemy {
     pub head: BodyPart,
     pub body: BodyPart,
     pub health: i32,
+    pub max_health: i32,
     facing: Facing,
     pub owner: Owner,
     head_body_joint: Option<ImpulseJointHandle>,
@@ -39,15 +45,33 @@ pub struct Enemy {
     pub id: EnemyId,
     pub despawn: bool,
     pub item: Option<Box<dyn Item>>,
-    pub task: Task,
+    /// Path to the archetype in prefabs/enemies
+    pub archetype_path: String,
+    pub archetype: EnemyArchetype,
+    /// Path to the behaviour tree in prefabs/ai
+    pub ai_profile: String,
+    pub brain: Brain,
+    /// Which players we know about and where we last saw or heard them
+    pub perception: Perception,
     pub last_fired_weapon: web_time::Instant,
-    pub last_task_change: web_time::Instant,
     pub previous_velocity: RigidBodyVelocity<f32>,
     pub previous_position: Pose2,
     pub last_position_update: web_time::Instant,
     pub last_velocity_update: web_time::Instant,
     pub last_health_update: web_time::Instant,
     pub death_time: Option<web_time::Instant>,
+    pub killing_blow: Option<KillingBlow>,
+    /// Whoever kills it suggests one, but the server decides and tells everyone in the health update
+    pub death: Option<EnemyDeath>,
+    death_applied: bool,
+    pub interpolation: InterpolationBuffer,
+    /// Steps left to get to where the brain wants to go. Empty if we don't have one and just walk straight there
+    pub path: Vec<NavStep>,
+    /// Which nav graph the path was planned on
+    path_version: u64,
+    /// Where the path goes, so we know to plan again when the brain changes its mind
+    path_goal: Option<glamx::Vec2>,
+    last_path_plan: web_time::Instant,
 
 }
 
@@ -87,7 +111,7 @@ impl Enemy {
             },
         };
 
-        if target_angle.abs() > 0.399 {
+        if target_angle.abs() > self.archetype.neck.limit - 0.001 {
             // dont try to set the angle if we know its beyond the limit
             return;
         }
@@ -183,6 +207,24 @@ impl Enemy {
         area_context: &mut AreaContext 
     ) {
 
+        // the path knows exactly which prop is in the way so aim at that
+        if let Some(step) = self.path.first().copied() && step.kind == NavEdgeKind::BreakThrough {
+
+            let position = area_context.space.rigid_body_set.get(self.body.body_handle).unwrap().position().translation;
+
+            self.facing = match step.position.x < position.x {
+                true => Facing::Left,
+                false => Facing::Right,
+            };
+
+            if let Some(item) = &mut self.item && let Some(weapon) = item.as_weapon_mut() {
+                angle_weapon_to_mouse(area_context.space, weapon, self.body.body_handle, step.position, self.facing);
+            }
+
+            self.fire_weapon(ctx, area_context);
+
+            return;
+        }
     
         let blocking_colliders = self.get_colliders_between_enemy_and_target(
             area_context.space, 
@@ -240,38 +282,72 @@ impl Enemy {
     }
 
 
-    pub fn set_task(&mut self, space: &Space, players: &Vec<Player>, props: &Vec<Box<dyn Prop>>) {
-        
-        if self.last_task_change.elapsed().as_secs_f32() < 0.5 {
-            return;
-        }
+    /// Gather up everything the brain gets to know about
+    pub fn sense(&mut self, space: &Space, players: &Vec<Player>, props: &Vec<Box<dyn Prop>>, enemies: &Vec<Enemy>) -> Senses {
 
-        let colliders = self.get_colliders_between_enemy_and_target(space, players);
+        let position = space.rigid_body_set.get(self.body.body_handle).unwrap().position().translation;
 
-        for prop in props {
-            if colliders.contains(&prop.collider_handle()) {
-                self.task = Task::BreakingProps;
+        // only who we have seen or heard, and only where they were when we did
+        let target = self.perception.target(position)
+            .map(|memory| {
+                TargetSense {
+                    position: memory.last_known_position,
+                    visible: memory.visible,
+                }
+            });
 
-                self.last_task_change = web_time::Instant::now();
+        let path_blocked = match self.path.first() {
+            // the path goes through a prop, shoot it once we are next to it
+            Some(step) => step.kind == NavEdgeKind::BreakThrough && (step.position - position).length() < NAV_CELL_SIZE * 2.,
+            // no path so all we can go off is whether there's a prop between us and them
+            None => {
+                let colliders = self.get_colliders_between_enemy_and_target(space, players);
 
-                return;
-            }
-        }   
+                props.iter().any(|prop| colliders.contains(&prop.collider_handle()))
+            },
+        };
+
+        let ally_positions = enemies.iter()
+            .filter(|enemy| enemy.health > 0 && !enemy.despawn)
+            .map(|enemy| space.rigid_body_set.get(enemy.body.body_handle).unwrap().position().translation)
+            .collect();
+
+        Senses {
+            position,
+            health_fraction: self.health as f32 / self.max_health as f32,
+            target,
+            has_weapon: self.item.as_ref().is_some_and(|item| item.as_weapon().is_some()),
+            path_blocked,
+            ally_positions,
+        }
+    }
 
-        if let Some(distance) = self.distance_to_target(space, players) {
+    /// Run the behaviour tree if it's time. Loads the profile the first time because we need the prefabs for it
+    pub fn think(&mut self, ctx: &TickContext, area_context: &AreaContext) {
 
-            //dbg!(distance.magnitude());
-            if distance.length() < 1000. {
-                self.task = Task::AttackPlayer;
+        if !self.brain.has_profile() {
+            let profile = match ctx.prefabs() {
+                Some(prefabs) => AiProfile::load(prefabs, &self.ai_profile),
+                None => AiProfile::fallback(),
+            };
 
-                return;
-            }
+            self.brain.set_profile(profile);
         }
 
-        
+        if !self.brain.think_due() {
+            return;
+        }
 
-        // default task is to chase player
-        self.task = Task::ChasePlayer;
+        let senses = self.sense(area_context.space, area_context.players, area_context.props, area_context.enemies);
+
+        self.brain.think(
+            &senses,
+            &AiWorld {
+                nav_graph: area_context.nav_graph,
+                space: area_context.space,
+                props: area_context.props,
+            }
+        );
     }
 
 
@@ -279,37 +355,41 @@ impl Enemy {
         position: glamx::Pose2, 
         owner: Owner, 
         space: &mut Space, 
-        weapon: Option<Box<dyn Item>>
+        archetype_path: String,
+        archetype: EnemyArchetype
     ) -> Self {
 
-        let head = BodyPart::new(
-            PathBuf::from("assets/cat/head.png"), 
-            2, 
-            100.,
+        let mut head = BodyPart::new(
+            archetype.head.sprite.clone(), 
+            archetype.head.scale, 
+            archetype.head.mass,
             position, 
             space, 
             owner.clone(),
-            macroquad::math::Vec2::new(30., 28.)
+            macroquad::math::Vec2::new(archetype.head.size.x, archetype.head.size.y)
         );
 
-        let body = BodyPart::new(
-            PathBuf::from("assets/cat/body.png"), 
-            2, 
-            1000.,
+        let mut body = BodyPart::new(
+            archetype.body.sprite.clone(), 
+            archetype.body.scale, 
+            archetype.body.mass,
             position, 
             space, 
             owner.clone(),
-            Vec2::new(22., 19.)
+            Vec2::new(archetype.body.size.x, archetype.body.size.y)
         );
 
+        head.damage_multiplier = archetype.head.damage_multiplier;
+        body.damage_multiplier = archetype.body.damage_multiplier;
+
 
         let head_body_joint = space.impulse_joint_set.insert(
             body.body_handle, 
             head.body_handle, 
             RevoluteJointBuilder::new()
                 .local_anchor1(vec2(0., 0.))
-                .local_anchor2(vec2(0., -30.))
-                .limits([-0.4, 0.4])
+                .local_anchor2(archetype.neck.anchor)
+                .limits([-archetype.neck.limit, archetype.neck.limit])
                 .contacts_enabled(false)
             .build(), 
             true
@@ -318,7 +398,8 @@ impl Enemy {
         let enemy = Self {
             head,
             body,
-            health: 30,
+            health: archetype.health,
+            max_health: archetype.health,
             facing: Facing::Right,
             owner,
             head_body_joint: Some(head_body_joint),
@@ -326,28 +407,63 @@ impl Enemy {
             player_target: None,
             id: EnemyId::new(),
             despawn: false,
-            item: weapon,
-            task: Task::ChasePlayer,
+            item: None,
+            archetype_path,
+            ai_profile: archetype.ai_profile.clone(),
+            archetype,
+            brain: Brain::new(),
+            perception: Perception::new(),
             last_fired_weapon: web_time::Instant::now(),
-            last_task_change: web_time::Instant::now(),
             previous_position: Pose2::default(),
             previous_velocity: RigidBodyVelocity::zero(),
             last_position_update: web_time::Instant::now(),
             last_velocity_update: web_time::Instant::now(),
             last_health_update: web_time::Instant::now(),
-            death_time: None
+            death_time: None,
+            killing_blow: None,
+            death: None,
+            death_applied: false,
+            interpolation: InterpolationBuffer::new(),
+            path: Vec::new(),
+            path_version: 0,
+            path_goal: None,
+            last_path_plan: web_time::Instant::now(),
             
         };
 
         enemy
     }
 
-    pub fn from_save(save: EnemySave, space: &mut Space) -> Self {
+    /// A brand new enemy of the archetype at the path, holding whatever the archetype says it should be
+    pub fn spawn(
+        position: glamx::Pose2, 
+        owner: Owner, 
+        space: &mut Space, 
+        prefabs: &Prefabs,
+        archetype_path: &str
+    ) -> Self {
+
+        let archetype = EnemyArchetype::load(prefabs, archetype_path);
 
-        let mut enemy = Self::new(save.pos, save.owner, space, None);
+        let mut enemy = Self::new(position, owner, space, archetype_path.to_string(), archetype);
+
+        enemy.item = enemy.archetype.loadout.item(&enemy, space);
+
+        enemy
+    }
+
+    pub fn from_save(save: EnemySave, space: &mut Space, prefabs: &Prefabs) -> Self {
+
+        let archetype = EnemyArchetype::load(prefabs, &save.archetype);
+
+        let mut enemy = Self::new(save.pos, save.owner, space, save.archetype, archetype);
         
         enemy.id = save.id;
 
+        if let Some(ai_profile) = save.ai_profile {
+            enemy.ai_profile = ai_profile;
+        }
+
 
         if let Some(weapon_save) = save.weapon {
 
@@ -363,6 +479,8 @@ impl Enemy {
             pos: *space.rigid_body_set.get(self.body.body_handle).unwrap().position(),
             owner: self.owner,
             id: self.id,
+            archetype: self.archetype_path.clone(),
+            ai_profile: (self.ai_profile != self.archetype.ai_profile).then(|| self.ai_profile.clone()),
             weapon: match &self.item {
                 Some(weapon) => Some(weapon.save(space)),
                 None => None,
@@ -380,26 +498,43 @@ impl Enemy {
 
         match bullet_impact.weapon_owner {
             WeaponOwner::Enemy(_enemy_id) => return,
-            WeaponOwner::Player(_player_id) => {},
+            WeaponOwner::Player(player_id) => {
+                // getting shot gives away where they are
+                self.perception.remember(player_id, bullet_impact.shooter_pos, false);
+            },
         }
 
-        // body shot
-        if bullet_impact.impacted_collider == self.body.collider_handle {
+        // go see who did that
+        self.brain.notice(bullet_impact.shooter_pos);
+
+        let (part, location) = match bullet_impact.impacted_collider == self.head.collider_handle {
+            true => (&self.head, HitLocation::Head),
+            false => (&self.body, HitLocation::Body),
+        };
 
-            self.health -= (bullet_impact.damage * 0.5) as i32;
+        let damage = bullet_impact.damage * part.damage_multiplier;
 
-            area_context.space.rigid_body_set.get_mut(self.body.body_handle).unwrap().apply_impulse(bullet_impact.bullet_vector.normalize() * 100000., true);
+        area_context.space.rigid_body_set.get_mut(part.body_handle).unwrap().apply_impulse(bullet_impact.bullet_vector.normalize() * 100000., true);
 
-        }
-        // head shot
-        else if bullet_impact.impacted_collider == self.head.collider_handle {
+        let was_alive = self.health > 0;
+
+        self.health -= damage as i32;
 
-            let damage = bullet_impact.damage as i32;
+        let mut suggested_death = None;
 
-            self.health -= damage;
+        if was_alive && self.health <= 0 {
+            let killing_blow = KillingBlow {
+                damage,
+                location,
+            };
 
-            area_context.space.rigid_body_set.get_mut(self.head.body_handle).unwrap().apply_impulse(bullet_impact.bullet_vector.normalize() * 100000., true);
+            self.killing_blow = Some(killing_blow);
+            suggested_death = Some(EnemyDeath::from_killing_blow(killing_blow));
 
+            // someone else might have killed it at the same time, so clients wait for the server to say which death it was
+            if let TickContext::Server(_) = ctx && self.death.is_none() {
+                self.death = suggested_death;
+            }
         }
 
         ctx.send_network_packet(
@@ -408,18 +543,22 @@ impl Enemy {
                     area_id: *area_context.id,
                     enemy_id: self.id,
                     health: self.health,
+                    death: self.death.or(suggested_death),
                 }
             )
         );
 
-        ctx.send_network_packet(
+        let body = area_context.space.rigid_body_set.get(self.body.body_handle).unwrap();
+
+        ctx.send_network_packet_near(
             NetworkPacket::EnemyVelocityUpdate(
                 EnemyVelocityUpdate {
                     area_id: *area_context.id,
                     enemy_id: self.id,
-                    velocity: *area_context.space.rigid_body_set.get(self.body.body_handle).unwrap().vels(),
+                    velocity: *body.vels(),
                 }
-            )
+            ),
+            body.position().translation
         );
 
         
@@ -469,6 +608,43 @@ impl Enemy {
 
     }
 
+    /// Roll the archetype's loot table and throw whatever comes up out of the body
+    pub fn drop_loot(&mut self, ctx: &mut TickContext, area_context: &mut AreaContext) {
+
+        let body = area_context.space.rigid_body_set.get(self.body.body_handle).unwrap();
+
+        let position = body.translation();
+
+        for drop in &self.archetype.loot {
+
+            if RandomRange::gen_range(0., 1.) >= drop.chance {
+                continue;
+            }
+
+            let velocity = RigidBodyVelocity {
+                linvel: vec2(RandomRange::gen_range(-100., 100.), 300.),
+                angvel: 0.,
+            };
+
+            let dropped_item = DroppedItem::with_size(
+                drop.item.item(),
+                Pose2::new(position + vec2(0., 20.), 0.),
+                velocity,
+                area_context.space,
+                LOOT_SIZE
+            );
+
+            ctx.send_network_packet(
+                NewDroppedItemUpdate {
+                    dropped_item: dropped_item.save(area_context.space),
+                    area_id: *area_context.id,
+                }.into()
+            );
+
+            area_context.dropped_items.push(dropped_item);
+        }
+    }
+
     pub fn fire_weapon(
         &mut self,
         ctx: &mut TickContext,
@@ -490,9 +666,7 @@ impl Enemy {
                     id: &mut self.id,
                     despawn: &mut self.despawn,
                     weapon: &mut None, // this seems indicative of future problems
-                    task: &mut self.task,
                     last_fired_weapon: &mut self.last_fired_weapon,
-                    last_task_change: &mut self.last_task_change,
                     previous_velocity: &mut self.previous_velocity,
                     previous_position: &mut self.previous_position,
                     last_position_update: &mut self.last_position_update,
@@ -517,12 +691,15 @@ impl Enemy {
         area_context: &mut AreaContext 
     ) {
         
-
-        self.set_task(area_context.space, area_context.players, area_context.props);
+        if self.health > 0 {
+            self.think(ctx, area_context);
+        }
 
         if self.health <= 0 {
             if self.death_time.is_none() {
                 self.death_time = Some(web_time::Instant::now());
+
+                self.drop_loot(ctx, area_context);
             }
         }
 
@@ -541,45 +718,43 @@ impl Enemy {
                 }
             );
 
-            ctx.send_network_packet(packet);
+            ctx.send_network_packet_near(packet, pos.translation);
             
         }
 
-        if *pos != self.previous_position && self.last_position_update.elapsed().as_secs_f32() > 3. {
+        // same rate as props. everyone else draws us from these so if they stop coming we just freeze
+        if *pos != self.previous_position && self.last_position_update.elapsed().as_millis() > 16 {
 
             let packet = NetworkPacket::EnemyPositionUpdate(
                 EnemyPositionUpdate {
                     area_id: *area_context.id,
                     enemy_id: self.id,
                     position: *pos,
+                    timestamp: snapshot_timestamp(),
                 }
             );
 
-            ctx.send_network_packet(packet);
-            
+            ctx.send_network_packet_near(packet, pos.translation);
 
+            self.previous_position = *pos;
             self.last_position_update = web_time::Instant::now();
         }
        
 
         if self.health > 0 {
-            match self.task {
-            Task::BreakingProps => {
-                self.break_obstacles(ctx, area_context);
-            },
-            Task::ChasePlayer => {
-                self.follow_target(area_context.space, area_context.players);
-            },
-            Task::AttackPlayer => {
+            let intent = self.brain.intent.clone();
 
+            if intent.break_obstacles {
+                self.break_obstacles(ctx, area_context);
+            }
+            else if intent.attack {
                 self.fire_weapon(ctx, area_context);
+            }
 
-                self.follow_target(area_context.space, area_context.players);
-
-            
+            if let Some(goal) = intent.move_to {
+                self.move_towards(area_context.space, area_context.nav_graph, goal);
             }
         }
-        }
 
         self.despawn_if_dead(ctx, area_context.space, *area_context.id);
         
@@ -631,14 +806,14 @@ impl Enemy {
             return;
         }
 
-        self.detach_head_if_dead(area_context.space);
+        self.apply_death(ctx, area_context.space, area_context.dissolved_pixels);
 
         
 
         if self.health > 0 {
             self.upright(area_context.space);
 
-            self.target_player(area_context.players, area_context.space);
+            self.perceive(area_context);
 
             self.angle_weapon_to_enemy(area_context.space, area_context.players);
             self.change_facing_direction(area_context.space);
@@ -669,64 +844,188 @@ impl Enemy {
 
 
     }
-    pub fn detach_head_if_dead(&mut self, space: &mut Space) {
+    /// Play whichever death was picked the first time we see the enemy dead
+    pub fn apply_death(&mut self, ctx: &TickContext, space: &mut Space, dissolved_pixels: &mut Vec<DissolvedPixel>) {
 
-        let head_joint_handle = match self.head_body_joint {
-            Some(head_joint_handle) => {
-                head_joint_handle
+        if self.health > 0 || self.death_applied {
+            return;
+        }
+
+        // the server hasn't told us how it died yet
+        if let TickContext::Client(_) = ctx && self.death.is_none() {
+            return;
+        }
+
+        self.death_applied = true;
+
+        // dead bodies shouldn't get in anyones way
+        let new_interaction_groups = InteractionGroups::none()
+            .with_memberships(DETACHED_BODY_PART_GROUP)
+            .with_filter(
+                Group::ALL
+                    .difference(DETACHED_BODY_PART_GROUP)
+                    .difference(BODY_PART_GROUP)
+            );
+
+        space.collider_set.get_mut(self.head.collider_handle).unwrap().set_collision_groups(new_interaction_groups);
+        space.collider_set.get_mut(self.body.collider_handle).unwrap().set_collision_groups(new_interaction_groups);
+
+        // nobody landed a killing blow, like if it was hurt some other way
+        let death = *self.death.get_or_insert_default();
+
+        match death {
+            EnemyDeath::Ragdoll => {
+                // let the head flop around instead of holding it up
+                if let Some(head_joint_handle) = self.head_body_joint {
+                    let head_joint = space.impulse_joint_set.get_mut(head_joint_handle, true).unwrap();
+
+                    head_joint.data.as_revolute_mut().unwrap().set_motor_position(0., 0., 0.);
+                }
             },
-            None => {
-                return;
+            EnemyDeath::Decapitation => {
+                self.detach_head(space);
             },
-        };
+            EnemyDeath::Dissolve => {
+                self.detach_head(space);
+
+                match ctx {
+                    TickContext::Client(ctx) => {
+                        self.head.dissolve(ctx.textures, space, dissolved_pixels);
+                        self.body.dissolve(ctx.textures, space, dissolved_pixels);
+                    },
+                    // no textures to make pixels out of
+                    _ => {
+                        self.head.disable(space);
+                        self.body.disable(space);
+                    }
+                }
+            },
+        }
+    }
 
-        if self.health <= 0 {
+    fn detach_head(&mut self, space: &mut Space) {
+        if let Some(head_joint_handle) = self.head_body_joint.take() {
             space.impulse_joint_set.remove(head_joint_handle, true);
+        }
+    }
 
-            self.head_body_joint = None;
+    fn dissolved(&self) -> bool {
+        self.death_applied && self.death == Some(EnemyDeath::Dissolve)
+    }
 
-            let new_interaction_groups = InteractionGroups::none()
-                .with_memberships(DETACHED_BODY_PART_GROUP)
-                .with_filter(
-                    Group::ALL
-                        .difference(DETACHED_BODY_PART_GROUP)
-                        .difference(BODY_PART_GROUP)
-                );
+    pub fn move_towards(&mut self, space: &mut Space, nav_graph: &NavGraph, goal: glamx::Vec2) {
 
-            
-            space.collider_set.get_mut(self.head.collider_handle).unwrap().set_collision_groups(new_interaction_groups);
-            space.collider_set.get_mut(self.body.collider_handle).unwrap().set_collision_groups(new_interaction_groups);
+        self.plan_path(space, nav_graph, goal);
+
+        self.skip_reached_steps(space);
+
+        match self.path.first().copied() {
+            Some(step) => self.follow_path_step(space, step),
+            // nothing to stand on or no way there, just head straight for it
+            None => self.walk_towards(space, goal),
         }
     }
 
-    pub fn follow_target(&mut self, space: &mut Space, players: &Vec<Player>) {
+    /// Plan a new path if the old one is stale, the goal moved or the nav graph changed under it
+    pub fn plan_path(&mut self, space: &Space, nav_graph: &NavGraph, goal: glamx::Vec2) {
 
-        let enemy_velocity = space.rigid_body_set.get(self.body.body_handle).unwrap().linvel().clone();
+        let goal_moved = self.path_goal.is_none_or(|path_goal| (path_goal - goal).length() > NAV_CELL_SIZE);
 
-        if enemy_velocity.y.abs() > 20. {
+        if !goal_moved && self.path_version == nav_graph.version && self.last_path_plan.elapsed() < REPLAN_INTERVAL {
             return;
         }
 
-        if enemy_velocity.x.abs() > 200. {
-            return;
+        let position = space.rigid_body_set.get(self.body.body_handle).unwrap().translation();
+
+        self.path = nav_graph.find_path(position, goal).unwrap_or_default();
+
+        self.path_goal = Some(goal);
+        self.path_version = nav_graph.version;
+        self.last_path_plan = web_time::Instant::now();
+    }
+
+    fn skip_reached_steps(&mut self, space: &Space) {
+
+        let position = space.rigid_body_set.get(self.body.body_handle).unwrap().translation();
+
+        while let Some(step) = self.path.first() {
+
+            let offset = step.position - position;
+
+            // close enough sideways and we are at least as high as it
+            let reached = offset.x.abs() < NAV_CELL_SIZE * 0.4 && offset.y < NAV_CELL_SIZE * 0.6 && offset.y > -NAV_CELL_SIZE * 0.6;
+
+            if !reached {
+                break;
+            }
+
+            self.path.remove(0);
         }
-        
-        let target_player = match &mut self.player_target {
-            Some(target_player_index) => {
+    }
+
+    fn follow_path_step(&mut self, space: &mut Space, step: NavStep) {
+
+        let enemy_body = space.rigid_body_set.get_mut(self.body.body_handle).unwrap();
 
-                let player = players.iter().find(|player| {player.id == *target_player_index}).unwrap();
+        let position = enemy_body.translation();
+        let velocity = enemy_body.linvel();
 
-                player
+        let grounded = velocity.y.abs() < 20.;
 
+        let direction = (step.position.x - position.x).signum();
+
+        match step.kind {
+            NavEdgeKind::Walk | NavEdgeKind::Drop => {
+                if !grounded || velocity.x.abs() > self.archetype.speed {
+                    return;
+                }
+
+                enemy_body.set_linvel(vec2(velocity.x + 10. * direction, velocity.y), true);
             },
-            None => return,
-        };
+            NavEdgeKind::Jump => {
+
+                if grounded && self.last_jump.elapsed().as_secs_f32() > 0.5 {
 
-        let target_player_body_translation = space.rigid_body_set.get(target_player.body.body_handle).unwrap().translation().clone();
+                    // jump high enough to clear the ledge with a bit to spare
+                    let height = (step.position.y - position.y).max(0.) + NAV_CELL_SIZE;
+
+                    enemy_body.set_linvel(vec2(direction * 100., (2. * GRAVITY * height).sqrt() * self.archetype.jump_strength), true);
+
+                    self.last_jump = web_time::Instant::now();
+
+                    return;
+                }
+
+                // only go sideways once we are above the ledge or we just hit the side of it
+                if !grounded && position.y > step.position.y - NAV_CELL_SIZE * 0.5 {
+                    enemy_body.set_linvel(vec2(direction * JUMP_SPEED, velocity.y), true);
+                }
+            },
+            NavEdgeKind::BreakThrough => {
+                // wait for it to get shot out of the way
+                if grounded {
+                    enemy_body.set_linvel(vec2(velocity.x * 0.9, velocity.y), true);
+                }
+            },
+        }
+    }
+
+    /// What enemies did before they could find their way around
+    fn walk_towards(&mut self, space: &mut Space, target_position: glamx::Vec2) {
 
         let enemy_body = space.rigid_body_set.get_mut(self.body.body_handle).unwrap();
 
-        let target_vector = (target_player_body_translation - enemy_body.translation()).normalize();
+        let enemy_velocity = enemy_body.linvel();
+
+        if enemy_velocity.y.abs() > 20. {
+            return;
+        }
+
+        if enemy_velocity.x.abs() > self.archetype.speed {
+            return;
+        }
+
+        let target_vector = (target_position - enemy_body.translation()).normalize();
 
         enemy_body.set_linvel(
             vec2(
@@ -738,41 +1037,27 @@ impl Enemy {
 
     }
 
-    pub fn target_player(&mut self, players: &Vec<Player>, space: &Space) {
-
-        let mut lowest_distance_player: Option<PlayerId> = None;
-        let mut lowest_distance: Option<f32> = None;
-
-        let enemy_body = space.rigid_body_set.get(self.body.body_handle).unwrap();
+    /// Look and listen for players. We only aim at players we can actually see
+    pub fn perceive(&mut self, area_context: &AreaContext) {
 
-        for player in players {
-            let player_body = space.rigid_body_set.get(player.body.body_handle).unwrap();
-
-            let distance = (player_body.translation() - enemy_body.translation()).length();
-
-            if let Some(mut lowest_distance) = lowest_distance {
-                if distance < lowest_distance {
-                    lowest_distance = distance;
-
-                    lowest_distance_player = Some(player.id);
-                }
-            }
-            else {
-                lowest_distance = Some(distance);
-                lowest_distance_player = Some(player.id);
+        let eyes = area_context.space.rigid_body_set.get(self.head.body_handle).unwrap().translation();
 
-            }
-        }
+        let heard = self.perception.update(
+            eyes,
+            self.facing,
+            area_context.space,
+            area_context.props,
+            area_context.players,
+            area_context.noises
+        );
 
-        // dont target players that are over 200 units away
-        if let Some(lowest_distance) = lowest_distance {
-            if lowest_distance > 200. {
-                self.player_target = None
-            }
+        for position in heard {
+            self.brain.notice(position);
         }
 
-        self.player_target = lowest_distance_player;
-
+        self.player_target = self.perception.target(eyes)
+            .filter(|memory| memory.visible)
+            .map(|memory| memory.player_id);
     }
 
     pub fn angle_weapon_to_enemy(&mut self, space: &mut Space, players: &Vec<Player>) {
@@ -818,7 +1103,7 @@ impl Enemy {
                 return
             }
             
-            body.set_linvel(vec2(current_velocity.x, current_velocity.y + 500.), true);
+            body.set_linvel(vec2(current_velocity.x, current_velocity.y + 500. * self.archetype.jump_strength), true);
 
             self.last_jump = web_time::Instant::now();
         }
@@ -849,7 +1134,7 @@ impl Enemy {
 
         draw_rectangle_lines(mpos.x - 29., mpos.y - 64., 58., 18., 6.,BLACK);
 
-        draw_rectangle(mpos.x - 25., mpos.y - 60., 50. * (self.health.max(0) as f32/30.), 10., GREEN);
+        draw_rectangle(mpos.x - 25., mpos.y - 60., 50. * (self.health.max(0) as f32/self.max_health as f32), 10., GREEN);
     }
 
     pub fn draw(&mut self, ctx: &mut TickContext, space: &mut Space) {
@@ -857,6 +1142,11 @@ impl Enemy {
             return;
         }
 
+        // the pixels are all thats left
+        if self.dissolved() {
+            return;
+        }
+
         let flip_x = match self.facing {
             Facing::Right => false,
             Facing::Left => true,
@@ -916,9 +1206,7 @@ pub struct EnemyContext<'a> {
     pub id: &'a mut EnemyId,
     pub despawn: &'a mut bool,
     pub weapon: &'a mut Option<Box<dyn Item>>,
-    pub task: &'a mut Task,
     pub last_fired_weapon: &'a mut web_time::Instant,
-    pub last_task_change: &'a mut web_time::Instant,
     pub previous_velocity: &'a mut RigidBodyVelocity<f32>,
     pub previous_position: &'a mut Pose2,
     pub last_position_update: &'a mut web_time::Instant,
@@ -932,9 +1220,19 @@ pub struct EnemySave {
     pos: Pose2,
     owner: Owner,
     id: EnemyId,
+    /// Path to the archetype in prefabs/enemies
+    #[serde(default = "default_archetype")]
+    archetype: String,
+    /// Only set if it isn't the archetype's usual one
+    #[serde(default)]
+    ai_profile: Option<String>,
     weapon: Option<Box<dyn ItemSave>>
 }
 
+fn default_archetype() -> String {
+    DEFAULT_ENEMY_ARCHETYPE.to_string()
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct NewEnemyUpdate {
     pub area_id: AreaId,
@@ -952,7 +1250,9 @@ pub struct EnemyVelocityUpdate {
 pub struct EnemyPositionUpdate {
     pub area_id: AreaId,
     pub enemy_id: EnemyId,
-    pub position: Pose2
+    pub position: Pose2,
+    /// When the sender took this, see interpolation::snapshot_timestamp
+    pub timestamp: u64
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -973,7 +1273,9 @@ pub struct EnemyDespawnUpdate {
 pub struct EnemyHealthUpdate {
     pub area_id: AreaId,
     pub enemy_id: EnemyId,
-    pub health: i32
+    pub health: i32,
+    /// Set once the enemy is dead so everyone plays the same death
+    pub death: Option<EnemyDeath>
 }
 
 
@@ -0,0 +1,399 @@
+use glamx::Vec2;
+use macroquad::rand::RandomRange;
+use rapier2d::{parry::query::Ray, prelude::QueryFilter};
+use serde::{Deserialize, Serialize};
+
+use crate::{Prefabs, nav_graph::NavGraph, prop::Prop, space::Space};
+
+/// What enemies get if nobody picked anything else
+pub const DEFAULT_AI_PROFILE: &str = "prefabs/ai/grunt.json";
+
+/// Close enough to a patrol point, cover spot or sound to count as being there
+const ARRIVE_DISTANCE: f32 = 40.;
+
+/// How high up an enemy's eyes are from its body
+const EYE_HEIGHT: f32 = 40.;
+
+fn default_think_interval() -> f32 {
+    0.25
+}
+
+/// A behaviour tree and how often to run it. These live in prefabs/ai so new kinds of enemy don't need any code
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AiProfile {
+    /// Seconds between decisions. Lower reacts faster
+    #[serde(default = "default_think_interval")]
+    pub think_interval: f32,
+    pub tree: BehaviourNode
+}
+
+impl AiProfile {
+    pub fn load(prefabs: &Prefabs, path: &str) -> Self {
+        let Some(json) = prefabs.try_get_prefab_data(path) else {
+            log::error!("No AI profile at {}, using the fallback", path);
+
+            return Self::fallback();
+        };
+
+        match serde_json::from_str(&json) {
+            Ok(profile) => profile,
+            Err(error) => {
+                log::error!("Failed to parse AI profile {}: {}", path, error);
+
+                Self::fallback()
+            },
+        }
+    }
+
+    /// Break whatever is in the way, otherwise shoot and chase. What enemies did before profiles existed
+    pub fn fallback() -> Self {
+        Self {
+            think_interval: default_think_interval(),
+            tree: BehaviourNode::Selector {
+                children: vec![
+                    BehaviourNode::Sequence {
+                        children: vec![BehaviourNode::PathBlocked, BehaviourNode::BreakObstacles],
+                    },
+                    BehaviourNode::Sequence {
+                        children: vec![BehaviourNode::TargetWithin { distance: 1000. }, BehaviourNode::Attack, BehaviourNode::Chase],
+                    },
+                    BehaviourNode::Chase,
+                ],
+            },
+        }
+    }
+}
+
+/// Selectors and sequences pick between the other nodes. Conditions pass or fail, actions say what the enemy should do.
+/// The whole tree is run again every think so enemies react as soon as something changes
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BehaviourNode {
+    /// Runs children in order and goes with the first one that passes
+    Selector {
+        children: Vec<BehaviourNode>
+    },
+    /// Runs children in order, all of them have to pass. Actions in a sequence all happen at once
+    Sequence {
+        children: Vec<BehaviourNode>
+    },
+    /// Passes if the child fails
+    Invert {
+        child: Box<BehaviourNode>
+    },
+
+    HasTarget,
+    TargetVisible,
+    TargetWithin {
+        distance: f32
+    },
+    /// Fraction of max health, 0.3 is below 30%
+    HealthBelow {
+        fraction: f32
+    },
+    HasWeapon,
+    /// The path goes through a prop that needs breaking
+    PathBlocked,
+    /// Something happened that we should go look at
+    HasPointOfInterest,
+
+    Chase,
+    Attack,
+    BreakObstacles,
+    /// Wander between random spots around where we spawned
+    Patrol {
+        radius: f32
+    },
+    /// Go somewhere the target can't see us from
+    TakeCover {
+        search_radius: f32
+    },
+    /// Come at the target from the opposite side to everyone else
+    Flank {
+        distance: f32
+    },
+    /// Back away from the target
+    Retreat {
+        distance: f32
+    },
+    /// Go to the point of interest and look around
+    Investigate,
+    Idle
+}
+
+/// What the enemy knows when it makes a decision
+pub struct Senses {
+    pub position: Vec2,
+    pub health_fraction: f32,
+    pub target: Option<TargetSense>,
+    pub has_weapon: bool,
+    pub path_blocked: bool,
+    /// Other enemies, so flankers can go round the other side
+    pub ally_positions: Vec<Vec2>
+}
+
+pub struct TargetSense {
+    pub position: Vec2,
+    pub visible: bool
+}
+
+/// The bits of the area behaviours need to look at
+pub struct AiWorld<'a> {
+    pub nav_graph: &'a NavGraph,
+    pub space: &'a Space,
+    pub props: &'a Vec<Box<dyn Prop>>
+}
+
+/// What the tree decided. The enemy carries it out every tick until the next think
+#[derive(Debug, Clone, Default)]
+pub struct Intent {
+    pub move_to: Option<Vec2>,
+    pub attack: bool,
+    pub break_obstacles: bool
+}
+
+/// One per enemy. Holds the tree and whatever the behaviours need to remember between thinks
+pub struct Brain {
+    profile: Option<AiProfile>,
+    pub intent: Intent,
+    last_think: web_time::Instant,
+    /// Where we spawned, patrols stay around here
+    home: Option<Vec2>,
+    patrol_point: Option<Vec2>,
+    cover_point: Option<Vec2>,
+    point_of_interest: Option<Vec2>
+}
+
+impl Brain {
+    pub fn new() -> Self {
+        Self {
+            profile: None,
+            intent: Intent::default(),
+            last_think: web_time::Instant::now(),
+            home: None,
+            patrol_point: None,
+            cover_point: None,
+            point_of_interest: None,
+        }
+    }
+
+    pub fn has_profile(&self) -> bool {
+        self.profile.is_some()
+    }
+
+    pub fn set_profile(&mut self, profile: AiProfile) {
+        self.profile = Some(profile);
+    }
+
+    /// Something worth checking out happened here, like getting shot from this direction
+    pub fn notice(&mut self, position: Vec2) {
+        self.point_of_interest = Some(position);
+    }
+
+    pub fn think_due(&self) -> bool {
+        let interval = self.profile.as_ref().map_or(default_think_interval(), |profile| profile.think_interval);
+
+        self.last_think.elapsed().as_secs_f32() >= interval
+    }
+
+    pub fn think(&mut self, senses: &Senses, world: &AiWorld) {
+
+        self.last_think = web_time::Instant::now();
+
+        self.home.get_or_insert(senses.position);
+
+        // taking the tree out so the nodes can change the rest of the brain
+        let Some(profile) = self.profile.take() else {
+            return;
+        };
+
+        let mut intent = Intent::default();
+
+        profile.tree.evaluate(self, senses, world, &mut intent);
+
+        self.intent = intent;
+        self.profile = Some(profile);
+    }
+}
+
+impl BehaviourNode {
+    fn evaluate(&self, brain: &mut Brain, senses: &Senses, world: &AiWorld, intent: &mut Intent) -> bool {
+        match self {
+            BehaviourNode::Selector { children } => {
+                for child in children {
+                    let mut attempt = intent.clone();
+
+                    if child.evaluate(brain, senses, world, &mut attempt) {
+                        *intent = attempt;
+
+                        return true;
+                    }
+                }
+
+                false
+            },
+            BehaviourNode::Sequence { children } => {
+                // a sequence that fails half way shouldn't leave half its actions behind
+                let mut attempt = intent.clone();
+
+                for child in children {
+                    if !child.evaluate(brain, senses, world, &mut attempt) {
+                        return false;
+                    }
+                }
+
+                *intent = attempt;
+
+                true
+            },
+            BehaviourNode::Invert { child } => {
+                !child.evaluate(brain, senses, world, &mut intent.clone())
+            },
+
+            BehaviourNode::HasTarget => senses.target.is_some(),
+            BehaviourNode::TargetVisible => senses.target.as_ref().is_some_and(|target| target.visible),
+            BehaviourNode::TargetWithin { distance } => {
+                senses.target.as_ref().is_some_and(|target| (target.position - senses.position).length() <= *distance)
+            },
+            BehaviourNode::HealthBelow { fraction } => senses.health_fraction < *fraction,
+            BehaviourNode::HasWeapon => senses.has_weapon,
+            BehaviourNode::PathBlocked => senses.path_blocked,
+            BehaviourNode::HasPointOfInterest => brain.point_of_interest.is_some(),
+
+            BehaviourNode::Chase => {
+                let Some(target) = &senses.target else {
+                    return false;
+                };
+
+                intent.move_to = Some(target.position);
+
+                true
+            },
+            BehaviourNode::Attack => {
+                let can_attack = senses.has_weapon && senses.target.as_ref().is_some_and(|target| target.visible);
+
+                intent.attack |= can_attack;
+
+                can_attack
+            },
+            BehaviourNode::BreakObstacles => {
+                intent.break_obstacles |= senses.path_blocked;
+
+                senses.path_blocked
+            },
+            BehaviourNode::Patrol { radius } => {
+                let reached = brain.patrol_point.is_none_or(|point| (point - senses.position).length() < ARRIVE_DISTANCE);
+
+                if reached {
+                    let home = brain.home.unwrap_or(senses.position);
+
+                    let spots = world.nav_graph.positions_near(home, *radius);
+
+                    brain.patrol_point = match spots.is_empty() {
+                        true => None,
+                        false => Some(spots[RandomRange::gen_range(0, spots.len())]),
+                    };
+                }
+
+                intent.move_to = brain.patrol_point;
+
+                brain.patrol_point.is_some()
+            },
+            BehaviourNode::TakeCover { search_radius } => {
+                let Some(target) = &senses.target else {
+                    return false;
+                };
+
+                let still_covered = brain.cover_point.is_some_and(|point| sight_blocked(world.space, world.props, target.position, point + Vec2::new(0., EYE_HEIGHT)));
+
+                if !still_covered {
+                    brain.cover_point = world.nav_graph.positions_near(senses.position, *search_radius)
+                        .into_iter()
+                        .filter(|spot| sight_blocked(world.space, world.props, target.position, *spot + Vec2::new(0., EYE_HEIGHT)))
+                        .min_by(|a, b| (*a - senses.position).length().total_cmp(&(*b - senses.position).length()));
+                }
+
+                intent.move_to = brain.cover_point;
+
+                brain.cover_point.is_some()
+            },
+            BehaviourNode::Flank { distance } => {
+                let Some(target) = &senses.target else {
+                    return false;
+                };
+
+                // go round whichever side everyone else isn't on
+                let crowd_x = match senses.ally_positions.is_empty() {
+                    true => senses.position.x,
+                    false => senses.ally_positions.iter().map(|position| position.x).sum::<f32>() / senses.ally_positions.len() as f32,
+                };
+
+                let side = match crowd_x < target.position.x {
+                    true => 1.,
+                    false => -1.,
+                };
+
+                intent.move_to = Some(Vec2::new(target.position.x + side * distance, target.position.y));
+
+                true
+            },
+            BehaviourNode::Retreat { distance } => {
+                let Some(target) = &senses.target else {
+                    return false;
+                };
+
+                let away = match senses.position.x < target.position.x {
+                    true => -1.,
+                    false => 1.,
+                };
+
+                intent.move_to = Some(Vec2::new(senses.position.x + away * distance, senses.position.y));
+
+                true
+            },
+            BehaviourNode::Investigate => {
+                let Some(point) = brain.point_of_interest else {
+                    return false;
+                };
+
+                // nothing here
+                if (point - senses.position).length() < ARRIVE_DISTANCE * 2. {
+                    brain.point_of_interest = None;
+
+                    return false;
+                }
+
+                intent.move_to = Some(point);
+
+                true
+            },
+            BehaviourNode::Idle => true,
+        }
+    }
+}
+
+/// True if a wall, clip or prop is between the two points. Bodies and weapons don't count
+pub fn sight_blocked(space: &Space, props: &Vec<Box<dyn Prop>>, from: Vec2, to: Vec2) -> bool {
+
+    let query_pipeline = space.broad_phase.as_query_pipeline(
+        space.narrow_phase.query_dispatcher(),
+        &space.rigid_body_set,
+        &space.collider_set,
+        QueryFilter::default()
+    );
+
+    let ray = Ray::new(from, to - from);
+
+    for (collider_handle, collider, _) in query_pipeline.intersect_ray(ray, 1., true) {
+
+        let fixed = collider.parent()
+            .and_then(|parent| space.rigid_body_set.get(parent))
+            .is_some_and(|body| body.is_fixed());
+
+        if fixed || props.iter().any(|prop| prop.collider_handle() == collider_handle) {
+            return true;
+        }
+    }
+
+    false
+}
@@ -0,0 +1,162 @@
+use std::path::PathBuf;
+
+use glamx::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::{Prefabs, enemy::Enemy, enemy_ai::DEFAULT_AI_PROFILE, items::{Item, prop::prop_item::SimplePropItem}, player::Facing, space::Space, weapons::{lmg::weapon::LMG, smg::weapon::SMG, weapon::weapon::WeaponOwner}};
+
+/// What gets spawned if nobody picked anything else
+pub const DEFAULT_ENEMY_ARCHETYPE: &str = "prefabs/enemies/cat.json";
+
+fn default_speed() -> f32 {
+    200.
+}
+
+fn default_jump_strength() -> f32 {
+    1.
+}
+
+fn default_damage_multiplier() -> f32 {
+    1.
+}
+
+fn default_ai_profile() -> String {
+    DEFAULT_AI_PROFILE.to_string()
+}
+
+/// A kind of enemy. These live in prefabs/enemies so adding a new one is just a json file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EnemyArchetype {
+    pub head: BodyPartDefinition,
+    pub body: BodyPartDefinition,
+    pub neck: NeckDefinition,
+    pub health: i32,
+    /// Fastest it will walk itself
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// 1 jumps just high enough to clear a ledge, more than that overshoots it
+    #[serde(default = "default_jump_strength")]
+    pub jump_strength: f32,
+    #[serde(default)]
+    pub loadout: EnemyLoadout,
+    /// Path to a behaviour tree in prefabs/ai
+    #[serde(default = "default_ai_profile")]
+    pub ai_profile: String,
+    #[serde(default)]
+    pub loot: Vec<LootDrop>
+}
+
+impl EnemyArchetype {
+    pub fn load(prefabs: &Prefabs, path: &str) -> Self {
+        let Some(json) = prefabs.try_get_prefab_data(path) else {
+            log::error!("No enemy archetype at {}, using the fallback", path);
+
+            return Self::fallback();
+        };
+
+        match serde_json::from_str(&json) {
+            Ok(archetype) => archetype,
+            Err(error) => {
+                log::error!("Failed to parse enemy archetype {}: {}", path, error);
+
+                Self::fallback()
+            },
+        }
+    }
+
+    /// The cat, the way it was before archetypes existed
+    pub fn fallback() -> Self {
+        Self {
+            head: BodyPartDefinition {
+                sprite: PathBuf::from("assets/cat/head.png"),
+                size: Vec2::new(30., 28.),
+                scale: 2,
+                mass: 100.,
+                damage_multiplier: 1.,
+            },
+            body: BodyPartDefinition {
+                sprite: PathBuf::from("assets/cat/body.png"),
+                size: Vec2::new(22., 19.),
+                scale: 2,
+                mass: 1000.,
+                damage_multiplier: 0.5,
+            },
+            neck: NeckDefinition {
+                anchor: Vec2::new(0., -30.),
+                limit: 0.4,
+            },
+            health: 30,
+            speed: default_speed(),
+            jump_strength: default_jump_strength(),
+            loadout: EnemyLoadout::default(),
+            ai_profile: default_ai_profile(),
+            loot: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BodyPartDefinition {
+    pub sprite: PathBuf,
+    /// Size of the sprite in pixels before it gets scaled
+    pub size: Vec2,
+    pub scale: u16,
+    pub mass: f32,
+    /// How much of a bullet's damage this part takes
+    #[serde(default = "default_damage_multiplier")]
+    pub damage_multiplier: f32
+}
+
+/// How the head is attached to the body
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NeckDefinition {
+    /// Where the joint is on the head, relative to the middle of it
+    pub anchor: Vec2,
+    /// How far the head can turn either way in radians
+    pub limit: f32
+}
+
+/// What the enemy is holding when it spawns
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum EnemyLoadout {
+    #[default]
+    Unarmed,
+    SMG,
+    LMG
+}
+
+impl EnemyLoadout {
+    pub fn item(&self, enemy: &Enemy, space: &mut Space) -> Option<Box<dyn Item>> {
+
+        let owner = WeaponOwner::Enemy(enemy.id);
+        let body = Some(enemy.body.body_handle);
+
+        match self {
+            EnemyLoadout::Unarmed => None,
+            EnemyLoadout::SMG => Some(Box::new(SMG::new(owner, body, Facing::Right))),
+            EnemyLoadout::LMG => Some(Box::new(LMG::new(space, Vec2::ZERO, owner, body, Facing::Right))),
+        }
+    }
+}
+
+/// Something that might fall out of the enemy when it dies
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct LootDrop {
+    pub item: LootItem,
+    /// 0 never drops, 1 always does
+    pub chance: f32
+}
+
+/// Weapons are tied to whoever is holding them so only simple items can be loot for now
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum LootItem {
+    WoodenBox
+}
+
+impl LootItem {
+    pub fn item(&self) -> Box<dyn Item> {
+        match self {
+            LootItem::WoodenBox => Box::new(SimplePropItem::WoodenBox),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+/// A killing blow at least this big turns the enemy to dust instead of just knocking it over
+const DISSOLVE_DAMAGE: f32 = 50.;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum HitLocation {
+    Head,
+    Body
+}
+
+/// The hit that took the enemy's health to 0
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct KillingBlow {
+    /// After the body part's multiplier
+    pub damage: f32,
+    pub location: HitLocation
+}
+
+/// How the enemy goes down. Whoever lands the killing blow suggests one from it, then the server picks the first it hears about and sends it to everyone so they all see the same thing
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum EnemyDeath {
+    /// Goes limp and falls over
+    #[default]
+    Ragdoll,
+    /// Head comes off
+    Decapitation,
+    /// Falls apart into pixels
+    Dissolve
+}
+
+impl EnemyDeath {
+    pub fn from_killing_blow(killing_blow: KillingBlow) -> Self {
+        if killing_blow.damage >= DISSOLVE_DAMAGE {
+            return EnemyDeath::Dissolve;
+        }
+
+        match killing_blow.location {
+            HitLocation::Head => EnemyDeath::Decapitation,
+            HitLocation::Body => EnemyDeath::Ragdoll,
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::f32::consts::PI;
+
+use glamx::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::{TickContext, area::AreaId, enemy_ai::sight_blocked, player::{Facing, Player, PlayerId}, prop::Prop, space::Space};
+
+/// How far enemies can see
+const VISION_RANGE: f32 = 1200.;
+
+/// Half the width of the vision cone, so 70 degrees either side of straight ahead
+const VISION_HALF_ANGLE: f32 = PI * 70. / 180.;
+
+/// Anyone this close gets noticed even if they are behind us
+const PERIPHERAL_RANGE: f32 = 150.;
+
+/// A player we haven't seen or heard for this long is forgotten
+const MEMORY_DURATION: web_time::Duration = web_time::Duration::from_secs(10);
+
+/// Getting to where someone was last seen and not finding them there counts as losing them
+const SEARCH_ARRIVE_DISTANCE: f32 = 80.;
+
+/// Sounds this close to the enemy are probably its own gun
+const SELF_NOISE_DISTANCE: f32 = 100.;
+
+/// Noises are only around long enough for every enemy to get a tick in
+const NOISE_LIFETIME: web_time::Duration = web_time::Duration::from_secs(1);
+
+/// Stops a client telling every enemy in the area where it is
+const MAX_NOISE_RADIUS: f32 = 3000.;
+
+pub const GUNFIRE_NOISE_RADIUS: f32 = 1500.;
+
+pub const PROP_BREAK_NOISE_RADIUS: f32 = 600.;
+
+fn now() -> web_time::Instant {
+    web_time::Instant::now()
+}
+
+/// Something loud happened. Enemies within the radius hear it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Noise {
+    pub position: Vec2,
+    pub radius: f32,
+    /// Set if the noise gives away where a player is, like them shooting
+    pub player: Option<PlayerId>,
+    /// When we found out about it, not when it happened
+    #[serde(skip, default = "now")]
+    pub at: web_time::Instant
+}
+
+impl Noise {
+    pub fn new(position: Vec2, radius: f32, player: Option<PlayerId>) -> Self {
+        Self {
+            position,
+            radius: radius.min(MAX_NOISE_RADIUS),
+            player,
+            at: now(),
+        }
+    }
+
+    pub fn expired(&self) -> bool {
+        self.at.elapsed() > NOISE_LIFETIME
+    }
+}
+
+/// Clients send this so the server's enemies can hear what happens on them
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct NoiseUpdate {
+    pub area_id: AreaId,
+    pub noise: Noise
+}
+
+/// Let the enemies in the area hear something. Clients also pass it on to the server because that's where the enemies usually live
+pub fn make_noise(ctx: &mut TickContext, noises: &mut Vec<Noise>, area_id: AreaId, noise: Noise) {
+
+    noises.push(noise);
+
+    if let TickContext::Client(ctx) = ctx {
+        ctx.network_io.send_network_packet(
+            NoiseUpdate {
+                area_id,
+                noise,
+            }.into()
+        );
+    }
+}
+
+/// What an enemy remembers about a player
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerMemory {
+    pub player_id: PlayerId,
+    pub last_known_position: Vec2,
+    pub visible: bool,
+    last_noticed: web_time::Instant
+}
+
+/// One per enemy. Enemies only know about players they have seen or heard, and only for a while
+pub struct Perception {
+    memories: Vec<PlayerMemory>,
+    last_listen: web_time::Instant
+}
+
+impl Perception {
+    pub fn new() -> Self {
+        Self {
+            memories: Vec::new(),
+            last_listen: now(),
+        }
+    }
+
+    /// Look around, listen and forget. Returns where any noises that didn't give a player away came from
+    pub fn update(
+        &mut self,
+        eyes: Vec2,
+        facing: Facing,
+        space: &Space,
+        props: &Vec<Box<dyn Prop>>,
+        players: &Vec<Player>,
+        noises: &Vec<Noise>
+    ) -> Vec<Vec2> {
+
+        for memory in &mut self.memories {
+            memory.visible = false;
+        }
+
+        for player in players {
+
+            if player.despawn || player.health <= 0 {
+                continue;
+            }
+
+            let position = space.rigid_body_set.get(player.body.body_handle).unwrap().translation();
+            let head_position = space.rigid_body_set.get(player.head.body_handle).unwrap().translation();
+
+            // either bit of them poking out is enough
+            let seen = can_see(space, props, eyes, facing, position) || can_see(space, props, eyes, facing, head_position);
+
+            if seen {
+                self.remember(player.id, position, true);
+            }
+        }
+
+        let mut heard = Vec::new();
+
+        for noise in noises {
+
+            if noise.at <= self.last_listen {
+                continue;
+            }
+
+            let distance = (noise.position - eyes).length();
+
+            if distance > noise.radius || distance < SELF_NOISE_DISTANCE {
+                continue;
+            }
+
+            match noise.player {
+                Some(player_id) => self.remember(player_id, noise.position, false),
+                None => heard.push(noise.position),
+            }
+        }
+
+        self.last_listen = now();
+
+        self.memories.retain(|memory| {
+
+            let still_here = players.iter().any(|player| player.id == memory.player_id && !player.despawn);
+
+            // we went to where they were and they aren't there anymore
+            let lost = !memory.visible && (memory.last_known_position - eyes).length() < SEARCH_ARRIVE_DISTANCE;
+
+            still_here && !lost && memory.last_noticed.elapsed() < MEMORY_DURATION
+        });
+
+        heard
+    }
+
+    /// Someone gave themselves away here, like by shooting us
+    pub fn remember(&mut self, player_id: PlayerId, position: Vec2, visible: bool) {
+
+        let memory = PlayerMemory {
+            player_id,
+            last_known_position: position,
+            visible,
+            last_noticed: now(),
+        };
+
+        match self.memories.iter_mut().find(|memory| memory.player_id == player_id) {
+            Some(existing) => {
+                // hearing them shouldn't make us forget we can see them
+                let visible = existing.visible || visible;
+
+                *existing = memory;
+                existing.visible = visible;
+            },
+            None => self.memories.push(memory),
+        }
+    }
+
+    /// Who to go after. Anyone we can see beats anyone we only remember, then whoever is closest
+    pub fn target(&self, eyes: Vec2) -> Option<&PlayerMemory> {
+        self.memories.iter()
+            .min_by(|a, b| {
+                b.visible.cmp(&a.visible)
+                    .then((a.last_known_position - eyes).length().total_cmp(&(b.last_known_position - eyes).length()))
+            })
+    }
+}
+
+/// In range, inside the vision cone and nothing solid in the way
+pub fn can_see(space: &Space, props: &Vec<Box<dyn Prop>>, eyes: Vec2, facing: Facing, target: Vec2) -> bool {
+
+    let offset = target - eyes;
+
+    let distance = offset.length();
+
+    if distance > VISION_RANGE {
+        return false;
+    }
+
+    if distance > PERIPHERAL_RANGE {
+
+        let forward = match facing {
+            Facing::Right => Vec2::X,
+            Facing::Left => Vec2::NEG_X,
+        };
+
+        if forward.dot(offset / distance) < VISION_HALF_ANGLE.cos() {
+            return false;
+        }
+    }
+
+    !sight_blocked(space, props, eyes, target)
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+/// Server and client need to agree on this for prediction to line up, so the server tells clients what it is using
+pub const DEFAULT_TICK_RATE: u32 = 125;
+
+/// If we fall further behind than this we drop the time instead of trying to catch up, otherwise a slow tick makes the next one even slower
+pub const DEFAULT_MAX_CATCH_UP_STEPS: u32 = 5;
+
+/// Sent when a client connects and whenever the server's tick rate changes
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct TickRateUpdate {
+    pub tick_rate: u32
+}
+
+/// Turns however much real time has passed into a whole number of fixed size simulation steps
+pub struct FixedTimestep {
+    step: web_time::Duration,
+    max_catch_up_steps: u32,
+    accumulator: web_time::Duration,
+    last_update: web_time::Instant
+}
+
+impl FixedTimestep {
+    pub fn new(tick_rate: u32, max_catch_up_steps: u32) -> Self {
+        Self {
+            step: web_time::Duration::from_secs_f64(1. / tick_rate.max(1) as f64),
+            max_catch_up_steps: max_catch_up_steps.max(1),
+            accumulator: web_time::Duration::ZERO,
+            last_update: web_time::Instant::now(),
+        }
+    }
+
+    /// How long each simulation step is
+    pub fn step(&self) -> web_time::Duration {
+        self.step
+    }
+
+    pub fn tick_rate(&self) -> u32 {
+        (1. / self.step.as_secs_f64()).round() as u32
+    }
+
+    /// Change the step size without losing the time we already have banked
+    pub fn set_tick_rate(&mut self, tick_rate: u32) {
+        self.step = web_time::Duration::from_secs_f64(1. / tick_rate.max(1) as f64);
+    }
+
+    /// Add the time since the last call and return how many steps we need to run now
+    pub fn advance(&mut self) -> u32 {
+        let now = web_time::Instant::now();
+
+        self.accumulator += now - self.last_update;
+        self.last_update = now;
+
+        let mut steps = 0;
+
+        while self.accumulator >= self.step {
+            if steps == self.max_catch_up_steps {
+                log::debug!("Simulation fell {:?} behind, dropping it", self.accumulator);
+
+                self.accumulator = web_time::Duration::ZERO;
+
+                break;
+            }
+
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        steps
+    }
+
+    /// How far we are between the last step and the next one, for drawing things in between
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f32() / self.step.as_secs_f32()).clamp(0., 1.)
+    }
+}
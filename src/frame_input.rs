@@ -0,0 +1,50 @@
+use std::collections::HashSet;
+
+use macroquad::input::{KeyCode, MouseButton, get_keys_released, is_mouse_button_released, mouse_wheel};
+
+const MOUSE_BUTTONS: [MouseButton; 3] = [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+
+/// Key and mouse releases and scrolling that happened since the last fixed step ran.
+/// Macroquad only reports these for the frame they happened on, so reading them inside the fixed step would miss them on frames with no steps and repeat them on frames with several
+#[derive(Default, Clone, Debug)]
+pub struct FrameInput {
+    keys_released: HashSet<KeyCode>,
+    mouse_buttons_released: HashSet<MouseButton>,
+    mouse_wheel: f32
+}
+
+impl FrameInput {
+
+    /// Add this frame's input on top of whatever is still waiting for a step
+    pub fn sample(&mut self) {
+        self.keys_released.extend(get_keys_released());
+
+        for button in MOUSE_BUTTONS {
+            if is_mouse_button_released(button) {
+                self.mouse_buttons_released.insert(button);
+            }
+        }
+
+        self.mouse_wheel += mouse_wheel().1;
+    }
+
+    /// A step used these up
+    pub fn clear(&mut self) {
+        self.keys_released.clear();
+        self.mouse_buttons_released.clear();
+        self.mouse_wheel = 0.;
+    }
+
+    pub fn is_key_released(&self, key: KeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    pub fn is_mouse_button_released(&self, button: MouseButton) -> bool {
+        self.mouse_buttons_released.contains(&button)
+    }
+
+    /// Vertical scroll, same sign as macroquad's mouse_wheel
+    pub fn mouse_wheel(&self) -> f32 {
+        self.mouse_wheel
+    }
+}
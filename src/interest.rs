@@ -0,0 +1,61 @@
+use glamx::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::area::AreaId;
+
+/// How far outside of a client's camera we still send them updates.
+/// Needs to be generous so things don't pop in when they walk into view
+pub const RELEVANCE_MARGIN: f32 = 1500.;
+
+/// What a client can currently see, in rapier coords
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ClientView {
+    pub center: Vec2,
+    pub half_extents: Vec2
+}
+
+impl ClientView {
+    pub fn is_relevant(&self, position: Vec2) -> bool {
+        let distance = (position - self.center).abs();
+
+        distance.x <= self.half_extents.x + RELEVANCE_MARGIN
+            && distance.y <= self.half_extents.y + RELEVANCE_MARGIN
+    }
+}
+
+/// The client tells us where its camera is so we can skip updates it won't see
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ClientViewUpdate {
+    pub view: ClientView
+}
+
+/// Which area a packet belongs to and where it happened, if it matters
+#[derive(Default, Clone, Copy)]
+pub struct PacketRelevance {
+    pub area_id: Option<AreaId>,
+    pub position: Option<Vec2>
+}
+
+/// Everything the server knows about what a client cares about
+#[derive(Default, Clone, Copy)]
+pub struct ClientInterest {
+    pub area_id: Option<AreaId>,
+    pub view: Option<ClientView>
+}
+
+impl ClientInterest {
+    pub fn is_interested(&self, relevance: &PacketRelevance) -> bool {
+
+        // clients dont have other areas loaded so they would just crash trying to apply these
+        if let (Some(packet_area), Some(client_area)) = (relevance.area_id, self.area_id) {
+            if packet_area != client_area {
+                return false;
+            }
+        }
+
+        match (relevance.position, self.view) {
+            (Some(position), Some(view)) => view.is_relevant(position),
+            _ => true
+        }
+    }
+}
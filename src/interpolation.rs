@@ -0,0 +1,240 @@
+use std::{collections::VecDeque, sync::LazyLock};
+
+use glamx::{Pose2, Vec2};
+use rapier2d::prelude::RigidBody;
+
+/// Don't let the buffer grow forever if we stop sampling it (like when the entity becomes ours)
+const MAX_BUFFERED_SNAPSHOTS: usize = 32;
+
+/// Timestamps going back this far can't be from the same sender, so the entity must have changed owner
+const NEW_SENDER_THRESHOLD_MS: u64 = 1000;
+
+static CLOCK_START: LazyLock<web_time::Instant> = LazyLock::new(web_time::Instant::now);
+
+/// Milliseconds on our own clock. Goes in position updates so whoever receives them knows how far apart they were taken,
+/// which is more reliable than when they happened to arrive. Only ever compared with other timestamps from the same sender
+pub fn snapshot_timestamp() -> u64 {
+    CLOCK_START.elapsed().as_millis() as u64
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct InterpolationSettings {
+    /// How far in the past remote entities are rendered. Needs to be bigger than the gap between updates
+    pub delay: web_time::Duration,
+    /// How long we keep guessing where an entity is after we run out of updates
+    pub max_extrapolation: web_time::Duration,
+    /// If the entity is this far from where it should be we just teleport it
+    pub snap_distance: f32
+}
+
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        Self {
+            delay: web_time::Duration::from_millis(100),
+            max_extrapolation: web_time::Duration::from_millis(250),
+            snap_distance: 200.,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Snapshot {
+    /// Where this sits on our timeline, going by the sender's timestamps
+    time: web_time::Instant,
+    timestamp: u64,
+    pos: Pose2
+}
+
+/// Sampled position for a remote entity
+pub struct InterpolatedPose {
+    pub pos: Pose2,
+    pub linvel: Vec2
+}
+
+/// Timestamped position updates for an entity we dont own
+#[derive(Clone, Debug)]
+pub struct InterpolationBuffer {
+    snapshots: VecDeque<Snapshot>,
+    /// A local time and the sender timestamp we line up with it
+    sync: Option<(web_time::Instant, u64)>
+}
+
+impl InterpolationBuffer {
+    pub fn new() -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            sync: None,
+        }
+    }
+
+    pub fn push(&mut self, pos: Pose2, timestamp: u64, settings: &InterpolationSettings) {
+
+        if let Some(latest) = self.snapshots.back() {
+
+            let teleported = (latest.pos.translation - pos.translation).length() > settings.snap_distance;
+            let new_sender = timestamp + NEW_SENDER_THRESHOLD_MS < latest.timestamp;
+
+            // dont smoothly slide it across the map, or try to line up someone else's clock with the old one
+            if teleported || new_sender {
+                self.clear();
+            }
+            // arrived late or twice
+            else if timestamp <= latest.timestamp {
+                return;
+            }
+        }
+
+        let now = web_time::Instant::now();
+
+        let synced_time = match self.sync {
+            Some((sync_time, sync_timestamp)) if timestamp >= sync_timestamp => Some(sync_time + web_time::Duration::from_millis(timestamp - sync_timestamp)),
+            _ => None,
+        };
+
+        let mut time = match synced_time {
+            Some(time) if time + settings.delay >= now && time <= now + settings.delay => time,
+            // first one, or the latency changed enough that our timeline is off, so line it up again from here
+            _ => {
+                self.sync = Some((now, timestamp));
+                now
+            },
+        };
+
+        if let Some(latest) = self.snapshots.back() {
+            time = time.max(latest.time);
+        }
+
+        self.snapshots.push_back(
+            Snapshot {
+                time,
+                timestamp,
+                pos,
+            }
+        );
+
+        if self.snapshots.len() > MAX_BUFFERED_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.sync = None;
+    }
+
+    /// Where the entity should be right now, rendered settings.delay in the past
+    pub fn sample(&mut self, settings: &InterpolationSettings) -> Option<InterpolatedPose> {
+
+        let now = web_time::Instant::now();
+
+        let render_time = now.checked_sub(settings.delay)?;
+
+        // we only ever need one snapshot older than the render time
+        while self.snapshots.len() > 2 && self.snapshots[1].time <= render_time {
+            self.snapshots.pop_front();
+        }
+
+        let first = *self.snapshots.front()?;
+
+        if self.snapshots.len() == 1 {
+            return Some(InterpolatedPose { pos: first.pos, linvel: Vec2::ZERO });
+        }
+
+        let second = self.snapshots[1];
+
+        let gap = (second.time - first.time).as_secs_f32();
+
+        if gap <= 0. {
+            return Some(InterpolatedPose { pos: second.pos, linvel: Vec2::ZERO });
+        }
+
+        let linvel = (second.pos.translation - first.pos.translation) / gap;
+
+        if render_time <= first.time {
+            return Some(InterpolatedPose { pos: first.pos, linvel: Vec2::ZERO });
+        }
+
+        let latest = *self.snapshots.back().unwrap();
+
+        // ran out of updates so guess where it went, but not for too long
+        if render_time > latest.time {
+
+            let previous = self.snapshots[self.snapshots.len() - 2];
+
+            let gap = (latest.time - previous.time).as_secs_f32();
+
+            if gap <= 0. {
+                return Some(InterpolatedPose { pos: latest.pos, linvel: Vec2::ZERO });
+            }
+
+            let linvel = (latest.pos.translation - previous.pos.translation) / gap;
+
+            let extrapolation = (render_time - latest.time).min(settings.max_extrapolation).as_secs_f32();
+
+            return Some(
+                InterpolatedPose {
+                    pos: Pose2::new(latest.pos.translation + linvel * extrapolation, latest.pos.rotation.angle()),
+                    linvel: match render_time - latest.time > settings.max_extrapolation {
+                        true => Vec2::ZERO,
+                        false => linvel,
+                    },
+                }
+            );
+        }
+
+        let t = (render_time - first.time).as_secs_f32() / gap;
+
+        Some(
+            InterpolatedPose {
+                pos: lerp_pose(first.pos, second.pos, t),
+                linvel,
+            }
+        )
+    }
+
+    /// Move the body to wherever the buffer says it should be
+    pub fn apply(&mut self, body: &mut RigidBody, settings: &InterpolationSettings) {
+
+        let Some(interpolated) = self.sample(settings) else {
+            return;
+        };
+
+        let error = (body.position().translation - interpolated.pos.translation).length();
+
+        // way off (respawn, teleport, we just loaded in) so dont bother smoothing from old data
+        if error > settings.snap_distance {
+            let mut latest = *self.snapshots.back().unwrap();
+
+            latest.time = web_time::Instant::now();
+
+            self.clear();
+            self.snapshots.push_back(latest);
+            self.sync = Some((latest.time, latest.timestamp));
+
+            body.set_position(latest.pos, true);
+
+            return;
+        }
+
+        body.set_position(interpolated.pos, true);
+        body.set_linvel(interpolated.linvel, true);
+    }
+}
+
+pub fn lerp_pose(from: Pose2, to: Pose2, t: f32) -> Pose2 {
+    let t = t.clamp(0., 1.);
+
+    let translation = from.translation + (to.translation - from.translation) * t;
+
+    let from_angle = from.rotation.angle();
+    let mut angle_difference = to.rotation.angle() - from_angle;
+
+    // take the short way around
+    if angle_difference > std::f32::consts::PI {
+        angle_difference -= std::f32::consts::TAU;
+    } else if angle_difference < -std::f32::consts::PI {
+        angle_difference += std::f32::consts::TAU;
+    }
+
+    Pose2::new(translation, from_angle + angle_difference * t)
+}
@@ -1,4 +1,4 @@
-use std::{collections::{HashMap, HashSet, VecDeque}, f32::consts::PI, fs::read_to_string, marker::PhantomData, net::{TcpListener, TcpStream}, ops::Add, path::{Path, PathBuf}, process::exit, str::FromStr, time::Duration};
+use std::{collections::{HashMap, HashSet, VecDeque}, f32::consts::PI, fs::read_to_string, marker::PhantomData, net::{SocketAddr, TcpListener, TcpStream}, ops::Add, path::{Path, PathBuf}, str::FromStr, time::Duration};
 
 use derive_more::From;
 use ewebsock::{WsReceiver, WsSender};
@@ -7,10 +7,10 @@ use macroquad::{camera::{Camera2D, set_camera, set_default_camera}, color::{Colo
 use rapier2d::{parry::query::Ray, prelude::{AxisMask, ColliderBuilder, ColliderHandle, QueryFilter, RigidBodyHandle, VoxelData, Voxels, VoxelsChunkRef}};
 use serde::{Deserialize, Serialize};
 use strum::Display;
-use tungstenite::WebSocket;
+use tungstenite::{WebSocket, handshake::{MidHandshake, server::{NoCallback, ServerHandshake}}};
 use include_dir::{Dir, include_dir};
 
-use crate::{all_keys::ALL_KEYS, font_loader::FontLoader, material_loader::{MaterialLoader, MaterialMeta}, player::Facing, screen_shake::ScreenShakeParameters, server_texture_loader::ServerTextureLoader, sound_loader::SoundLoader, space::Space, texture_loader::ClientTextureLoader, updates::NetworkPacket, weapons::Weapon};
+use crate::{all_keys::ALL_KEYS, area::AreaId, chat::sanitize_name, codec::PacketCodec, interest::{ClientInterest, ClientView, PacketRelevance}, font_loader::FontLoader, frame_input::FrameInput, material_loader::{MaterialLoader, MaterialMeta}, network_conditions::{NetworkConditions, NetworkSimulator}, player::Facing, recording::Recorder, screen_shake::ScreenShakeParameters, server_texture_loader::ServerTextureLoader, sound_loader::SoundLoader, space::Space, texture_loader::ClientTextureLoader, updates::NetworkPacket, weapons::Weapon};
 
 pub mod space;
 pub mod updates;
@@ -57,6 +57,25 @@ pub mod prop;
 pub mod prop_save;
 pub mod base_prop_save;
 pub mod items;
+pub mod codec;
+pub mod player_movement;
+pub mod interpolation;
+pub mod voxel_diff;
+pub mod interest;
+pub mod portal;
+pub mod lobby;
+pub mod wave_director;
+pub mod fixed_timestep;
+pub mod recording;
+pub mod network_conditions;
+pub mod chat;
+pub mod prop_ownership;
+pub mod nav_graph;
+pub mod enemy_ai;
+pub mod enemy_archetype;
+pub mod enemy_death;
+pub mod enemy_perception;
+pub mod frame_input;
 
 fn draw_rounded_rect(x: f32, y: f32, w: f32, h: f32, r: f32, color: Color) {
     let r = r.min(w * 0.5).min(h * 0.5);
@@ -519,14 +538,211 @@ pub fn get_angle_between_rapier_points(point_1: glamx::Vec2, point_2: glamx::Vec
     distance_to_mouse.x.atan2(distance_to_mouse.y)
 }
 
+const RECONNECT_BASE_DELAY: web_time::Duration = web_time::Duration::from_millis(500);
+const RECONNECT_MAX_DELAY: web_time::Duration = web_time::Duration::from_secs(8);
+const MAX_RECONNECT_ATTEMPTS: u32 = 8;
+const CONNECT_TIMEOUT: web_time::Duration = web_time::Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub enum ConnectionState {
+    /// Waiting for the socket to open. Attempt is 0 for the first connection
+    Connecting {
+        attempt: u32,
+        since: web_time::Instant
+    },
+    Connected,
+    /// The connection just dropped and we haven't scheduled a reconnect yet
+    Lost {
+        reason: String
+    },
+    Reconnecting {
+        attempt: u32,
+        next_attempt: web_time::Instant
+    },
+    Failed {
+        reason: String
+    },
+    /// Not connected to anything on purpose, like when watching a replay
+    Offline
+}
+
+/// On windows ewebsock reports would block errors that don't actually mean anything went wrong
+fn is_spurious_websocket_error(error: &str) -> bool {
+    error.contains("could not be completed immediately") || error.contains("WouldBlock")
+}
+
 pub struct ClientIO {
-    pub send: WsSender,
-    pub receive: WsReceiver,
-    pub packet_queue: Vec<NetworkPacket>
+    /// None when we are offline
+    pub send: Option<WsSender>,
+    pub receive: Option<WsReceiver>,
+    pub packet_queue: Vec<NetworkPacket>,
+    pub codec: PacketCodec,
+    pub url: String,
+    pub client_id: ClientId,
+    /// What other players see us as
+    pub name: String,
+    pub state: ConnectionState,
+    reconnected: bool,
+    /// Fake lag and packet loss for testing. Passes everything straight through by default
+    pub network_simulator: NetworkSimulator<NetworkPacket>
 }
 
 
 impl ClientIO {
+
+    pub fn connect(url: String, client_id: ClientId, name: String, codec: PacketCodec) -> Result<Self, String> {
+
+        let (send, receive) = ewebsock::connect(url.clone(), ewebsock::Options::default())?;
+
+        Ok(Self {
+            send: Some(send),
+            receive: Some(receive),
+            packet_queue: Vec::new(),
+            codec,
+            url,
+            client_id,
+            name,
+            state: ConnectionState::Connecting { attempt: 0, since: web_time::Instant::now() },
+            reconnected: false,
+            network_simulator: NetworkSimulator::new(NetworkConditions::default()),
+        })
+    }
+
+    /// Never connects. Anything sent is thrown away and nothing is ever received
+    pub fn offline(client_id: ClientId) -> Self {
+        Self {
+            send: None,
+            receive: None,
+            packet_queue: Vec::new(),
+            codec: PacketCodec::default(),
+            url: String::new(),
+            client_id,
+            name: String::new(),
+            state: ConnectionState::Offline,
+            reconnected: false,
+            network_simulator: NetworkSimulator::new(NetworkConditions::default()),
+        }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        matches!(self.state, ConnectionState::Offline)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state, ConnectionState::Connected)
+    }
+
+    /// Returns true once after we reconnect so the client can throw away the stale world before the new LoadArea arrives
+    pub fn take_reconnected(&mut self) -> bool {
+        std::mem::take(&mut self.reconnected)
+    }
+
+    fn handle_opened(&mut self) {
+
+        let Some(send) = &mut self.send else {
+            return;
+        };
+
+        // the server identifies us by our first message. sending the same id again lets it give us our old player back
+        send.send(
+            ewebsock::WsMessage::Binary(
+                codec::encode_value(
+                    self.codec,
+                    &ClientHello {
+                        client_id: self.client_id,
+                        name: self.name.clone(),
+                    }
+                )
+            )
+        );
+
+        if let ConnectionState::Connecting { attempt, .. } = self.state {
+            if attempt > 0 {
+                log::info!("Reconnected to server after {} attempts", attempt);
+                self.reconnected = true;
+            }
+        }
+
+        self.state = ConnectionState::Connected;
+    }
+
+    fn connection_lost(&mut self, reason: String) {
+        match &self.state {
+            ConnectionState::Connected => {
+                log::warn!("Lost connection to server: {}", reason);
+                self.state = ConnectionState::Lost { reason };
+            },
+            ConnectionState::Connecting { attempt, .. } => {
+                let attempt = *attempt;
+                log::warn!("Connection attempt {} failed: {}", attempt, reason);
+                self.schedule_reconnect(attempt + 1, reason);
+            },
+            _ => {}
+        }
+    }
+
+    fn schedule_reconnect(&mut self, attempt: u32, reason: String) {
+        if attempt > MAX_RECONNECT_ATTEMPTS {
+            log::error!("Giving up on connecting to server after {} attempts: {}", MAX_RECONNECT_ATTEMPTS, reason);
+            self.state = ConnectionState::Failed { reason };
+            return;
+        }
+
+        let delay = (RECONNECT_BASE_DELAY * 2_u32.pow(attempt - 1)).min(RECONNECT_MAX_DELAY);
+
+        self.state = ConnectionState::Reconnecting {
+            attempt,
+            next_attempt: web_time::Instant::now() + delay
+        };
+    }
+
+    /// Advance the connection state machine. Should be called every frame
+    pub fn update_connection(&mut self) {
+
+        // delayed batches need to go out when they are due, not whenever we next flush
+        if self.is_connected() {
+            let packets = self.network_simulator.outgoing(Vec::new());
+
+            self.send_frames(codec::encode_batch(self.codec, &packets));
+        }
+
+        match &self.state {
+            ConnectionState::Lost { reason } => {
+                let reason = reason.clone();
+                self.schedule_reconnect(1, reason);
+            },
+            ConnectionState::Connecting { attempt, since } => {
+                if since.elapsed() > CONNECT_TIMEOUT {
+                    let attempt = *attempt;
+                    self.schedule_reconnect(attempt + 1, "timed out".to_string());
+                }
+            },
+            ConnectionState::Reconnecting { attempt, next_attempt } => {
+                if web_time::Instant::now() < *next_attempt {
+                    return;
+                }
+
+                let attempt = *attempt;
+
+                log::info!("Reconnecting to {} (attempt {})", self.url, attempt);
+
+                match ewebsock::connect(self.url.clone(), ewebsock::Options::default()) {
+                    Ok((send, receive)) => {
+                        self.send = Some(send);
+                        self.receive = Some(receive);
+
+                        self.state = ConnectionState::Connecting { attempt, since: web_time::Instant::now() };
+                    },
+                    Err(error) => {
+                        // this only happens if the url itself is bad so there is no point trying again
+                        self.state = ConnectionState::Failed { reason: error };
+                    },
+                }
+            },
+            ConnectionState::Connected | ConnectionState::Failed { .. } | ConnectionState::Offline => {},
+        }
+    }
+
     pub fn send_network_packet(&mut self, packet: NetworkPacket) {
 
         self.packet_queue.push(packet);
@@ -536,59 +752,94 @@ impl ClientIO {
 
     pub fn flush(&mut self) {
 
-        let packet_queue_json = serde_json::to_string(&self.packet_queue).unwrap();
-        let packet_queue_bytes = packet_queue_json.as_bytes().to_vec();
+        // anything we queued while disconnected is stale by the time we reconnect
+        if !self.is_connected() {
+            self.packet_queue.clear();
+            self.network_simulator.clear();
+            return;
+        }
 
-        self.send.send(
-            ewebsock::WsMessage::Binary(
-                packet_queue_bytes
-            )
-        );
+        let packets = self.network_simulator.outgoing(self.packet_queue.drain(..));
 
-        self.packet_queue.clear();
+        self.send_frames(codec::encode_batch(self.codec, &packets));
+    }
+
+    fn send_frames(&mut self, frames: Vec<Vec<u8>>) {
+        let Some(send) = &mut self.send else {
+            return;
+        };
+
+        for frame in frames {
+            send.send(
+                ewebsock::WsMessage::Binary(
+                    frame
+                )
+            );
+        }
     }
 
     pub fn receive_packets(&mut self) -> Vec<NetworkPacket> {
 
-        let mut packets: Vec<NetworkPacket> = Vec::new();
+        let mut frames: Vec<Vec<u8>> = Vec::new();
 
         loop {
-            let network_packet_bytes = match self.receive.try_recv() {
+            let Some(receive) = &mut self.receive else {
+                break;
+            };
+
+            let network_packet_bytes = match receive.try_recv() {
                 Some(event) => {
                     match event {
-                        ewebsock::WsEvent::Opened => todo!("unhandled 'Opened' event"),
+                        ewebsock::WsEvent::Opened => {
+                            self.handle_opened();
+                            continue;
+                        },
                         ewebsock::WsEvent::Message(message) => {
                             match message {
                                 ewebsock::WsMessage::Binary(bytes) => bytes,
-                                _ => todo!("unhandled message type when trying to receive packet from server")
+                                _ => {
+                                    log::warn!("Received non binary message from server");
+                                    continue;
+                                }
                             }
                         },
-                        ewebsock::WsEvent::Error(_error) => {
+                        ewebsock::WsEvent::Error(error) => {
 
+                            if is_spurious_websocket_error(&error) {
+                                continue;
+                            }
 
-                            return Vec::new();
-                            // this is stupid
-                            // if error.contains("A non-blocking socket operation could not be completed immediately)") {
-                            //     println!("io error: {}", error);
-                            //     return Vec::new();
-                            // }
-                            //todo!("unhandled 'Error' event when trying to receive update from server: {}", error)
+                            self.connection_lost(error);
+                            break;
+                        },
+                        ewebsock::WsEvent::Closed => {
+                            self.connection_lost("server closed the connection".to_string());
+                            break;
                         },
-                        ewebsock::WsEvent::Closed => todo!("server closed"),
                     }
                 },
                 None => break, // this means there are no more updates
             };
 
-            let network_packet_json = String::from_utf8(network_packet_bytes).unwrap();
+            frames.push(network_packet_bytes);
+        }
+
+        let mut packets: Vec<NetworkPacket> = Vec::new();
 
-            let mut network_packets: Vec<NetworkPacket> = serde_json::from_str(&network_packet_json).unwrap();
+        for frame in frames {
 
-            packets.append(&mut network_packets);
+            let (_codec, mut network_packets) = match codec::decode_batch(&frame) {
+                Ok(decoded) => decoded,
+                Err(error) => {
+                    log::error!("Failed to decode packets from server: {}", error);
+                    continue;
+                },
+            };
 
+            packets.append(&mut network_packets);
         }
 
-        packets
+        self.network_simulator.incoming(packets)
     }
 
 }
@@ -670,199 +921,355 @@ impl ClientId {
         }
     }
 }
+
+/// Just the number, so admins can type it back in
+impl std::fmt::Display for ClientId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+/// The first thing a client sends once the websocket is open
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientHello {
+    /// Sending the same id again after reconnecting gets you your old player back
+    pub client_id: ClientId,
+    pub name: String
+}
+
+/// How long a new connection has to finish the websocket handshake and say hello
+const HANDSHAKE_TIMEOUT: web_time::Duration = web_time::Duration::from_secs(5);
+
+enum PendingConnectionStage {
+    Accepted(TcpStream),
+    Handshaking(MidHandshake<ServerHandshake<TcpStream, NoCallback>>),
+    /// The websocket is open but the client hasn't said hello yet
+    AwaitingHello(WebSocket<TcpStream>)
+}
+
+/// A connection that is still doing the websocket handshake. Advanced a little bit every server loop so slow clients dont stall everyone else
+struct PendingConnection {
+    address: SocketAddr,
+    started: web_time::Instant,
+    stage: Option<PendingConnectionStage>
+}
+
+impl PendingConnection {
+
+    /// Returns the client once it has said hello, None if it isn't done yet
+    fn advance(&mut self) -> Result<Option<(PacketCodec, ClientHello, WebSocket<TcpStream>)>, String> {
+
+        let handshake_result = match self.stage.take() {
+            Some(PendingConnectionStage::Accepted(stream)) => tungstenite::accept(stream),
+            Some(PendingConnectionStage::Handshaking(mid_handshake)) => mid_handshake.handshake(),
+            Some(PendingConnectionStage::AwaitingHello(websocket_stream)) => Ok(websocket_stream),
+            None => return Err("connection has no stage".to_string()),
+        };
+
+        let mut websocket_stream = match handshake_result {
+            Ok(websocket_stream) => websocket_stream,
+            Err(tungstenite::HandshakeError::Interrupted(mid_handshake)) => {
+                self.stage = Some(PendingConnectionStage::Handshaking(mid_handshake));
+                return Ok(None);
+            },
+            Err(tungstenite::HandshakeError::Failure(error)) => {
+                return Err(format!("handshake failed: {}", error));
+            },
+        };
+
+        match websocket_stream.read() {
+            Ok(tungstenite::Message::Binary(bytes)) => {
+                let (client_codec, hello) = codec::decode_value(&bytes)
+                    .map_err(|error| format!("invalid hello: {}", error))?;
+
+                Ok(Some((client_codec, hello, websocket_stream)))
+            },
+            Ok(tungstenite::Message::Ping(_) | tungstenite::Message::Pong(_)) => {
+                self.stage = Some(PendingConnectionStage::AwaitingHello(websocket_stream));
+                Ok(None)
+            },
+            Ok(_) => Err("client tried to send non binary data when saying hello".to_string()),
+            Err(tungstenite::Error::Io(error)) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                // keep waiting until the client says hello
+                self.stage = Some(PendingConnectionStage::AwaitingHello(websocket_stream));
+                Ok(None)
+            },
+            Err(error) => Err(format!("an error occured while reading the client's hello: {}", error)),
+        }
+    }
+}
+
 pub struct ServerIO {
     pub clients: HashMap<ClientId, WebSocket<TcpStream>>,
     pub listener: TcpListener,
-    queued_packets: HashMap<ClientId, Vec<NetworkPacket>>
+    queued_packets: HashMap<ClientId, Vec<NetworkPacket>>,
+    /// The codec each client used for its handshake, we reply in the same one
+    pub client_codecs: HashMap<ClientId, PacketCodec>,
+    /// Display names from the hello. Kept after a disconnect so we can say who left, the server removes them
+    pub client_names: HashMap<ClientId, String>,
+    /// Kicked clients can't come back with the same id. Restarting the game gets them a new one
+    pub kicked_clients: HashSet<ClientId>,
+    pending_connections: Vec<PendingConnection>,
+    /// Which area each client is in and what they can see, so we don't send them stuff they don't need
+    pub client_interest: HashMap<ClientId, ClientInterest>,
+    /// Everything we send goes in here too if we are recording the match
+    pub recorder: Option<Recorder>,
+    /// Fake lag and packet loss for testing. Passes everything straight through by default
+    pub network_simulator: NetworkSimulator<(ClientId, NetworkPacket)>
 }
 
 impl ServerIO {
 
-    pub fn new() -> Self {
-        let listener = match TcpListener::bind("0.0.0.0:5560") {
-            Ok(listener) => listener,
-            Err(error) => panic!("failed to bind listener: {}", error),
-        };
+    pub fn new(address: &str) -> Result<Self, std::io::Error> {
+        let listener = TcpListener::bind(address)?;
 
-        match listener.set_nonblocking(true) {
-            Ok(_) => {},
-            Err(error) => panic!("failed to set server as non blocking: {}", error),
-        };
+        listener.set_nonblocking(true)?;
 
-        Self {
+        Ok(Self {
             clients: HashMap::new(),
             listener,
-            queued_packets: HashMap::new()
-        }
+            queued_packets: HashMap::new(),
+            client_codecs: HashMap::new(),
+            client_names: HashMap::new(),
+            kicked_clients: HashSet::new(),
+            pending_connections: Vec::new(),
+            client_interest: HashMap::new(),
+            recorder: None,
+            network_simulator: NetworkSimulator::new(NetworkConditions::default())
+        })
     }
 
     /// Send the queued packets and return disconnected client ids
     pub fn flush(&mut self, total_sent_bytes: &mut usize) -> Vec<ClientId> {
 
+        if let Some(recorder) = &mut self.recorder {
+            recorder.flush();
+        }
+
+        let mut outgoing: Vec<(ClientId, NetworkPacket)> = Vec::new();
+
+        for (client_id, queued_packets) in &mut self.queued_packets {
+            for packet in queued_packets.drain(..) {
+                outgoing.push((*client_id, packet));
+            }
+        }
+
+        // group whatever made it through the simulator back up by client, keeping the order it arrived in
+        let mut arrived: Vec<(ClientId, Vec<NetworkPacket>)> = Vec::new();
+
+        for (client_id, packet) in self.network_simulator.outgoing(outgoing) {
+            match arrived.iter_mut().find(|(arrived_client, _)| *arrived_client == client_id) {
+                Some((_, packets)) => packets.push(packet),
+                None => arrived.push((client_id, vec![packet])),
+            }
+        }
+
+        let mut frames: Vec<(ClientId, Vec<u8>)> = Vec::new();
+
+        for (client_id, packets) in arrived {
+
+            let client_codec = self.client_codecs.get(&client_id).copied().unwrap_or_default();
+
+            for frame in codec::encode_batch(client_codec, &packets) {
+                frames.push((client_id, frame));
+            }
+        }
+
         let mut disconnected_clients: Vec<ClientId> = Vec::new();
 
-        for (client_id, client) in &mut self.clients {
-            let queued_packets = match self.queued_packets.get(client_id) {
-                Some(queued_packets) => {
+        for (client_id, frame) in frames {
 
-                    if queued_packets.len() == 0 {
-                        continue;
-                    }
+            if disconnected_clients.contains(&client_id) {
+                continue;
+            }
 
-                    queued_packets
-                },
-                None => panic!("didn't have a packet queue for client: {:?}", client_id),
+            // they might have left while this was held up in the simulator
+            let Some(client) = self.clients.get_mut(&client_id) else {
+                continue;
             };
 
-            let queued_packets_json = serde_json::to_string(&queued_packets).unwrap();
-            let bytes = queued_packets_json.as_bytes();
+            *total_sent_bytes += frame.len();
 
-            *total_sent_bytes += bytes.len();
-
-            match client.send(
-                
+            if client.send(
                 tungstenite::Message::Binary(
-                    bytes.into()
+                    frame.into()
                 )
-            ) {
-                Ok(_) => {
-                    self.queued_packets.get_mut(client_id).unwrap().clear();
-                },
-                Err(_) => {
-                    disconnected_clients.push(*client_id);
-                },
+            ).is_err() {
+                disconnected_clients.push(client_id);
             }
-
-
         }
 
         for client in &disconnected_clients {
-            self.clients.remove(&client).unwrap();
-            self.queued_packets.remove(&client);
+            self.disconnect_client(*client);
         };
 
         disconnected_clients
     }
 
     pub fn send_all_except(&mut self, packet: NetworkPacket, except: ClientId) {
+        let relevance = packet.relevance();
 
-        for client_id in &mut self.clients.keys() {
+        self.send_relevant(packet, Some(except), relevance);
+    }
 
-            if *client_id == except {
-                continue;
-            }
+    pub fn send_all_clients(&mut self, packet: NetworkPacket) {
+        let relevance = packet.relevance();
 
-            let queued_packets = self.queued_packets.get_mut(client_id).unwrap();
+        self.send_relevant(packet, None, relevance);
+    }
 
+    /// For packets that don't say where they happened (like velocity updates) but we know anyway
+    pub fn send_all_near(&mut self, packet: NetworkPacket, position: glamx::Vec2, except: Option<ClientId>) {
+        let mut relevance = packet.relevance();
 
-            queued_packets.push(packet.clone());
-            
-        }
+        relevance.position = Some(position);
 
-        
+        self.send_relevant(packet, except, relevance);
     }
 
-    pub fn send_all_clients(&mut self, packet: NetworkPacket) {
+    fn send_relevant(&mut self, packet: NetworkPacket, except: Option<ClientId>, relevance: PacketRelevance) {
 
+        // the recording should have the whole match, not just what somebody happened to be looking at
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&packet);
+        }
 
-        for client_id in &mut self.clients.keys() {
+        for client_id in self.clients.keys() {
+
+            if Some(*client_id) == except {
+                continue;
+            }
+
+            if let Some(interest) = self.client_interest.get(client_id) {
+                if !interest.is_interested(&relevance) {
+                    continue;
+                }
+            }
 
             let queued_packets = self.queued_packets.get_mut(client_id).unwrap();
 
             queued_packets.push(packet.clone());
-            
-            
         }
+    }
 
+    pub fn set_client_area(&mut self, client_id: ClientId, area_id: AreaId) {
+        self.client_interest.entry(client_id).or_default().area_id = Some(area_id);
+    }
 
+    pub fn set_client_view(&mut self, client_id: ClientId, view: ClientView) {
+        self.client_interest.entry(client_id).or_default().view = Some(view);
     }
 
     pub fn send_client(&mut self, client_id: ClientId, packet: NetworkPacket) {
-        let queued_packets = self.queued_packets.get_mut(&client_id).unwrap();
+        // areas get reset between matches, the replay needs to know when that happens
+        if let (Some(recorder), NetworkPacket::LoadArea(_)) = (&mut self.recorder, &packet) {
+            recorder.record(&packet);
+        }
+
+        let Some(queued_packets) = self.queued_packets.get_mut(&client_id) else {
+            log::warn!("Tried to send a packet to unknown client {:?}", client_id);
+            return;
+        };
 
         queued_packets.push(packet);
     }
 
-    pub fn accept_new_client(&mut self) -> Option<ClientId> {
-        match self.listener.accept() {
-            Ok((stream, address)) => {
-                log::info!("Received new connection from address: {}", address);
-
-                stream.set_nonblocking(true)
-                    .map_err(|e| {
-                        log::error!("Failed to set new client as non blocking: {}", e);
-                        exit(1)
-                    });
-
-                let mut websocket_stream = loop {
-                    match tungstenite::accept(stream.try_clone().expect("failed to clone stream")) {
-                        Ok(websocket_stream) => break websocket_stream,
-                        Err(error) => {
-                            match error {
-                                tungstenite::HandshakeError::Interrupted(_) => continue, // try again if the handshake isnt done yet
-                                tungstenite::HandshakeError::Failure(error) => panic!("handshake failed with new client: {}", error),
-                            }
-                        },
-                    };
-                };
+    /// Close a client's connection and forget about them
+    pub fn disconnect_client(&mut self, client_id: ClientId) {
+        if let Some(mut client) = self.clients.remove(&client_id) {
+            // we dont care if this fails, we are getting rid of them either way
+            let _ = client.close(None);
+            let _ = client.flush();
+        }
 
-                let client_id: ClientId = loop {
-                    match websocket_stream.read() {
-                        Ok(message) => {
-                            match message {
-                                tungstenite::Message::Binary(bytes) => {
+        self.queued_packets.remove(&client_id);
+        self.client_codecs.remove(&client_id);
+        self.client_interest.remove(&client_id);
+    }
 
-                                    let json = String::from_utf8(bytes).unwrap();
+    /// Accept any new tcp connections and advance the handshakes of pending ones. Never blocks
+    pub fn accept_new_clients(&mut self) -> Vec<ClientId> {
 
-                                    break serde_json::from_str(&json).unwrap()
-                                },
-                                _ => {
-                                    panic!("client tried to send non binary data when sending client id")
-                                }
-                            }
-                        },
-                        Err(error) => {
-                            match error {
-                                
-                                tungstenite::Error::Io(error) => {
-                                    match error.kind() {
-                                        std::io::ErrorKind::WouldBlock => {
-                                            // keep waiting until the client sends the client id
-                                            continue;
-                                        },
-                                        _ => {
-                                            panic!("an error occured while reading the client's id: {}", error)
-                                        },
-                                    }
-                                },
-                                _ => {
-                                    panic!("an error occured while reading the client's id: {}", error)
-                                }
-                                
-                            }
-                        },
+        self.accept_pending_connections();
+
+        let mut new_clients = Vec::new();
+
+        for mut pending_connection in std::mem::take(&mut self.pending_connections) {
+
+            if pending_connection.started.elapsed() > HANDSHAKE_TIMEOUT {
+                log::warn!("Handshake with {} timed out", pending_connection.address);
+                continue;
+            }
+
+            match pending_connection.advance() {
+                Ok(Some((client_codec, hello, mut websocket_stream))) => {
+                    let client_id = hello.client_id;
+                    let name = sanitize_name(&hello.name);
+
+                    if self.kicked_clients.contains(&client_id) {
+                        log::info!("Turning away kicked client {}", name);
+
+                        let _ = websocket_stream.close(None);
+                        let _ = websocket_stream.flush();
+
+                        continue;
                     }
-                };
-                
-                log::info!("New client connected");
 
-                self.clients.insert(client_id, websocket_stream);
+                    log::info!("New client {} connected using {:?} packets", name, client_codec);
 
-                self.queued_packets.insert(client_id, Vec::new());
+                    self.client_names.insert(client_id, name);
 
-                return Some(client_id)
+                    self.clients.insert(client_id, websocket_stream);
 
-            },
-            Err(error) => {
-                match error.kind() {
-                    std::io::ErrorKind::WouldBlock => return None, // no new clients
+                    self.client_codecs.insert(client_id, client_codec);
+
+                    self.queued_packets.insert(client_id, Vec::new());
 
-                    _ => {
-                        log::warn!("Something went wrong trying to accept a new client");
-                        return None
+                    new_clients.push(client_id);
+                },
+                Ok(None) => {
+                    // not done yet, try again next time
+                    self.pending_connections.push(pending_connection);
+                },
+                Err(error) => {
+                    log::warn!("Failed to connect client {}: {}", pending_connection.address, error);
+                },
+            }
+        }
+
+        new_clients
+    }
+
+    fn accept_pending_connections(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    log::info!("Received new connection from address: {}", address);
+
+                    if let Err(error) = stream.set_nonblocking(true) {
+                        log::error!("Failed to set new client as non blocking: {}", error);
+                        continue;
                     }
-                }
-            },
+
+                    self.pending_connections.push(
+                        PendingConnection {
+                            address,
+                            started: web_time::Instant::now(),
+                            stage: Some(PendingConnectionStage::Accepted(stream)),
+                        }
+                    );
+                },
+                Err(error) => {
+                    match error.kind() {
+                        std::io::ErrorKind::WouldBlock => return, // no new clients
+
+                        _ => {
+                            log::warn!("Something went wrong trying to accept a new client: {}", error);
+                            return
+                        }
+                    }
+                },
+            }
         }
     }
 
@@ -1154,13 +1561,20 @@ pub struct ClientTickContext<'a> {
     pub draw_commands: &'a mut DrawCommands,
     pub material_loader: &'a mut MaterialLoader,
     pub fonts: &'a FontLoader,
-    pub debug_strings: &'a mut Vec<String>
+    pub debug_strings: &'a mut Vec<String>,
+    /// Key and mouse releases and scrolling, only handed to the first step of a frame
+    pub input: &'a FrameInput,
+    /// The chat box is open so keys are going into that instead of moving us around
+    pub typing: bool,
+    /// Draw where enemies think they can go
+    pub show_nav_graph: bool
 }
 
 
 pub struct ServerTickContext<'a> {
     pub network_io: &'a mut ServerIO,
-    pub last_tick_duration: web_time::Duration
+    pub last_tick_duration: web_time::Duration,
+    pub prefabs: &'a Prefabs
 }
 
 
@@ -1311,6 +1725,25 @@ impl<'a> TickContext<'a> {
         }
     }
 
+    /// Same as send_network_packet but the server only sends it to clients close enough to position
+    pub fn send_network_packet_near(&mut self, packet: NetworkPacket, position: glamx::Vec2) {
+        match self {
+            TickContext::Server(server_tick_context) => {
+                server_tick_context.network_io.send_all_near(packet, position, None);
+            },
+            _ => self.send_network_packet(packet)
+        }
+    }
+
+    /// The editor doesn't have any loaded
+    pub fn prefabs(&self) -> Option<&Prefabs> {
+        match self {
+            TickContext::Client(client_tick_context) => Some(client_tick_context.prefabs),
+            TickContext::Server(server_tick_context) => Some(server_tick_context.prefabs),
+            TickContext::Editor(_) => None,
+        }
+    }
+
     pub fn last_tick_duration(&self) -> web_time::Duration {
         match self {
             TickContext::Client(client_tick_context) => *client_tick_context.last_tick_duration,
@@ -1348,6 +1781,13 @@ impl Prefabs {
         self.prefabs.get(&normalized_path.to_string_lossy().to_string()).unwrap().clone()
     }
 
+    /// Same as get_prefab_data but for paths that came from a person and might be wrong
+    pub fn try_get_prefab_data(&self, path: impl ToString) -> Option<String> {
+        let normalized_path = normalize_path(&PathBuf::from_str(&path.to_string()).ok()?);
+
+        self.prefabs.get(&normalized_path.to_string_lossy().to_string()).cloned()
+    }
+
     pub fn load_prefab_data(&mut self, path: impl ToString, bytes: &[u8]) {
 
         let json_string = String::from_utf8(bytes.into()).unwrap();
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ClientId;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum GamePhase {
+    /// Everyone hangs out in the lobby area until they ready up
+    Lobby,
+    /// Everyone is ready, the match starts when this runs out
+    Countdown {
+        remaining: web_time::Duration
+    },
+    InMatch
+}
+
+/// Client telling the server whether it's ready. Server forwards it to everyone with the real client id filled in
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct PlayerReadyUpdate {
+    pub client_id: ClientId,
+    pub ready: bool
+}
+
+/// A client left so it shouldn't show up in the lobby anymore
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct PlayerLeftLobby {
+    pub client_id: ClientId
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct GamePhaseUpdate {
+    pub phase: GamePhase
+}
+
+/// The client's copy of the lobby so it can draw who is ready and the countdown
+pub struct LobbyState {
+    /// None if the server isn't running a lobby
+    pub phase: Option<GamePhase>,
+    /// Everyone in the lobby in the order they joined
+    pub ready: Vec<(ClientId, bool)>,
+    /// When we got the last phase update, so we can count the countdown down ourselves
+    phase_received: web_time::Instant
+}
+
+impl LobbyState {
+    pub fn new() -> Self {
+        Self {
+            phase: None,
+            ready: Vec::new(),
+            phase_received: web_time::Instant::now(),
+        }
+    }
+
+    pub fn set_phase(&mut self, phase: GamePhase) {
+
+        // ready states only mean something for the next match
+        if phase == GamePhase::InMatch {
+            for (_, ready) in &mut self.ready {
+                *ready = false;
+            }
+        }
+
+        self.phase = Some(phase);
+        self.phase_received = web_time::Instant::now();
+    }
+
+    /// Whether we should be showing the lobby overlay and listening for ready up
+    pub fn in_lobby(&self) -> bool {
+        match self.phase {
+            Some(GamePhase::Lobby) | Some(GamePhase::Countdown { .. }) => true,
+            _ => false
+        }
+    }
+
+    pub fn set_ready(&mut self, client_id: ClientId, ready: bool) {
+        match self.ready.iter_mut().find(|(ready_client, _)| *ready_client == client_id) {
+            Some((_, client_ready)) => *client_ready = ready,
+            None => self.ready.push((client_id, ready)),
+        }
+    }
+
+    pub fn remove(&mut self, client_id: ClientId) {
+        self.ready.retain(|(ready_client, _)| *ready_client != client_id);
+    }
+
+    pub fn is_ready(&self, client_id: ClientId) -> bool {
+        self.ready.iter().any(|(ready_client, ready)| *ready_client == client_id && *ready)
+    }
+
+    pub fn countdown_remaining(&self) -> Option<web_time::Duration> {
+        match self.phase {
+            Some(GamePhase::Countdown { remaining }) => Some(remaining.saturating_sub(self.phase_received.elapsed())),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,545 @@
+use std::{cmp::Ordering, collections::{BinaryHeap, HashMap}};
+
+use glamx::{IVec2, Vec2};
+use macroquad::color::{Color, BLUE, GREEN, RED, YELLOW};
+
+use crate::{DrawCommand, DrawLineParameters, DrawRectangleParameters, TickContext, clip::Clip, prop::Prop, rapier_to_macroquad, space::Space, tile::Tile};
+
+/// Same size as a tile so tiles land exactly on cells
+pub const NAV_CELL_SIZE: f32 = 50.;
+
+/// How many cells up an enemy can jump
+const MAX_JUMP_HEIGHT: i32 = 3;
+
+/// How many cells across an enemy can jump
+const MAX_JUMP_DISTANCE: i32 = 3;
+
+/// Enemies won't walk off anything taller than this
+const MAX_DROP_HEIGHT: i32 = 12;
+
+/// Falling is the cheapest way to cover ground, so the path heuristic has to assume everything costs this little or it overestimates
+const DROP_COST_PER_CELL: f32 = 0.5;
+
+/// Breaking a prop is slow so only do it if going around is a lot further
+const BREAK_THROUGH_COST: f32 = 8.;
+
+/// Stops a path to somewhere unreachable from searching the whole map
+const MAX_SEARCH_NODES: usize = 4000;
+
+/// Props get pushed around and chipped away so we rebuild every so often even if nothing was destroyed
+const REBUILD_INTERVAL: web_time::Duration = web_time::Duration::from_secs(5);
+
+/// Don't rebuild more than this often, a grenade can destroy a lot of props in a row
+const MIN_REBUILD_INTERVAL: web_time::Duration = web_time::Duration::from_millis(500);
+
+/// Props moving faster than this are flying around and aren't worth planning around
+const STATIC_PROP_SPEED: f32 = 5.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum CellKind {
+    /// Clips you can jump up through
+    OneWay,
+    /// Props. In the way but can be shot through
+    Breakable,
+    Solid
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NavEdgeKind {
+    Walk,
+    Jump,
+    Drop,
+    /// There's a prop in the way that needs to be destroyed first
+    BreakThrough
+}
+
+impl NavEdgeKind {
+    fn color(&self) -> Color {
+        match self {
+            NavEdgeKind::Walk => GREEN,
+            NavEdgeKind::Jump => YELLOW,
+            NavEdgeKind::Drop => BLUE,
+            NavEdgeKind::BreakThrough => RED,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NavEdge {
+    to: usize,
+    kind: NavEdgeKind,
+    cost: f32
+}
+
+/// A cell an enemy could stand in
+#[derive(Debug, Clone)]
+struct NavNode {
+    cell: IVec2,
+    /// There's a prop in this cell that has to go before we can stand here
+    blocked: bool,
+    edges: Vec<NavEdge>
+}
+
+/// One step along a path. The kind is how we get to this position from the last one
+#[derive(Debug, Clone, Copy)]
+pub struct NavStep {
+    pub position: Vec2,
+    pub cell: IVec2,
+    pub kind: NavEdgeKind
+}
+
+/// Where enemies can stand in an area and how they get between those places
+pub struct NavGraph {
+    cells: HashMap<IVec2, CellKind>,
+    nodes: Vec<NavNode>,
+    node_lookup: HashMap<IVec2, usize>,
+    /// Bumped every rebuild so enemies know their path might be out of date
+    pub version: u64,
+    dirty: bool,
+    last_build: web_time::Instant
+}
+
+impl NavGraph {
+    pub fn new() -> Self {
+        Self {
+            cells: HashMap::new(),
+            nodes: Vec::new(),
+            node_lookup: HashMap::new(),
+            version: 0,
+            dirty: true,
+            last_build: web_time::Instant::now(),
+        }
+    }
+
+    /// Something got destroyed, rebuild soon
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn needs_rebuild(&self) -> bool {
+        if self.last_build.elapsed() < MIN_REBUILD_INTERVAL && self.version != 0 {
+            return false;
+        }
+
+        self.dirty || self.last_build.elapsed() > REBUILD_INTERVAL
+    }
+
+    pub fn cell_at(position: Vec2) -> IVec2 {
+        IVec2::new(
+            (position.x / NAV_CELL_SIZE).round() as i32,
+            (position.y / NAV_CELL_SIZE).round() as i32
+        )
+    }
+
+    pub fn cell_center(cell: IVec2) -> Vec2 {
+        Vec2::new(cell.x as f32 * NAV_CELL_SIZE, cell.y as f32 * NAV_CELL_SIZE)
+    }
+
+    pub fn rebuild(&mut self, tiles: &Vec<Vec<Option<Tile>>>, clips: &Vec<Clip>, props: &Vec<Box<dyn Prop>>, space: &Space) {
+
+        self.cells.clear();
+        self.nodes.clear();
+        self.node_lookup.clear();
+
+        for (x, column) in tiles.iter().enumerate() {
+            for (y, tile) in column.iter().enumerate() {
+                if tile.is_some() {
+                    self.cells.insert(IVec2::new(x as i32, y as i32), CellKind::Solid);
+                }
+            }
+        }
+
+        for clip in clips.iter().filter(|clip| !clip.despawn) {
+            let kind = match clip.one_way {
+                true => CellKind::OneWay,
+                false => CellKind::Solid,
+            };
+
+            self.fill_collider(space, clip.collider_handle, kind);
+        }
+
+        for prop in props.iter().filter(|prop| !prop.should_despawn()) {
+            let Some(body) = space.rigid_body_set.get(prop.rigid_body_handle()) else {
+                continue;
+            };
+
+            // something flying through the air isn't going to be there by the time we get to it
+            if !body.is_fixed() && !body.is_sleeping() && body.linvel().length() > STATIC_PROP_SPEED {
+                continue;
+            }
+
+            self.fill_collider(space, prop.collider_handle(), CellKind::Breakable);
+        }
+
+        // you can only stand right on top of something
+        let mut candidates: Vec<IVec2> = self.cells.keys()
+            .map(|cell| *cell + IVec2::Y)
+            .filter(|cell| self.is_standable(*cell))
+            .collect();
+
+        // hash map order is random and we want the same graph every time
+        candidates.sort_by_key(|cell| (cell.x, cell.y));
+
+        for cell in candidates {
+            self.node_lookup.insert(cell, self.nodes.len());
+
+            self.nodes.push(
+                NavNode {
+                    cell,
+                    blocked: self.cell(cell) == Some(CellKind::Breakable) || self.cell(cell + IVec2::Y) == Some(CellKind::Breakable),
+                    edges: Vec::new(),
+                }
+            );
+        }
+
+        for index in 0..self.nodes.len() {
+            let edges = self.find_edges(index);
+
+            self.nodes[index].edges = edges;
+        }
+
+        self.version += 1;
+        self.dirty = false;
+        self.last_build = web_time::Instant::now();
+    }
+
+    /// Mark every cell the collider covers more than a sliver of
+    fn fill_collider(&mut self, space: &Space, collider_handle: rapier2d::prelude::ColliderHandle, kind: CellKind) {
+        let Some(collider) = space.collider_set.get(collider_handle) else {
+            return;
+        };
+
+        let aabb = collider.compute_aabb();
+
+        let margin = 5.;
+        let half_cell = NAV_CELL_SIZE / 2.;
+
+        let min_x = ((aabb.mins.x - half_cell + margin) / NAV_CELL_SIZE).floor() as i32 + 1;
+        let max_x = ((aabb.maxs.x + half_cell - margin) / NAV_CELL_SIZE).ceil() as i32 - 1;
+        let min_y = ((aabb.mins.y - half_cell + margin) / NAV_CELL_SIZE).floor() as i32 + 1;
+        let max_y = ((aabb.maxs.y + half_cell - margin) / NAV_CELL_SIZE).ceil() as i32 - 1;
+
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                let cell = self.cells.entry(IVec2::new(x, y)).or_insert(kind);
+
+                // solid wins over breakable wins over one way
+                *cell = (*cell).max(kind);
+            }
+        }
+    }
+
+    fn cell(&self, cell: IVec2) -> Option<CellKind> {
+        self.cells.get(&cell).copied()
+    }
+
+    /// Can move through without breaking anything
+    fn is_passable(&self, cell: IVec2) -> bool {
+        matches!(self.cell(cell), None | Some(CellKind::OneWay))
+    }
+
+    fn is_standable(&self, cell: IVec2) -> bool {
+
+        let fits = |cell: IVec2| matches!(self.cell(cell), None | Some(CellKind::Breakable));
+
+        // enemies are about a cell tall but need a bit of headroom
+        fits(cell) && fits(cell + IVec2::Y) && self.cell(cell - IVec2::Y).is_some()
+    }
+
+    fn find_edges(&self, index: usize) -> Vec<NavEdge> {
+
+        let node = &self.nodes[index];
+        let from = node.cell;
+
+        let mut edges = Vec::new();
+
+        for direction in [-1, 1] {
+
+            let side = from + IVec2::new(direction, 0);
+
+            // walking along the ground, or into a prop
+            if let Some(target) = self.node_lookup.get(&side) {
+                let (kind, cost) = match self.nodes[*target].blocked {
+                    true => (NavEdgeKind::BreakThrough, BREAK_THROUGH_COST),
+                    false => (NavEdgeKind::Walk, 1.),
+                };
+
+                edges.push(NavEdge { to: *target, kind, cost });
+
+                continue;
+            }
+
+            // walking off a ledge
+            if !node.blocked && self.is_passable(side) && self.is_passable(side + IVec2::Y) {
+                for depth in 1..=MAX_DROP_HEIGHT {
+                    let below = side - IVec2::new(0, depth);
+
+                    if let Some(target) = self.node_lookup.get(&below) && !self.nodes[*target].blocked {
+                        edges.push(NavEdge { to: *target, kind: NavEdgeKind::Drop, cost: 1. + depth as f32 * DROP_COST_PER_CELL });
+
+                        break;
+                    }
+
+                    if !self.is_passable(below) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if node.blocked {
+            return edges;
+        }
+
+        for dx in -MAX_JUMP_DISTANCE..=MAX_JUMP_DISTANCE {
+            for dy in 0..=MAX_JUMP_HEIGHT {
+
+                // walking covers these
+                if dy == 0 && dx.abs() <= 1 {
+                    continue;
+                }
+
+                let to = from + IVec2::new(dx, dy);
+
+                let Some(target) = self.node_lookup.get(&to) else {
+                    continue;
+                };
+
+                if self.nodes[*target].blocked || !self.jump_is_clear(from, to) {
+                    continue;
+                }
+
+                edges.push(
+                    NavEdge {
+                        to: *target,
+                        kind: NavEdgeKind::Jump,
+                        cost: 2. + (dx as f32).hypot(dy as f32),
+                    }
+                );
+            }
+        }
+
+        edges
+    }
+
+    /// Jump straight up out of the start cell then across at the top. Not how the physics actually goes but close enough
+    fn jump_is_clear(&self, from: IVec2, to: IVec2) -> bool {
+
+        let top = from.y.max(to.y);
+
+        // room to go up, one way clips let us through from below
+        for y in from.y..=top + 1 {
+            if !self.is_passable(IVec2::new(from.x, y)) {
+                return false;
+            }
+        }
+
+        let direction = (to.x - from.x).signum();
+
+        if direction == 0 {
+            return true;
+        }
+
+        let mut x = from.x + direction;
+
+        loop {
+            for y in top..=top + 1 {
+                if !self.is_passable(IVec2::new(x, y)) {
+                    return false;
+                }
+            }
+
+            if x == to.x {
+                return true;
+            }
+
+            x += direction;
+        }
+    }
+
+    /// The node an enemy at this position is standing on, or the closest one if they are in the air
+    fn nearest_node(&self, position: Vec2) -> Option<usize> {
+
+        let cell = Self::cell_at(position);
+
+        // falling or jumping, look down for where they will land
+        for depth in 0..=3 {
+            if let Some(index) = self.node_lookup.get(&(cell - IVec2::new(0, depth))) {
+                return Some(*index);
+            }
+        }
+
+        if let Some(index) = self.node_lookup.get(&(cell + IVec2::Y)) {
+            return Some(*index);
+        }
+
+        self.nodes.iter()
+            .enumerate()
+            .map(|(index, node)| (index, (node.cell - cell).as_vec2().length()))
+            .filter(|(_, distance)| *distance <= 4.)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index)
+    }
+
+    /// Everywhere an enemy could stand within the radius without breaking anything first
+    pub fn positions_near(&self, position: Vec2, radius: f32) -> Vec<Vec2> {
+        self.nodes.iter()
+            .filter(|node| !node.blocked)
+            .map(|node| Self::cell_center(node.cell))
+            .filter(|node_position| (*node_position - position).length() <= radius)
+            .collect()
+    }
+
+    /// A* from one position to another. None if there's no way there
+    pub fn find_path(&self, start: Vec2, goal: Vec2) -> Option<Vec<NavStep>> {
+
+        let start = self.nearest_node(start)?;
+        let goal = self.nearest_node(goal)?;
+
+        let heuristic = |index: usize| (self.nodes[index].cell - self.nodes[goal].cell).as_vec2().length() * DROP_COST_PER_CELL;
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<usize, (usize, NavEdgeKind)> = HashMap::new();
+        let mut costs: HashMap<usize, f32> = HashMap::new();
+
+        costs.insert(start, 0.);
+        open.push(OpenNode { index: start, cost: 0., estimate: heuristic(start) });
+
+        let mut searched = 0;
+
+        while let Some(OpenNode { index, cost, .. }) = open.pop() {
+
+            // we found a cheaper way here after this was pushed and already went through that one
+            if cost > costs[&index] {
+                continue;
+            }
+
+            if index == goal {
+                return Some(self.reconstruct_path(&came_from, start, goal));
+            }
+
+            searched += 1;
+
+            if searched > MAX_SEARCH_NODES {
+                return None;
+            }
+
+            for edge in &self.nodes[index].edges {
+                let new_cost = cost + edge.cost;
+
+                if costs.get(&edge.to).is_some_and(|existing| *existing <= new_cost) {
+                    continue;
+                }
+
+                costs.insert(edge.to, new_cost);
+                came_from.insert(edge.to, (index, edge.kind));
+
+                open.push(OpenNode { index: edge.to, cost: new_cost, estimate: new_cost + heuristic(edge.to) });
+            }
+        }
+
+        None
+    }
+
+    fn reconstruct_path(&self, came_from: &HashMap<usize, (usize, NavEdgeKind)>, start: usize, goal: usize) -> Vec<NavStep> {
+
+        let mut steps = Vec::new();
+        let mut current = goal;
+
+        while current != start {
+            let (previous, kind) = came_from[&current];
+
+            let cell = self.nodes[current].cell;
+
+            steps.push(
+                NavStep {
+                    position: Self::cell_center(cell),
+                    cell,
+                    kind,
+                }
+            );
+
+            current = previous;
+        }
+
+        steps.reverse();
+
+        steps
+    }
+
+    /// Nodes as little squares and edges as lines coloured by how you get across them
+    pub fn draw(&self, ctx: &mut TickContext) {
+
+        let layer = 100;
+
+        for node in &self.nodes {
+
+            let position = rapier_to_macroquad(Self::cell_center(node.cell));
+
+            let color = match node.blocked {
+                true => RED,
+                false => GREEN,
+            };
+
+            ctx.add_draw_command(
+                layer,
+                DrawCommand::DrawRectangle(
+                    DrawRectangleParameters {
+                        position,
+                        size: macroquad::math::Vec2::new(8., 8.),
+                        offset: Some(macroquad::math::Vec2::new(0.5, 0.5)),
+                        rotation: None,
+                        color: Some(color),
+                    }
+                )
+            );
+
+            for edge in &node.edges {
+                let end = rapier_to_macroquad(Self::cell_center(self.nodes[edge.to].cell));
+
+                let mut color = edge.kind.color();
+                color.a = 0.5;
+
+                ctx.add_draw_command(
+                    layer,
+                    DrawCommand::DrawLine(
+                        DrawLineParameters {
+                            start: position,
+                            end,
+                            thickness: 2.,
+                            color,
+                        }
+                    )
+                );
+            }
+        }
+    }
+}
+
+/// BinaryHeap is a max heap so this is backwards to pop the cheapest first
+struct OpenNode {
+    index: usize,
+    /// What it cost to get here when this was pushed
+    cost: f32,
+    estimate: f32
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
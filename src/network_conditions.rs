@@ -0,0 +1,156 @@
+use std::fmt::Display;
+
+use macroquad::rand::RandomRange;
+
+use crate::{ClientId, updates::NetworkPacket};
+
+/// Fake latency, jitter and packet loss so we can see what the game is like on a bad connection without leaving localhost.
+/// Applied separately to each direction, so the round trip is twice the latency
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetworkConditions {
+    pub latency: web_time::Duration,
+    /// Each packet gets up to this much extra or less latency, which also reorders them
+    pub jitter: web_time::Duration,
+    /// Chance from 0 to 1 that a packet never arrives. Only unreliable packets are ever lost
+    pub loss: f32
+}
+
+impl NetworkConditions {
+    pub fn new(latency_ms: u64, jitter_ms: u64, loss_percent: f32) -> Self {
+        Self {
+            latency: web_time::Duration::from_millis(latency_ms),
+            jitter: web_time::Duration::from_millis(jitter_ms),
+            loss: (loss_percent / 100.).clamp(0., 1.),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.latency.is_zero() || !self.jitter.is_zero() || self.loss > 0.
+    }
+
+    fn delay(&self) -> web_time::Duration {
+        let jitter = self.jitter.as_secs_f32();
+
+        let delay = self.latency.as_secs_f32() + RandomRange::gen_range(-jitter, jitter);
+
+        web_time::Duration::from_secs_f32(delay.max(0.))
+    }
+
+    fn dropped(&self) -> bool {
+        self.loss > 0. && RandomRange::gen_range(0., 1.) < self.loss
+    }
+}
+
+impl Display for NetworkConditions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}ms ± {}ms, {:.1}% loss", self.latency.as_millis(), self.jitter.as_millis(), self.loss * 100.)
+    }
+}
+
+/// Lets the simulator tell what it is allowed to lose
+pub trait SimulatedPacket {
+    fn is_unreliable(&self) -> bool;
+}
+
+impl SimulatedPacket for NetworkPacket {
+    fn is_unreliable(&self) -> bool {
+        NetworkPacket::is_unreliable(self)
+    }
+}
+
+impl SimulatedPacket for (ClientId, NetworkPacket) {
+    fn is_unreliable(&self) -> bool {
+        self.1.is_unreliable()
+    }
+}
+
+/// One direction of a connection. Holds on to things until their simulated arrival time
+struct SimulatedLink<T> {
+    in_flight: Vec<(web_time::Instant, T)>,
+    /// Reliable packets can't overtake each other, so none of them can arrive before this
+    last_reliable_arrival: Option<web_time::Instant>
+}
+
+impl<T: SimulatedPacket> SimulatedLink<T> {
+    fn new() -> Self {
+        Self {
+            in_flight: Vec::new(),
+            last_reliable_arrival: None,
+        }
+    }
+
+    fn send(&mut self, item: T, conditions: &NetworkConditions) {
+
+        let mut arrival = web_time::Instant::now() + conditions.delay();
+
+        // the real connection is a websocket, so spawns and despawns can be late but never lost or out of order
+        if item.is_unreliable() {
+            if conditions.dropped() {
+                return;
+            }
+        } else {
+            if let Some(last_reliable_arrival) = self.last_reliable_arrival {
+                arrival = arrival.max(last_reliable_arrival);
+            }
+
+            self.last_reliable_arrival = Some(arrival);
+        }
+
+        self.in_flight.push((arrival, item));
+    }
+
+    fn arrived(&mut self) -> Vec<T> {
+        let now = web_time::Instant::now();
+
+        // stable so things with the same arrival time stay in the order they were sent
+        self.in_flight.sort_by_key(|(arrival, _)| *arrival);
+
+        let arrived_count = self.in_flight.iter().take_while(|(arrival, _)| *arrival <= now).count();
+
+        self.in_flight.drain(..arrived_count).map(|(_, item)| item).collect()
+    }
+}
+
+/// Sits between the websocket and the codec on both the client and the server. Does nothing unless conditions are set
+pub struct NetworkSimulator<T> {
+    pub conditions: NetworkConditions,
+    outgoing: SimulatedLink<T>,
+    incoming: SimulatedLink<T>
+}
+
+impl<T: SimulatedPacket> NetworkSimulator<T> {
+    pub fn new(conditions: NetworkConditions) -> Self {
+        Self {
+            conditions,
+            outgoing: SimulatedLink::new(),
+            incoming: SimulatedLink::new(),
+        }
+    }
+
+    /// Queue up packets we want to send and get back the ones that should actually go out now
+    pub fn outgoing(&mut self, packets: impl IntoIterator<Item = T>) -> Vec<T> {
+        for packet in packets {
+            self.outgoing.send(packet, &self.conditions);
+        }
+
+        self.outgoing.arrived()
+    }
+
+    /// Queue up packets we just read off the socket and get back the ones that should be handled now
+    pub fn incoming(&mut self, packets: impl IntoIterator<Item = T>) -> Vec<T> {
+        for packet in packets {
+            self.incoming.send(packet, &self.conditions);
+        }
+
+        self.incoming.arrived()
+    }
+
+    /// Throw away everything in flight, like a real connection dropping would
+    pub fn clear(&mut self) {
+        self.outgoing.in_flight.clear();
+        self.incoming.in_flight.clear();
+
+        self.outgoing.last_reliable_arrival = None;
+        self.incoming.last_reliable_arrival = None;
+    }
+}
@@ -1,13 +1,13 @@
 use core::f32;
-use std::{collections::HashMap, f32::consts::PI, mem::{swap, take}, path::PathBuf, str::FromStr, usize};
+use std::{f32::consts::PI, mem::{swap, take}, path::PathBuf, str::FromStr, usize};
 
 use cs_utils::drain_filter;
 use glamx::{Pose2, Vec2, vec2};
-use macroquad::{camera::Camera2D, color::{BLACK, WHITE}, input::{KeyCode, is_key_down, is_key_pressed, is_mouse_button_down, is_mouse_button_released, mouse_position, mouse_wheel}, shapes::draw_rectangle, text::{TextParams, draw_text, draw_text_ex}, window::{screen_height, screen_width}};
+use macroquad::{camera::Camera2D, color::{BLACK, WHITE}, input::{KeyCode, is_key_down, is_key_pressed, is_mouse_button_down, mouse_position}, shapes::draw_rectangle, text::{TextParams, draw_text, draw_text_ex, measure_text}, window::{screen_height, screen_width}};
 use rapier2d::{parry::query::Ray, prelude::{ImpulseJointHandle, QueryFilter, RevoluteJointBuilder, RigidBody, RigidBodyVelocity}};
 use serde::{Deserialize, Serialize};
 
-use crate::{ClientTickContext, DrawCommand, DrawRectangleParameters, DrawTextParameters, Owner, Prefabs, TextureLoader, TickContext, angle_weapon_to_mouse, area::{AreaContext, AreaId}, base_prop::{BaseProp, PropId, PropUpdateOwner}, body_part::BodyPart, bullet_trail::BulletTrail, dissolved_pixel::DissolvedPixel, drawable::{DrawContext, Drawable}, dropped_item::{DroppedItem, RemoveDroppedItemUpdate}, enemy::Enemy, font_loader::FontLoader, get_angle_between_rapier_points, inventory::Inventory, items::{Item, item_save::ItemSave}, mouse_world_pos, rapier_mouse_world_pos, rapier_to_macroquad, space::Space, texture_loader::ClientTextureLoader, tile::Tile, updates::NetworkPacket, uuid_u64, weapons::{bullet_impact_data::BulletImpactData, weapon::weapon::WeaponOwner, weapon_fire_context::WeaponFireContext, weapon_type_save::WeaponTypeSave}};
+use crate::{ClientTickContext, DrawCommand, DrawRectangleParameters, DrawTextParameters, Owner, Prefabs, TextureLoader, TickContext, angle_weapon_to_mouse, area::{AreaContext, AreaId}, base_prop::BaseProp, body_part::BodyPart, bullet_trail::BulletTrail, dissolved_pixel::DissolvedPixel, drawable::{DrawContext, Drawable}, dropped_item::{DroppedItem, RemoveDroppedItemUpdate}, enemy::Enemy, font_loader::FontLoader, get_angle_between_rapier_points, interpolation::{InterpolationBuffer, snapshot_timestamp}, player_movement::{MovementState, PlayerInput, PlayerInputUpdate, PlayerStateUpdate}, inventory::Inventory, items::{Item, item_save::ItemSave}, mouse_world_pos, rapier_mouse_world_pos, rapier_to_macroquad, space::Space, texture_loader::ClientTextureLoader, tile::Tile, updates::NetworkPacket, uuid_u64, weapons::{bullet_impact_data::BulletImpactData, weapon::weapon::WeaponOwner, weapon_fire_context::WeaponFireContext, weapon_type_save::WeaponTypeSave}};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Copy, Hash, Eq)]
 pub struct PlayerId {
@@ -66,6 +66,8 @@ pub struct Player {
     pub body: BodyPart,
     max_speed: Vec2,
     pub owner: Owner,
+    /// Display name shown above us to everyone else
+    pub name: String,
     previous_velocity: RigidBodyVelocity<f32>,
     head_joint_handle: Option<ImpulseJointHandle>,
     pub facing: Facing,
@@ -84,6 +86,8 @@ pub struct Player {
     pub despawn: bool,
     pub move_right_toggle: bool,
     pub move_left_toggle: bool,
+    pub movement: MovementState,
+    pub interpolation: InterpolationBuffer,
 }
 
 impl Player {
@@ -106,8 +110,8 @@ impl Player {
             item_slot.item.unequip(ctx, area_context, player_context);
         }
     }
-    pub fn dash(&mut self, space: &mut Space) {
-        if !is_key_down(KeyCode::LeftShift) {
+    pub fn dash(&mut self, input: &PlayerInput, space: &mut Space) {
+        if !input.dash {
             return
         }
 
@@ -117,13 +121,13 @@ impl Player {
 
         let body = space.rigid_body_set.get_mut(self.body.body_handle).unwrap();
 
-        if is_key_down(KeyCode::A) {
+        if input.left {
             body.apply_impulse(vec2(-1000000. * 0.4, 0.), true);
 
             self.last_dash = web_time::Instant::now();
         }
 
-        if is_key_down(KeyCode::D) {
+        if input.right {
             body.apply_impulse(vec2(1000000. * 0.4, 0.), true);
 
             self.last_dash = web_time::Instant::now();
@@ -281,6 +285,37 @@ impl Player {
 
     }
 
+    /// Name tag above the inventory. We already know who we are so we only see everyone else's
+    pub fn draw_name(&self, ctx: &mut TickContext, space: &Space) {
+
+        if self.name.is_empty() || self.owner == ctx.id() {
+            return;
+        }
+
+        let pos = space.rigid_body_set.get(self.body.body_handle).unwrap().position().translation;
+
+        let mpos = rapier_to_macroquad(pos);
+
+        let text_size = measure_text(&self.name, None, 24, 1.);
+
+        ctx.add_draw_command(
+            1,
+            DrawCommand::DrawText(
+                DrawTextParameters {
+                    text: self.name.clone(),
+                    position: macroquad::math::Vec2 {
+                        x: mpos.x - (text_size.width / 2.),
+                        y: mpos.y - 90.,
+                    },
+                    font_size: Some(24),
+                    color: Some(WHITE),
+                    font: Some(PathBuf::from("assets/fonts/CutePixel.ttf")),
+                    rotation: None,
+                }
+            )
+        );
+    }
+
     pub fn draw_inventory(&self, ctx: &mut TickContext, space: &Space) {
 
         let pos = space.rigid_body_set.get(self.body.body_handle).unwrap().position().translation;
@@ -413,11 +448,11 @@ impl Player {
 
 
         if is_key_down(KeyCode::LeftControl) {
-            if mouse_wheel().1 < 0. {
+            if ctx.input.mouse_wheel() < 0. {
                 self.desired_camera_width += 30.;
             }
 
-            if mouse_wheel().1 > 0. {
+            if ctx.input.mouse_wheel() > 0. {
                 self.desired_camera_width -= 30.;
             }
         }
@@ -500,6 +535,7 @@ impl Player {
             head,
             body,
             owner,
+            name: String::new(),
             previous_velocity: RigidBodyVelocity::zero(),
             head_joint_handle: Some(joint),
             facing: Facing::Right,
@@ -521,6 +557,8 @@ impl Player {
             move_right_toggle: false,
             desired_camera_width: 1280.,
             current_camera_width: 1280.,
+            movement: MovementState::new(),
+            interpolation: InterpolationBuffer::new(),
         }
     }
 
@@ -535,11 +573,11 @@ impl Player {
         if is_key_down(KeyCode::LeftControl) {
             return;
         }
-        if mouse_wheel().1 == 0. {
+        if ctx.input.mouse_wheel() == 0. {
             return;
         }
 
-        if mouse_wheel().1 < 0. {
+        if ctx.input.mouse_wheel() < 0. {
 
             if self.selected_item == 5 {
                 self.selected_item = 0;
@@ -561,7 +599,7 @@ impl Player {
 
 
 
-        } else if mouse_wheel().1 > 0. {
+        } else if ctx.input.mouse_wheel() > 0. {
             if self.selected_item == 0 {
                 self.selected_item = 5;
 
@@ -635,7 +673,12 @@ impl Player {
 
         };
 
-        if is_mouse_button_released(macroquad::input::MouseButton::Left) {
+        let released = match ctx {
+            TickContext::Client(ctx) => ctx.input.is_mouse_button_released(macroquad::input::MouseButton::Left),
+            _ => false,
+        };
+
+        if released {
             item_slot.item.use_released(ctx, area_context, &mut player_context.into());
         } else {
             if is_mouse_button_down(macroquad::input::MouseButton::Left) {
@@ -673,8 +716,7 @@ impl Player {
 
     pub fn control_controller(
         &mut self,
-        ctx: &mut ClientTickContext,
-        area_context: &mut AreaContext
+        input: &mut PlayerInput
     ) {
 
         // let body = space.rigid_body_set.get_mut(self.body.body_handle).unwrap();
@@ -732,15 +774,16 @@ impl Player {
         // if self.move_right_toggle {
         //     self.move_right(body);
         // }
+
+        input.left |= self.move_left_toggle;
+        input.right |= self.move_right_toggle;
     }
 
     pub fn move_left(
         &mut self,
-        ctx: &mut ClientTickContext,
-        area_context: &mut AreaContext
-
+        space: &mut Space
     ) {
-        let body = area_context.space.rigid_body_set.get_mut(self.body.body_handle).unwrap();
+        let body = space.rigid_body_set.get_mut(self.body.body_handle).unwrap();
 
         if body.linvel().x < -self.max_speed.x {
             return;
@@ -761,12 +804,10 @@ impl Player {
 
     pub fn move_right(
         &mut self,
-        ctx: &mut ClientTickContext,
-        area_context: &mut AreaContext
-
+        space: &mut Space
     ) {
 
-        let body = area_context.space.rigid_body_set.get_mut(self.body.body_handle).unwrap();
+        let body = space.rigid_body_set.get_mut(self.body.body_handle).unwrap();
 
         if body.linvel().x > self.max_speed.x {
             return;
@@ -785,23 +826,84 @@ impl Player {
         );
     }
 
-    pub fn control_mkb(
+    pub fn control_mkb(&self) -> PlayerInput {
+        PlayerInput {
+            sequence: 0,
+            left: is_key_down(KeyCode::A),
+            right: is_key_down(KeyCode::D),
+            jump: is_key_down(KeyCode::Space),
+            dash: is_key_down(KeyCode::LeftShift),
+        }
+    }
+
+    /// Runs on both the client (prediction) and the server (authoritative) so this needs to stay deterministic-ish
+    pub fn apply_input(&mut self, input: &PlayerInput, space: &mut Space) {
+
+        if input.jump {
+            self.jump(space);
+        }
+
+        if input.left {
+            self.move_left(space);
+        }
+
+        if input.right {
+            self.move_right(space);
+        }
+
+        self.dash(input, space);
+    }
+
+    /// Apply our input straight away and send it to the server so it can do the real simulation
+    pub fn predict_movement(
         &mut self,
         ctx: &mut ClientTickContext,
         area_context: &mut AreaContext
     ) {
+        let mut input = match ctx.typing {
+            true => PlayerInput::default(),
+            false => self.control_mkb(),
+        };
 
+        self.control_controller(&mut input);
 
-        if is_key_down(KeyCode::Space) {
-            self.jump(ctx, area_context);
+        if input.is_idle() {
+            return;
         }
 
-        if is_key_down(KeyCode::A) {
-            self.move_left(ctx, area_context);
-        }
-        if is_key_down(KeyCode::D) {
-            self.move_right(ctx, area_context);
-        }
+        input.sequence = self.movement.next_sequence();
+
+        self.apply_input(&input, area_context.space);
+
+        let predicted_pos = area_context.space.rigid_body_set.get(self.body.body_handle).unwrap().position().translation;
+
+        self.movement.record_prediction(input.sequence, predicted_pos);
+
+        ctx.network_io.send_network_packet(
+            PlayerInputUpdate {
+                area_id: *area_context.id,
+                player_id: self.id,
+                input,
+            }.into()
+        );
+    }
+
+    /// Move our predicted player towards the server's snapshot if we drifted too far
+    pub fn reconcile(&mut self, update: &PlayerStateUpdate, space: &mut Space) {
+
+        let body = space.rigid_body_set.get_mut(self.body.body_handle).unwrap();
+
+        let current_pos = body.position().translation;
+
+        let Some(correction) = self.movement.reconcile(update.last_processed_input, update.pos.translation, current_pos) else {
+            return;
+        };
+
+        let mut corrected_pos = *body.position();
+        corrected_pos.translation += correction;
+
+        // only the position gets corrected. the server's velocity is from before the inputs it hasnt seen yet, so using it would throw away what we predicted for them
+        body.set_position(corrected_pos, true);
     }
 
 
@@ -875,6 +977,10 @@ impl Player {
             );
         }
 
+        if let TickContext::Server(_) = ctx {
+            self.server_tick(ctx, area_context);
+        }
+
         self.unequip_previous_weapon(ctx, area_context);
         self.equip_selected_item(ctx, area_context);
 
@@ -1031,12 +1137,10 @@ impl Player {
 
     pub fn jump(
         &mut self,
-        ctx: &mut ClientTickContext,
-        area_context: &mut AreaContext
-
+        space: &mut Space
     ) {
 
-        let body = area_context.space.rigid_body_set.get_mut(self.body.body_handle).unwrap();
+        let body = space.rigid_body_set.get_mut(self.body.body_handle).unwrap();
 
         if body.linvel().y.abs() > 1. {
             return
@@ -1061,162 +1165,6 @@ impl Player {
         }
     }
 
-    pub fn send_position_network_update(
-        &mut self,
-        ctx: &mut TickContext,
-        space: &mut Space,
-        area_id: AreaId
-    ) {
-
-        let pos = space.rigid_body_set.get(self.body.body_handle).unwrap().position();
-
-        if (self.last_position_update.elapsed().as_secs_f32() > 3.) && *pos != self.previous_pos {
-
-            let packet = NetworkPacket::PlayerPositionUpdate(
-                PlayerPositionUpdate {
-                    area_id,
-                    pos: *pos,
-                    player_id: self.id,
-                }
-            );
-
-            ctx.send_network_packet(packet);
-
-            self.last_position_update = web_time::Instant::now();
-        }
-    }
-
-    pub fn send_velocity_network_update(
-        &mut self,
-        ctx: &mut TickContext,
-        area_id: AreaId,
-        space: &Space
-    ) {
-
-        let current_velocity = space.rigid_body_set.get(self.body.body_handle).unwrap().vels();
-
-        if self.previous_velocity != *current_velocity {
-
-            let packet = crate::updates::NetworkPacket::PlayerVelocityUpdate(
-                PlayerVelocityUpdate {
-                    id: self.id.clone(),
-                    area_id,
-                    velocity: *current_velocity
-
-                }
-            );
-
-            ctx.send_network_packet(packet);
-        }
-    }
-
-    pub fn own_nearby_props(
-        &self,
-        area_context: &mut AreaContext,
-        ctx: &mut TickContext
-    ) {
-
-        let mut distances: HashMap<PropId, (PlayerId, f32)> = HashMap::new();
-
-        
-        
-        for prop in &mut *area_context.props {
-            let prop_body = area_context.space.rigid_body_set.get(prop.rigid_body_handle()).unwrap();
-            let prop_pose = prop_body.position();
-            let prop_collider = area_context.space.collider_set.get(prop.collider_handle()).unwrap();
-            let prop_shape = prop_collider.shape();
-
-            let our_player_body = area_context.space.rigid_body_set.get(self.body.body_handle).unwrap();
-            let our_player_translation = our_player_body.translation();
-            
-            
-            let then = web_time::Instant::now();
-            let distance = prop_shape.distance_to_point(prop_pose, our_player_translation, true).abs();
-          
-            //let distance = (prop_body.translation() - our_player_body.translation()).length();
-
-            distances.insert(prop.id(), (self.id, distance));
-
-            for player in &mut *area_context.players {
-                let player_body = area_context.space.rigid_body_set.get(player.body.body_handle).unwrap();
-                
-                
-                let distance = prop_shape.distance_to_point(prop_pose, player_body.translation(), true);
-                
-                
-                if let Some((current_closest_player, current_closest_distance)) = distances.get_mut(&prop.id()) {
-                    if *current_closest_distance > distance {
-                        *current_closest_distance = distance;
-                        *current_closest_player = player.id
-                    }
-                }
-
-            }
-        }
-
-        for (prop_id, (closest_player, distance)) in distances {
-            if closest_player == self.id {
-
-                let prop = area_context.props.iter_mut().find(|prop| prop.id() == prop_id).unwrap();
-
-                if prop.last_ownership_change().elapsed().as_secs() < 1 {
-                    continue;
-                }
-
-                // if we are the closest but still far away we pass it to the server.
-                // this might improve performance for clients?
-                if distance > 300. {
-
-
-                    match prop.owner().unwrap() {
-                        Owner::ClientId(_) => {
-
-
-                            *prop.owner_mut() = Some(Owner::Server);
-
-                            *prop.last_ownership_change_mut() = web_time::Instant::now();
-
-                            ctx.send_network_packet(
-                                PropUpdateOwner {
-                                    owner: Some(Owner::Server),
-                                    id: prop_id,
-                                    area_id: *area_context.id,
-                                }.into()
-                            );
-                        },
-                        Owner::Server => {
-                            // already owned by the server, do nothing
-                        },
-                        Owner::Editor => todo!(),
-                    }
-
-                    continue;
-                }
-
-                // dont send an update if we already own the prop
-                if prop.owner().unwrap() == ctx.id() {
-                    continue;
-                }
-
-                *prop.owner_mut() = Some(ctx.id());
-
-                *prop.last_ownership_change_mut() = web_time::Instant::now();
-
-                ctx.send_network_packet(
-                    PropUpdateOwner {
-                        owner: Some(self.owner),
-                        id: prop_id,
-                        area_id: *area_context.id,
-                    }.into()
-                );
-
-
-
-            }
-        }
-
-
-    }
     pub fn owner_tick(
         &mut self,
         ctx: &mut TickContext,
@@ -1228,18 +1176,13 @@ impl Player {
             self.update_cursor_pos(ctx, area_context);
             self.change_active_inventory_slot(ctx, area_context);
             self.change_facing_direction(ctx, area_context);
-            self.control_controller(ctx, area_context);
-            self.control_mkb(ctx, area_context);
+            self.predict_movement(ctx, area_context);
             self.move_camera(ctx, area_context);
             self.face_towards_mouse(ctx, area_context);
         }
 
-        self.own_nearby_props(area_context, ctx);
         self.use_item(ctx, area_context);
-        self.send_position_network_update(ctx, area_context.space, *area_context.id);
-        self.dash(area_context.space);
         self.pickup_item(area_context.dropped_items, area_context.space, ctx, *area_context.id);
-        self.send_velocity_network_update(ctx, *area_context.id, area_context.space);
 
 
     }
@@ -1256,11 +1199,50 @@ impl Player {
         }
 
         player.id = save.id;
+        player.name = save.name;
         player
     }
 
-    pub fn server_tick(&mut self) {
+    /// Simulate the inputs our owner sent us and let everyone know where we ended up
+    pub fn server_tick(
+        &mut self,
+        ctx: &mut TickContext,
+        area_context: &mut AreaContext
+    ) {
+
+        if let Some(input) = self.movement.next_pending_input() {
+            self.apply_input(&input, area_context.space);
+
+            self.movement.last_processed_sequence = input.sequence;
+        }
+
+        if !self.movement.snapshot_due() {
+            return;
+        }
+
+        let body = area_context.space.rigid_body_set.get(self.body.body_handle).unwrap();
+
+        let pos = *body.position();
+        let velocity = *body.vels();
 
+        // nothing to tell anyone if we are just standing still
+        if !self.movement.has_unacked_inputs() && pos == self.previous_pos && velocity == self.previous_velocity {
+            return;
+        }
+
+        ctx.send_network_packet(
+            PlayerStateUpdate {
+                area_id: *area_context.id,
+                player_id: self.id,
+                last_processed_input: self.movement.last_processed_sequence,
+                pos,
+                velocity,
+                timestamp: snapshot_timestamp(),
+            }.into()
+        );
+
+        self.previous_pos = pos;
+        self.movement.snapshot_sent();
     }
 
     pub fn save(&self, space: &Space) -> PlayerSave {
@@ -1282,6 +1264,7 @@ impl Player {
             pos,
             id: self.id.clone(),
             owner: self.owner.clone(),
+            name: self.name.clone(),
             items
         }
     }
@@ -1298,6 +1281,7 @@ impl Player {
 
         self.draw_selected_item(ctx, space);
         self.draw_inventory(ctx, space);
+        self.draw_name(ctx, space);
 
         let pos = space.rigid_body_set.get(self.body.body_handle).unwrap().position().translation;
 
@@ -1362,18 +1346,14 @@ pub struct PlayerContext<'a> {
 #[derive(Serialize, Deserialize, Clone, )]
 pub struct PlayerSave {
     pos: Pose2,
-    owner: Owner,
-    id: PlayerId, // we arent storing the player as a prefab so the player will always have an id
+    pub owner: Owner,
+    pub id: PlayerId, // we arent storing the player as a prefab so the player will always have an id
+    /// The server overwrites this with the name the client said hello with
+    #[serde(default)]
+    pub name: String,
     items: Vec<Option<ItemSlotSave>>
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct PlayerVelocityUpdate {
-    pub id: PlayerId,
-    pub area_id: AreaId,
-    pub velocity: RigidBodyVelocity<f32>
-}
-
 #[derive(Serialize, Deserialize, Clone)]
 pub struct NewPlayer {
     pub player: PlayerSave,
@@ -1394,13 +1374,6 @@ pub struct PlayerFacingUpdate {
     pub facing: Facing
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct PlayerPositionUpdate {
-    pub area_id: AreaId,
-    pub pos: Pose2,
-    pub player_id: PlayerId
-}
-
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ItemSlotQuantityUpdate {
     pub area_id: AreaId,
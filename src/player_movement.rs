@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+
+use glamx::{Pose2, Vec2};
+use rapier2d::prelude::RigidBodyVelocity;
+use serde::{Deserialize, Serialize};
+
+use crate::{area::AreaId, player::PlayerId};
+
+/// How far the server can disagree with our prediction before we snap back to it
+const RECONCILE_THRESHOLD: f32 = 15.;
+
+/// Inputs older than this are never going to get acked so there is no point keeping them around
+const MAX_INPUT_HISTORY: usize = 256;
+
+/// Stop clients from flooding the server with inputs to speed themselves up
+const MAX_PENDING_INPUTS: usize = 16;
+
+pub const SNAPSHOT_INTERVAL: web_time::Duration = web_time::Duration::from_millis(50);
+
+/// The movement intents for a single client tick
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerInput {
+    pub sequence: u32,
+    pub left: bool,
+    pub right: bool,
+    pub jump: bool,
+    pub dash: bool
+}
+
+impl PlayerInput {
+    pub fn is_idle(&self) -> bool {
+        !(self.left || self.right || self.jump || self.dash)
+    }
+}
+
+struct PredictedInput {
+    sequence: u32,
+    predicted_pos: Vec2
+}
+
+/// Client side this holds the inputs we predicted but the server hasnt acked yet.
+/// Server side it holds the inputs we received but havent simulated yet.
+pub struct MovementState {
+    next_sequence: u32,
+    history: VecDeque<PredictedInput>,
+    pending_inputs: VecDeque<PlayerInput>,
+    pub last_processed_sequence: u32,
+    last_snapshot_sequence: u32,
+    last_snapshot: web_time::Instant
+}
+
+impl MovementState {
+    pub fn new() -> Self {
+        Self {
+            next_sequence: 1,
+            history: VecDeque::new(),
+            pending_inputs: VecDeque::new(),
+            last_processed_sequence: 0,
+            last_snapshot_sequence: 0,
+            last_snapshot: web_time::Instant::now(),
+        }
+    }
+
+    pub fn next_sequence(&mut self) -> u32 {
+        let sequence = self.next_sequence;
+
+        self.next_sequence += 1;
+
+        sequence
+    }
+
+    pub fn record_prediction(&mut self, sequence: u32, predicted_pos: Vec2) {
+        self.history.push_back(PredictedInput { sequence, predicted_pos });
+
+        if self.history.len() > MAX_INPUT_HISTORY {
+            self.history.pop_front();
+        }
+    }
+
+    /// Compare the server's position to what we predicted for the same input.
+    /// Returns how far we need to move the player to agree with the server, if at all
+    pub fn reconcile(&mut self, last_processed_sequence: u32, server_pos: Vec2, current_pos: Vec2) -> Option<Vec2> {
+
+        let mut predicted_pos = None;
+
+        while let Some(predicted_input) = self.history.front() {
+            if predicted_input.sequence > last_processed_sequence {
+                break;
+            }
+
+            if predicted_input.sequence == last_processed_sequence {
+                predicted_pos = Some(predicted_input.predicted_pos);
+            }
+
+            self.history.pop_front();
+        }
+
+        let predicted_pos = match predicted_pos {
+            Some(predicted_pos) => predicted_pos,
+            // nothing in flight so we should be exactly where the server says we are
+            None if self.history.is_empty() => current_pos,
+            // this snapshot is for an input we already dropped
+            None => return None,
+        };
+
+        let error = server_pos - predicted_pos;
+
+        if error.length() < RECONCILE_THRESHOLD {
+            return None;
+        }
+
+        Some(error)
+    }
+
+    pub fn queue_input(&mut self, input: PlayerInput) {
+
+        // old or duplicated input
+        if input.sequence <= self.last_processed_sequence {
+            return;
+        }
+
+        if self.pending_inputs.iter().any(|pending_input| pending_input.sequence == input.sequence) {
+            return;
+        }
+
+        self.pending_inputs.push_back(input);
+
+        if self.pending_inputs.len() > MAX_PENDING_INPUTS {
+            self.pending_inputs.pop_front();
+        }
+    }
+
+    /// The oldest input we haven't simulated yet. Only one gets simulated per tick, the same as on the client, so sending a burst of them doesn't make you faster
+    pub fn next_pending_input(&mut self) -> Option<PlayerInput> {
+        let (index, _) = self.pending_inputs.iter().enumerate().min_by_key(|(_, input)| input.sequence)?;
+
+        self.pending_inputs.remove(index)
+    }
+
+    pub fn snapshot_due(&self) -> bool {
+        self.last_snapshot.elapsed() >= SNAPSHOT_INTERVAL
+    }
+
+    /// The owner is still waiting for us to ack some inputs
+    pub fn has_unacked_inputs(&self) -> bool {
+        self.last_processed_sequence != self.last_snapshot_sequence
+    }
+
+    pub fn snapshot_sent(&mut self) {
+        self.last_snapshot_sequence = self.last_processed_sequence;
+        self.last_snapshot = web_time::Instant::now();
+    }
+}
+
+/// Sent by the owning client every tick it has movement input
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerInputUpdate {
+    pub area_id: AreaId,
+    pub player_id: PlayerId,
+    pub input: PlayerInput
+}
+
+/// Authoritative player state from the server
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlayerStateUpdate {
+    pub area_id: AreaId,
+    pub player_id: PlayerId,
+    /// Sequence of the last input from the owner that was simulated before this snapshot
+    pub last_processed_input: u32,
+    pub pos: Pose2,
+    pub velocity: RigidBodyVelocity<f32>,
+    /// When the sender took this, see interpolation::snapshot_timestamp
+    pub timestamp: u64
+}
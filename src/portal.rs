@@ -0,0 +1,77 @@
+use glamx::Vec2;
+use macroquad::color::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::{DrawCommand, DrawRectangleParameters, TickContext, rapier_to_macroquad};
+
+/// Walking into one of these sends the player to another area. Only the server acts on them
+pub struct Portal {
+    pub pos: Vec2,
+    pub size: Vec2,
+    pub destination: String,
+    pub destination_point: Option<Vec2>,
+    pub layer: u32
+}
+
+impl Portal {
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        let distance = (point - self.pos).abs();
+
+        distance.x <= self.size.x / 2. && distance.y <= self.size.y / 2.
+    }
+
+    pub fn draw(&self, ctx: &mut TickContext) {
+
+        // portals are invisible in game, the area should have a door decoration or something on top of them
+        let TickContext::Editor(_) = ctx else {
+            return
+        };
+
+        ctx.add_draw_command(
+            self.layer,
+            DrawCommand::DrawRectangle(
+                DrawRectangleParameters {
+                    position: rapier_to_macroquad(self.pos),
+                    size: macroquad::math::Vec2::new(self.size.x, self.size.y),
+                    offset: Some(macroquad::math::Vec2::new(0.5, 0.5)),
+                    rotation: None,
+                    color: Some(Color::new(0.5, 0., 1., 0.3)),
+                }
+            )
+        );
+    }
+
+    pub fn from_save(save: PortalSave) -> Self {
+        Self {
+            pos: save.pos,
+            size: save.size,
+            destination: save.destination,
+            destination_point: save.destination_point,
+            layer: save.layer,
+        }
+    }
+
+    pub fn save(&self) -> PortalSave {
+        PortalSave {
+            pos: self.pos,
+            size: self.size,
+            destination: self.destination.clone(),
+            destination_point: self.destination_point,
+            layer: self.layer,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PortalSave {
+    pub pos: Vec2,
+    pub size: Vec2,
+    /// Path of the area file this leads to, same as the one passed to the server
+    pub destination: String,
+    /// Where the player comes out. Defaults to the destination's spawn point
+    #[serde(default)]
+    pub destination_point: Option<Vec2>,
+    #[serde(default)]
+    pub layer: u32
+}
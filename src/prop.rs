@@ -4,7 +4,7 @@ use downcast_rs::{Downcast, impl_downcast};
 use macroquad::math::Rect;
 use rapier2d::prelude::{ColliderHandle, RigidBodyHandle};
 
-use crate::{Owner, TickContext, area::AreaContext, base_prop::{Material, PropId}, drawable::Drawable, prop_save::PropSave, space::Space, texture_loader::ClientTextureLoader, weapons::bullet_impact_data::BulletImpactData};
+use crate::{Owner, TickContext, area::AreaContext, base_prop::{Material, PropId}, drawable::Drawable, interpolation::InterpolationBuffer, prop_save::PropSave, space::Space, texture_loader::ClientTextureLoader, weapons::bullet_impact_data::BulletImpactData};
 
 
 impl_downcast!(Prop);
@@ -42,6 +42,8 @@ pub trait Prop: Downcast {
     }
     fn last_received_position_update(&self) -> web_time::Instant;
     fn last_received_position_update_mut(&mut self) -> &mut web_time::Instant;
+    fn interpolation_mut(&mut self) -> &mut InterpolationBuffer;
+    fn take_pending_removed_voxels(&mut self) -> Vec<glamx::IVec2>;
     fn mark_despawn(&mut self);
     fn draw_editor_context_menu(&self); // maybe we should actually use the trait 
     fn update_menu(&mut self, space: &mut Space, camera_rect: &Rect, selected: bool, textures: &ClientTextureLoader);
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use glamx::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::{ClientId, Owner, ServerIO, area::{Area, AreaId}, base_prop::{PropId, PropUpdateOwner}};
+
+/// Players closer than this can take a prop that nobody nearby is simulating
+const CLAIM_RADIUS: f32 = 200.;
+
+/// The owner keeps the prop until they get further away than this. Bigger than the claim radius so standing right on the edge doesn't flip it back and forth
+const RELEASE_RADIUS: f32 = 350.;
+
+/// Nobody can take a prop off a present owner until they have had it for this long
+const MIN_HOLD_TIME: web_time::Duration = web_time::Duration::from_millis(1500);
+
+/// Shooting a prop counts for this long when deciding who should have it
+const INTERACTION_WINDOW: web_time::Duration = web_time::Duration::from_secs(2);
+
+/// Distance checks against voxel shapes aren't free so we don't do it every tick
+const ARBITRATION_INTERVAL: web_time::Duration = web_time::Duration::from_millis(100);
+
+/// Clients send this when they shoot or otherwise mess with a prop they don't own. It's a hint, the server decides
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct PropInteraction {
+    pub area_id: AreaId,
+    pub prop_id: PropId
+}
+
+struct Interaction {
+    client_id: ClientId,
+    at: web_time::Instant
+}
+
+/// Server side. Decides which client simulates each prop so two clients never both think they own it
+pub struct PropOwnershipArbiter {
+    interactions: HashMap<PropId, Interaction>,
+    last_arbitration: web_time::Instant
+}
+
+impl PropOwnershipArbiter {
+    pub fn new() -> Self {
+        Self {
+            interactions: HashMap::new(),
+            last_arbitration: web_time::Instant::now(),
+        }
+    }
+
+    pub fn record_interaction(&mut self, prop_id: PropId, client_id: ClientId) {
+        self.interactions.insert(
+            prop_id,
+            Interaction {
+                client_id,
+                at: web_time::Instant::now(),
+            }
+        );
+    }
+
+    /// True once it's been long enough since the last round
+    pub fn due(&mut self) -> bool {
+        if self.last_arbitration.elapsed() < ARBITRATION_INTERVAL {
+            return false;
+        }
+
+        self.last_arbitration = web_time::Instant::now();
+
+        self.interactions.retain(|_, interaction| interaction.at.elapsed() < INTERACTION_WINDOW);
+
+        true
+    }
+
+    /// Pick an owner for every prop in the area and tell everyone about the ones that changed.
+    /// `can_own` filters out clients that shouldn't be simulating anything, like ones waiting to reconnect
+    pub fn arbitrate(&self, area: &mut Area, network_io: &mut ServerIO, can_own: impl Fn(ClientId) -> bool) {
+
+        let players: Vec<(ClientId, Vec2)> = area.players.iter()
+            .filter(|player| !player.despawn)
+            .filter_map(|player| {
+                match player.owner {
+                    Owner::ClientId(client_id) if can_own(client_id) => {
+                        let position = area.space.rigid_body_set.get(player.body.body_handle).unwrap().position().translation;
+
+                        Some((client_id, position))
+                    },
+                    _ => None
+                }
+            })
+            .collect();
+
+        for prop in &mut area.props {
+
+            let prop_pose = area.space.rigid_body_set.get(prop.rigid_body_handle()).unwrap().position();
+            let prop_shape = area.space.collider_set.get(prop.collider_handle()).unwrap().shape();
+
+            let distances: Vec<(ClientId, f32)> = players.iter()
+                .map(|(client_id, position)| (*client_id, prop_shape.distance_to_point(prop_pose, *position, true).abs()))
+                .collect();
+
+            let owner = self.decide(prop.id(), prop.owner(), prop.last_ownership_change().elapsed(), &distances);
+
+            if owner == prop.owner() {
+                continue;
+            }
+
+            *prop.owner_mut() = owner;
+            *prop.last_ownership_change_mut() = web_time::Instant::now();
+
+            network_io.send_all_clients(
+                PropUpdateOwner {
+                    owner,
+                    id: prop.id(),
+                    area_id: area.id,
+                }.into()
+            );
+        }
+    }
+
+    fn decide(&self, prop_id: PropId, current: Option<Owner>, held_for: web_time::Duration, distances: &[(ClientId, f32)]) -> Option<Owner> {
+
+        let distance_of = |client_id: ClientId| {
+            distances.iter()
+                .find(|(candidate, _)| *candidate == client_id)
+                .map(|(_, distance)| *distance)
+        };
+
+        // a client that left or went through a portal loses its props straight away
+        let current_present = match current {
+            Some(Owner::ClientId(client_id)) => distance_of(client_id).is_some(),
+            Some(_) => true,
+            None => false,
+        };
+
+        if current_present && held_for < MIN_HOLD_TIME {
+            return current;
+        }
+
+        // whoever shot it last gets it so they see it react to their shots without lag
+        let interactor = self.interactions.get(&prop_id)
+            .map(|interaction| interaction.client_id)
+            .filter(|client_id| distance_of(*client_id).is_some_and(|distance| distance <= RELEASE_RADIUS));
+
+        if let Some(client_id) = interactor {
+            return Some(Owner::ClientId(client_id));
+        }
+
+        if let Some(Owner::ClientId(client_id)) = current && distance_of(client_id).is_some_and(|distance| distance <= RELEASE_RADIUS) {
+            return current;
+        }
+
+        let closest = distances.iter()
+            .filter(|(_, distance)| *distance <= CLAIM_RADIUS)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        match closest {
+            Some((client_id, _)) => Some(Owner::ClientId(*client_id)),
+            // nobody is close enough to bother so the server looks after it
+            None => Some(Owner::Server),
+        }
+    }
+}
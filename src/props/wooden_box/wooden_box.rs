@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use crate::{Owner, TickContext, area::AreaContext, base_prop::{self, BaseProp, Material, PropId}, drawable::Drawable, prop::Prop, prop_save::PropSave, space::Space, texture_loader::ClientTextureLoader, weapons::bullet_impact_data::BulletImpactData};
+use crate::{Owner, TickContext, area::AreaContext, base_prop::{self, BaseProp, Material, PropId}, drawable::Drawable, interpolation::InterpolationBuffer, prop::Prop, prop_save::PropSave, space::Space, texture_loader::ClientTextureLoader, weapons::bullet_impact_data::BulletImpactData};
 use async_trait::async_trait;
 use delegate::delegate;
 use macroquad::math::Rect;
@@ -43,6 +43,8 @@ impl Prop for WoodenBox {
             fn voxels_modified_mut(&mut self) -> &mut bool;
             fn last_received_position_update(&self) -> web_time::Instant;
             fn last_received_position_update_mut(&mut self) -> &mut web_time::Instant;
+            fn interpolation_mut(&mut self) -> &mut InterpolationBuffer;
+            fn take_pending_removed_voxels(&mut self) -> Vec<glamx::IVec2>;
             fn mark_despawn(&mut self);
             fn draw_editor_context_menu(&self);
             fn update_menu(&mut self, space: &mut Space, camera_rect: &Rect, selected: bool, textures: &ClientTextureLoader);
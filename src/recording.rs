@@ -0,0 +1,152 @@
+use std::{fs::File, io::{BufRead, BufReader, BufWriter, Write}, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{fixed_timestep::DEFAULT_TICK_RATE, updates::{LoadArea, NetworkPacket}};
+
+fn default_tick_rate() -> u32 {
+    DEFAULT_TICK_RATE
+}
+
+/// Written once at the top of the file. Every area as it was when the recording started, spawn area first
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecordingHeader {
+    pub areas: Vec<LoadArea>,
+    /// What the server was ticking at when the recording started. Changes after that are in the packets
+    #[serde(default = "default_tick_rate")]
+    pub tick_rate: u32
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecordedPacket {
+    /// Time since the recording started
+    pub time: web_time::Duration,
+    pub packet: NetworkPacket
+}
+
+/// Writes every packet the server sends out to a file so the match can be watched again later.
+/// One json value per line because saves use typetag which bitcode can't do
+pub struct Recorder {
+    writer: BufWriter<File>,
+    started: web_time::Instant,
+    failed: bool
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>, header: &RecordingHeader) -> Result<Self, String> {
+
+        let path = path.as_ref();
+
+        let file = File::create(path)
+            .map_err(|error| format!("failed to create recording {:?}: {}", path, error))?;
+
+        let mut recorder = Self {
+            writer: BufWriter::new(file),
+            started: web_time::Instant::now(),
+            failed: false,
+        };
+
+        recorder.write_line(header)
+            .map_err(|error| format!("failed to write recording header to {:?}: {}", path, error))?;
+
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, packet: &NetworkPacket) {
+
+        // clients send these to us, they don't say anything about the match
+        if let NetworkPacket::Ping(_) | NetworkPacket::ClientViewUpdate(_) = packet {
+            return;
+        }
+
+        let recorded = RecordedPacket {
+            time: self.started.elapsed(),
+            packet: packet.clone(),
+        };
+
+        if let Err(error) = self.write_line(&recorded) {
+            self.fail(error);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Err(error) = self.writer.flush() {
+            self.fail(error.to_string());
+        }
+    }
+
+    fn write_line(&mut self, value: &impl Serialize) -> Result<(), String> {
+
+        // one bad write and the rest of the file is garbage, so just stop
+        if self.failed {
+            return Ok(());
+        }
+
+        serde_json::to_writer(&mut self.writer, value).map_err(|error| error.to_string())?;
+
+        self.writer.write_all(b"\n").map_err(|error| error.to_string())
+    }
+
+    fn fail(&mut self, error: String) {
+        if !self.failed {
+            log::error!("Failed to write to recording, stopping recording: {}", error);
+        }
+
+        self.failed = true;
+    }
+}
+
+/// A recording read back in
+pub struct Recording {
+    pub header: RecordingHeader,
+    /// In the order they were sent
+    pub packets: Vec<RecordedPacket>
+}
+
+impl Recording {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+
+        let path = path.as_ref();
+
+        let file = File::open(path)
+            .map_err(|error| format!("failed to open recording {:?}: {}", path, error))?;
+
+        let mut lines = BufReader::new(file).lines();
+
+        let header_line = lines.next()
+            .ok_or_else(|| format!("recording {:?} is empty", path))?
+            .map_err(|error| format!("failed to read recording {:?}: {}", path, error))?;
+
+        let header: RecordingHeader = serde_json::from_str(&header_line)
+            .map_err(|error| format!("failed to parse recording header in {:?}: {}", path, error))?;
+
+        let mut packets = Vec::new();
+
+        for (index, line) in lines.enumerate() {
+
+            let line = line.map_err(|error| format!("failed to read recording {:?}: {}", path, error))?;
+
+            if line.is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str(&line) {
+                Ok(packet) => packets.push(packet),
+                Err(error) => {
+                    // the server probably got killed halfway through writing the last line
+                    log::warn!("Stopping at bad packet on line {} of {:?}: {}", index + 2, path, error);
+                    break;
+                },
+            }
+        }
+
+        Ok(Self {
+            header,
+            packets,
+        })
+    }
+
+    pub fn duration(&self) -> web_time::Duration {
+        self.packets.last().map(|packet| packet.time).unwrap_or_default()
+    }
+}
@@ -0,0 +1,129 @@
+use glamx::Vec2;
+use interceptors_lib::{enemy_archetype::DEFAULT_ENEMY_ARCHETYPE, network_conditions::NetworkConditions};
+
+pub const HELP: &str = "\
+help                               show this
+clients                            list connected clients and their ping
+areas                              list loaded areas
+kick <client>                      disconnect a client and stop them reconnecting
+move <client> <area>               send a client's player to another area
+reload <area>                      reload an area from its file, players in it stay
+spawn enemy <area> <x> <y> [prefabs/enemies/...]
+spawn prop <area> <prefab> <x> <y>
+wave start <area>                  skip the break and start the next wave
+wave skip <area>                   end the current wave, despawning its enemies
+tickrate <ticks per second>        change the simulation rate
+netsim <latency ms> <jitter ms> <loss %>  simulate a bad connection, 0 0 0 turns it off
+
+clients can be given by id or name, areas by index or file path";
+
+/// Everything an admin can do to a running server. Only the console sends these for now but anything that can produce a line of text could
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    Help,
+    Clients,
+    Areas,
+    Kick {
+        client: String
+    },
+    Move {
+        client: String,
+        area: String
+    },
+    Reload {
+        area: String
+    },
+    SpawnEnemy {
+        area: String,
+        pos: Vec2,
+        archetype: String
+    },
+    SpawnProp {
+        area: String,
+        prefab: String,
+        pos: Vec2
+    },
+    StartWave {
+        area: String
+    },
+    SkipWave {
+        area: String
+    },
+    TickRate(u32),
+    NetworkConditions(NetworkConditions)
+}
+
+impl AdminCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let command = match words.as_slice() {
+            ["help"] | ["?"] => AdminCommand::Help,
+            ["clients"] => AdminCommand::Clients,
+            ["areas"] => AdminCommand::Areas,
+            ["kick", client] => AdminCommand::Kick {
+                client: client.to_string()
+            },
+            ["move", client, area] => AdminCommand::Move {
+                client: client.to_string(),
+                area: area.to_string()
+            },
+            ["reload", area] => AdminCommand::Reload {
+                area: area.to_string()
+            },
+            ["spawn", "enemy", area, x, y, rest @ ..] => AdminCommand::SpawnEnemy {
+                area: area.to_string(),
+                pos: parse_position(x, y)?,
+                archetype: match rest {
+                    [] => DEFAULT_ENEMY_ARCHETYPE.to_string(),
+                    [archetype] => archetype.to_string(),
+                    _ => return Err("too many arguments".to_string()),
+                },
+            },
+            ["spawn", "prop", area, prefab, x, y] => AdminCommand::SpawnProp {
+                area: area.to_string(),
+                prefab: prefab.to_string(),
+                pos: parse_position(x, y)?
+            },
+            ["wave", "start", area] => AdminCommand::StartWave {
+                area: area.to_string()
+            },
+            ["wave", "skip", area] => AdminCommand::SkipWave {
+                area: area.to_string()
+            },
+            ["tickrate", tick_rate] => {
+                let tick_rate: u32 = parse_number(tick_rate)?;
+
+                if tick_rate == 0 {
+                    return Err("tick rate has to be more than 0".to_string());
+                }
+
+                AdminCommand::TickRate(tick_rate)
+            },
+            ["netsim", latency, jitter, loss] => {
+                let loss: f32 = parse_number(loss)?;
+
+                if !(0. ..=100.).contains(&loss) {
+                    return Err("loss has to be between 0 and 100".to_string());
+                }
+
+                AdminCommand::NetworkConditions(
+                    NetworkConditions::new(parse_number(latency)?, parse_number(jitter)?, loss)
+                )
+            },
+            [] => return Err("empty command".to_string()),
+            [command, ..] => return Err(format!("unknown command or wrong arguments for '{}', try help", command)),
+        };
+
+        Ok(command)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("'{}' isn't a valid number", text))
+}
+
+fn parse_position(x: &str, y: &str) -> Result<Vec2, String> {
+    Ok(Vec2::new(parse_number(x)?, parse_number(y)?))
+}
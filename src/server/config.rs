@@ -0,0 +1,239 @@
+use std::{fs::read_to_string, path::PathBuf};
+
+use clap::Parser;
+use interceptors_lib::{fixed_timestep::{DEFAULT_MAX_CATCH_UP_STEPS, DEFAULT_TICK_RATE}, network_conditions::NetworkConditions};
+use serde::{Deserialize, Serialize};
+
+#[derive(Parser)]
+pub struct Args {
+    /// Optional json config file. Arguments passed on the command line override values in the file
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    #[arg(long)]
+    pub address: Option<String>,
+    #[arg(long)]
+    pub port: Option<u16>,
+    /// Server ticks per second
+    #[arg(long)]
+    pub tick_rate: Option<u32>,
+    /// Most ticks we will run back to back to catch up after falling behind
+    #[arg(long)]
+    pub max_catch_up_steps: Option<u32>,
+    /// Area files to load, can be passed multiple times
+    #[arg(long = "area")]
+    pub areas: Vec<String>,
+    /// Area that new clients are sent to. Defaults to the first loaded area
+    #[arg(long)]
+    pub spawn_area: Option<String>,
+    /// Area the lobby sends everyone to once they ready up. The lobby is disabled without one
+    #[arg(long)]
+    pub match_area: Option<String>,
+    /// Seconds between everyone readying up and the match starting
+    #[arg(long)]
+    pub countdown: Option<f32>,
+    /// Record every packet we send to this file so the match can be replayed with the client's --replay
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+    /// Fake latency added to every packet in each direction, in milliseconds
+    #[arg(long)]
+    pub sim_latency: Option<u64>,
+    /// How much the fake latency varies either way, in milliseconds
+    #[arg(long)]
+    pub sim_jitter: Option<u64>,
+    /// Percent of position and velocity updates to drop in each direction
+    #[arg(long)]
+    pub sim_loss: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ServerConfig {
+    #[serde(default = "default_address")]
+    pub address: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_tick_rate")]
+    pub tick_rate: u32,
+    #[serde(default = "default_max_catch_up_steps")]
+    pub max_catch_up_steps: u32,
+    #[serde(default = "default_areas")]
+    pub areas: Vec<String>,
+    #[serde(default)]
+    pub spawn_area: Option<String>,
+    #[serde(default)]
+    pub match_area: Option<String>,
+    #[serde(default = "default_countdown")]
+    pub countdown: f32,
+    #[serde(default)]
+    pub record: Option<PathBuf>,
+    #[serde(default)]
+    pub sim_latency: u64,
+    #[serde(default)]
+    pub sim_jitter: u64,
+    #[serde(default)]
+    pub sim_loss: f32
+}
+
+fn default_address() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_port() -> u16 {
+    5560
+}
+
+fn default_tick_rate() -> u32 {
+    DEFAULT_TICK_RATE
+}
+
+fn default_max_catch_up_steps() -> u32 {
+    DEFAULT_MAX_CATCH_UP_STEPS
+}
+
+fn default_countdown() -> f32 {
+    5.
+}
+
+fn default_areas() -> Vec<String> {
+    vec!["areas/new_area.json".to_string()]
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: default_address(),
+            port: default_port(),
+            tick_rate: default_tick_rate(),
+            max_catch_up_steps: default_max_catch_up_steps(),
+            areas: default_areas(),
+            spawn_area: None,
+            match_area: None,
+            countdown: default_countdown(),
+            record: None,
+            sim_latency: 0,
+            sim_jitter: 0,
+            sim_loss: 0.,
+        }
+    }
+}
+
+impl ServerConfig {
+
+    /// Build the config from the config file (if there is one) and then apply the command line overrides
+    pub fn from_args(args: Args) -> Result<Self, String> {
+
+        let mut config = match &args.config {
+            Some(config_path) => {
+                let config_json = read_to_string(config_path)
+                    .map_err(|error| format!("failed to read config file {:?}: {}", config_path, error))?;
+
+                serde_json::from_str(&config_json)
+                    .map_err(|error| format!("failed to parse config file {:?}: {}", config_path, error))?
+            },
+            None => ServerConfig::default(),
+        };
+
+        if let Some(address) = args.address {
+            config.address = address;
+        }
+
+        if let Some(port) = args.port {
+            config.port = port;
+        }
+
+        if let Some(tick_rate) = args.tick_rate {
+            config.tick_rate = tick_rate;
+        }
+
+        if let Some(max_catch_up_steps) = args.max_catch_up_steps {
+            config.max_catch_up_steps = max_catch_up_steps;
+        }
+
+        if !args.areas.is_empty() {
+            config.areas = args.areas;
+        }
+
+        if let Some(spawn_area) = args.spawn_area {
+            config.spawn_area = Some(spawn_area);
+        }
+
+        if let Some(match_area) = args.match_area {
+            config.match_area = Some(match_area);
+        }
+
+        if let Some(countdown) = args.countdown {
+            config.countdown = countdown;
+        }
+
+        if let Some(record) = args.record {
+            config.record = Some(record);
+        }
+
+        if let Some(sim_latency) = args.sim_latency {
+            config.sim_latency = sim_latency;
+        }
+
+        if let Some(sim_jitter) = args.sim_jitter {
+            config.sim_jitter = sim_jitter;
+        }
+
+        if let Some(sim_loss) = args.sim_loss {
+            config.sim_loss = sim_loss;
+        }
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.areas.is_empty() {
+            return Err("at least one area must be loaded".to_string());
+        }
+
+        if self.tick_rate == 0 {
+            return Err("tick rate must be greater than 0".to_string());
+        }
+
+        if self.max_catch_up_steps == 0 {
+            return Err("max catch up steps must be greater than 0".to_string());
+        }
+
+        if let Some(spawn_area) = &self.spawn_area {
+            if !self.areas.contains(spawn_area) {
+                return Err(format!("spawn area {} is not in the list of loaded areas", spawn_area));
+            }
+        }
+
+        if let Some(match_area) = &self.match_area {
+            if !self.areas.contains(match_area) {
+                return Err(format!("match area {} is not in the list of loaded areas", match_area));
+            }
+
+            if self.spawn_area.as_ref().unwrap_or(&self.areas[0]) == match_area {
+                return Err("match area can't be the same as the spawn area".to_string());
+            }
+        }
+
+        if self.countdown < 0. {
+            return Err("countdown can't be negative".to_string());
+        }
+
+        if !(0. ..=100.).contains(&self.sim_loss) {
+            return Err("simulated loss must be between 0 and 100 percent".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn lobby_countdown(&self) -> web_time::Duration {
+        web_time::Duration::from_secs_f32(self.countdown)
+    }
+
+    pub fn network_conditions(&self) -> NetworkConditions {
+        NetworkConditions::new(self.sim_latency, self.sim_jitter, self.sim_loss)
+    }
+
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.address, self.port)
+    }
+}
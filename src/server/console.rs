@@ -0,0 +1,41 @@
+use std::{io::BufRead, sync::mpsc::{Receiver, channel}};
+
+/// Reads commands typed into the server's terminal. Stdin blocks so it gets its own thread and the game loop just picks up whatever lines are ready
+pub struct Console {
+    lines: Receiver<String>
+}
+
+impl Console {
+    pub fn spawn() -> Self {
+
+        let (sender, lines) = channel();
+
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+
+                // the server is gone
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+
+            // stdin was closed, which is normal when running as a service
+            log::debug!("Console stopped reading stdin");
+        });
+
+        Self {
+            lines,
+        }
+    }
+
+    /// Lines typed since the last call. Never blocks
+    pub fn poll(&self) -> Vec<String> {
+        self.lines.try_iter()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+}
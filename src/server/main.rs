@@ -1,11 +1,33 @@
-use crate::server::Server;
+use std::process::exit;
+
+use clap::Parser;
+
+use crate::{config::{Args, ServerConfig}, server::Server};
 
 pub mod server;
+pub mod config;
+pub mod server_error;
+pub mod admin;
+pub mod console;
 
 pub fn main() {
     pretty_env_logger::init();
 
-    let mut server = Server::new();
+    let config = match ServerConfig::from_args(Args::parse()) {
+        Ok(config) => config,
+        Err(error) => {
+            log::error!("Invalid server config: {}", error);
+            exit(1);
+        },
+    };
+
+    let mut server = match Server::new(&config) {
+        Ok(server) => server,
+        Err(error) => {
+            log::error!("Failed to start server: {}", error);
+            exit(1);
+        },
+    };
 
     server.run();
-}
\ No newline at end of file
+}
@@ -1,80 +1,487 @@
-use std::{fs::read_to_string, process::exit};
+use std::{collections::{HashMap, HashSet}, fs::read_to_string};
 
-use glamx::Pose2;
-use interceptors_lib::{ClientId, Owner, Prefabs, ServerAssets, ServerIO, ServerTickContext, TickContext, area::{Area, AreaId, AreaSave}, base_prop::{BaseProp, PropUpdateOwner}, bullet_trail::BulletTrail, dissolved_pixel::DissolvedPixel, dropped_item::DroppedItem, enemy::Enemy, load_assets_server, load_prefabs, player::{ItemSlot, Player}, updates::{LoadArea, NetworkPacket, PlayerDespawnUpdate}, world::World};
-use macroquad::color::WHITE;
-use rapier2d::{geometry::VoxelData, parry::utils::hashset::HashSet, prelude::SharedShape};
+use interceptors_lib::{ClientId, base_prop_save::BasePropSave, prop_save::PropSave, chat::{ChatMessage, ChatRateLimiter, ChatSender, MAX_CHAT_MESSAGE_LENGTH, sanitize_chat_text}, enemy_perception::Noise, codec, fixed_timestep::{FixedTimestep, TickRateUpdate}, Owner, Prefabs, ServerAssets, ServerIO, ServerTickContext, TickContext, area::{Area, AreaId, AreaSave}, base_prop::{BaseProp, NewProp, PropId}, bullet_trail::BulletTrail, dropped_item::DroppedItem, enemy::{Enemy, EnemyDespawnUpdate, EnemyId, NewEnemyUpdate}, load_assets_server, load_prefabs, lobby::{GamePhase, GamePhaseUpdate, PlayerLeftLobby, PlayerReadyUpdate}, player::{ItemSlot, NewPlayer, Player, PlayerId}, player_movement::MovementState, prop::Prop, prop_ownership::PropOwnershipArbiter, recording::{Recorder, RecordingHeader}, updates::{LoadArea, NetworkPacket, PlayerDespawnUpdate}, world::World};
 use tungstenite::Message;
 
+use crate::{admin::{AdminCommand, HELP}, config::ServerConfig, console::Console, server_error::ServerError};
+
 pub struct Server {
     world: World,
     prefabs: Prefabs,
-    last_tick: web_time::Instant,
-    last_tick_duration: web_time::Duration,
     network_io: ServerIO,
     total_bits_sent: usize,
     previous_tick_connected_clients: Vec<ClientId>,
-    assets: ServerAssets
+    assets: ServerAssets,
+    spawn_area: AreaId,
+    timestep: FixedTimestep,
+    /// Clients that dropped recently. Their players stick around so they can pick them back up if they reconnect
+    disconnected_clients: HashMap<ClientId, web_time::Instant>,
+    /// Portals point at areas by the path they were loaded from
+    area_paths: HashMap<String, AreaId>,
+    /// When each client last went through a portal, so they dont bounce straight back through the one they came out of
+    portal_cooldowns: HashMap<ClientId, web_time::Instant>,
+    /// Where the lobby sends everyone once they are ready. None if we arent running a lobby
+    match_area: Option<MatchArea>,
+    phase: GamePhase,
+    ready_clients: HashSet<ClientId>,
+    lobby_countdown: web_time::Duration,
+    countdown_end: web_time::Instant,
+    chat_rate_limiters: HashMap<ClientId, ChatRateLimiter>,
+    /// Round trip times the clients measured and told us about
+    client_latencies: HashMap<ClientId, web_time::Duration>,
+    console: Console,
+    prop_ownership: PropOwnershipArbiter
+}
+
+/// We keep the save around so the area can be reset between matches
+struct MatchArea {
+    id: AreaId,
+    save: AreaSave
+}
+
+/// The replay starts from this, so it needs every area. Spawn area goes first since that's where the action starts
+fn recording_header(world: &World, spawn_area: AreaId, tick_rate: u32) -> RecordingHeader {
+    let mut areas: Vec<LoadArea> = world.areas.iter()
+        .map(|area| LoadArea { area: area.save(), id: area.id })
+        .collect();
+
+    areas.sort_by_key(|load_area| load_area.id != spawn_area);
+
+    RecordingHeader {
+        areas,
+        tick_rate,
+    }
 }
 
+/// How long a disconnected client's player is kept around waiting for them to reconnect
+const RECONNECT_GRACE_PERIOD: web_time::Duration = web_time::Duration::from_secs(30);
+
+const PORTAL_COOLDOWN: web_time::Duration = web_time::Duration::from_secs(2);
+
+struct AreaTransfer {
+    client_id: ClientId,
+    player_id: PlayerId,
+    from: AreaId,
+    to: AreaId,
+    destination_point: Option<glamx::Vec2>
+}
+
+
 impl Server {
-    pub fn new() -> Self {
+    pub fn new(config: &ServerConfig) -> Result<Self, String> {
 
         let mut world = World::empty();
 
         let assets = load_assets_server();
 
+        let prefabs = load_prefabs();
 
-        //let lobby_save: AreaSave = serde_json::from_str(&read_to_string("areas/ship.json").unwrap()).unwrap();
-        let forest_save: AreaSave = serde_json::from_str(&read_to_string("areas/new_area.json").unwrap()).unwrap();
+        let mut spawn_area = None;
+        let mut area_paths = HashMap::new();
+        let mut match_area = None;
 
+        for area_path in &config.areas {
 
+            let area_json = read_to_string(area_path)
+                .map_err(|error| format!("failed to read area {}: {}", area_path, error))?;
 
-        let prefabs = load_prefabs();
+            let area_save: AreaSave = serde_json::from_str(&area_json)
+                .map_err(|error| format!("failed to parse area {}: {}", area_path, error))?;
 
-        let forest = Area::from_save(forest_save, None, &prefabs, (&assets.textures).into());
+            let pristine_save = area_save.clone();
 
-        //forest.generate_terrain(0);
+            let area = Area::from_save(area_save, None, &prefabs, (&assets.textures).into());
 
-        world.areas.push(forest);
+            if config.match_area.as_ref() == Some(area_path) {
+                match_area = Some(MatchArea { id: area.id, save: pristine_save });
+            }
+
+            log::info!("Loaded area {}", area_path);
+
+            if config.spawn_area.as_ref() == Some(area_path) {
+                spawn_area = Some(area.id);
+            }
+
+            area_paths.insert(area_path.clone(), area.id);
+
+            world.areas.push(area);
+        }
 
+        // default to the first area if a spawn area wasnt designated
+        let spawn_area = spawn_area.unwrap_or(world.areas[0].id);
 
+        let mut network_io = ServerIO::new(&config.bind_address())
+            .map_err(|error| format!("failed to bind listener on {}: {}", config.bind_address(), error))?;
 
+        network_io.network_simulator.conditions = config.network_conditions();
 
-        Self {
-            last_tick: web_time::Instant::now(),
-            last_tick_duration: web_time::Duration::from_micros(1),
-            network_io: ServerIO::new(),
+        if network_io.network_simulator.conditions.is_active() {
+            log::warn!("Simulating network conditions: {}", network_io.network_simulator.conditions);
+        }
+
+        if let Some(record_path) = &config.record {
+            network_io.recorder = Some(Recorder::create(record_path, &recording_header(&world, spawn_area, config.tick_rate))?);
+
+            log::info!("Recording to {:?}", record_path);
+        }
+
+        log::info!("Listening on {}", config.bind_address());
+
+        Ok(Self {
+            network_io,
             world,
             total_bits_sent: 0,
             previous_tick_connected_clients: Vec::new(),
             prefabs,
-            assets
-        }
+            assets,
+            spawn_area,
+            timestep: FixedTimestep::new(config.tick_rate, config.max_catch_up_steps),
+            disconnected_clients: HashMap::new(),
+            area_paths,
+            portal_cooldowns: HashMap::new(),
+            match_area,
+            phase: GamePhase::Lobby,
+            ready_clients: HashSet::new(),
+            lobby_countdown: config.lobby_countdown(),
+            countdown_end: web_time::Instant::now(),
+            chat_rate_limiters: HashMap::new(),
+            client_latencies: HashMap::new(),
+            console: Console::spawn(),
+            prop_ownership: PropOwnershipArbiter::new()
+        })
 
     }
 
 pub fn handle_new_client(&mut self, new_client: ClientId) {
 
+        let name = self.client_name(new_client);
 
-        self.network_io.send_client(new_client, NetworkPacket::LoadArea(
+        if self.disconnected_clients.remove(&new_client).is_some() {
+            log::info!("Client {:?} reconnected", new_client);
 
-            // TEMPORARILY JUST SENDING THE FIRST AREA BUT WE NEED A WAY TO DESIGNATE A SPAWN AREA
-            LoadArea {
-                area: self.world.areas[0].save(),
-                id: self.world.areas[0].id
-            }
-        ));
+            self.network_io.send_all_clients(ChatMessage::system(None, format!("{} reconnected", name)).into());
 
-        // if this is the first client we give them ownership of all the props
-        if self.network_io.clients.len() == 1 {
+            // the client reloads the area so its input sequence starts over
             for area in &mut self.world.areas {
-                for prop in &mut area.props {
-                    *prop.owner_mut() = Some(Owner::ClientId(new_client));
-                    self.network_io.send_all_clients(NetworkPacket::PropUpdateOwner(PropUpdateOwner{ owner: Some(Owner::ClientId(new_client)), id: prop.id(), area_id: area.id }));
+                for player in area.players.iter_mut().filter(|player| player.owner == Owner::ClientId(new_client)) {
+                    player.movement = MovementState::new();
+                }
+            }
+        } else {
+            self.network_io.send_all_clients(ChatMessage::system(None, format!("{} joined", name)).into());
+        }
+
+
+        // reconnecting clients go back to whichever area their player went through a portal to
+        let area_id = self.world.areas.iter()
+            .find(|area| area.players.iter().any(|player| player.owner == Owner::ClientId(new_client) && !player.despawn))
+            .map(|area| area.id)
+            .unwrap_or(self.spawn_area);
+
+        let area = self.world.areas.iter().find(|area| area.id == area_id).unwrap();
+
+        let load_area = LoadArea {
+            area: area.save(),
+            id: area.id
+        };
+
+        // stops them getting packets for areas they havent loaded
+        self.network_io.set_client_area(new_client, area_id);
+
+        // prediction only lines up if they step at the same rate we do
+        self.network_io.send_client(new_client, TickRateUpdate { tick_rate: self.timestep.tick_rate() }.into());
+
+        self.network_io.send_client(new_client, NetworkPacket::LoadArea(load_area));
+
+        if self.match_area.is_some() {
+            self.network_io.send_client(new_client, GamePhaseUpdate { phase: self.current_phase() }.into());
+
+            // everyone needs to know about everyone else so the lobby can list them
+            for client_id in self.get_connected_clients_vector() {
+                if client_id == new_client {
+                    continue;
+                }
+
+                let ready = self.ready_clients.contains(&client_id);
+
+                self.network_io.send_client(new_client, PlayerReadyUpdate { client_id, ready }.into());
+            }
+
+            self.ready_clients.remove(&new_client);
+
+            self.network_io.send_all_clients(PlayerReadyUpdate { client_id: new_client, ready: false }.into());
+        }
+    }
+
+    /// The name they said hello with
+    fn client_name(&self, client_id: ClientId) -> String {
+        self.network_io.client_names.get(&client_id).cloned().unwrap_or_else(|| "Player".to_string())
+    }
+
+    /// Check the limits, fill in who actually sent it and pass it on to everyone
+    fn relay_chat_message(&mut self, client_id: ClientId, message: &ChatMessage) {
+
+        let text = sanitize_chat_text(&message.text, MAX_CHAT_MESSAGE_LENGTH);
+
+        if text.is_empty() {
+            return;
+        }
+
+        let allowed = self.chat_rate_limiters.entry(client_id)
+            .or_insert_with(ChatRateLimiter::new)
+            .allow();
+
+        if !allowed {
+            self.network_io.send_client(client_id, ChatMessage::system(None, "You are sending messages too fast").into());
+
+            return;
+        }
+
+        self.network_io.send_all_clients(
+            ChatMessage {
+                area_id: None,
+                sender: ChatSender::Player {
+                    client_id,
+                    name: self.client_name(client_id),
+                },
+                text,
+            }.into()
+        );
+    }
+
+    /// The phase as the clients should see it
+    fn current_phase(&self) -> GamePhase {
+        match self.phase {
+            GamePhase::Countdown { .. } => GamePhase::Countdown {
+                remaining: self.countdown_end.saturating_duration_since(web_time::Instant::now())
+            },
+            phase => phase
+        }
+    }
+
+    fn set_phase(&mut self, phase: GamePhase) {
+        log::info!("Game phase changed to {:?}", phase);
+
+        self.phase = phase;
+
+        self.network_io.send_all_clients(GamePhaseUpdate { phase }.into());
+    }
+
+    fn everyone_ready(&self) -> bool {
+        !self.network_io.clients.is_empty()
+            && self.network_io.clients.keys().all(|client_id| self.ready_clients.contains(client_id))
+    }
+
+    fn tick_lobby(&mut self) {
+
+        let Some(match_area) = &self.match_area else {
+            return;
+        };
+
+        let match_area_id = match_area.id;
+
+        match self.phase {
+            GamePhase::Lobby => {
+                if self.everyone_ready() {
+                    self.countdown_end = web_time::Instant::now() + self.lobby_countdown;
+
+                    self.set_phase(GamePhase::Countdown { remaining: self.lobby_countdown });
+                }
+            },
+            GamePhase::Countdown { .. } => {
+                // someone unreadied or a new client joined
+                if !self.everyone_ready() {
+                    self.set_phase(GamePhase::Lobby);
+
+                    return;
+                }
+
+                if web_time::Instant::now() >= self.countdown_end {
+                    self.start_match(match_area_id);
+                }
+            },
+            GamePhase::InMatch => {
+                if self.match_over(match_area_id) {
+                    self.end_match(match_area_id);
+                }
+            },
+        }
+    }
+
+    fn start_match(&mut self, match_area_id: AreaId) {
+
+        self.move_all_players(self.spawn_area, match_area_id);
+
+        self.ready_clients.clear();
+
+        self.set_phase(GamePhase::InMatch);
+    }
+
+    /// The match is over once every wave is beaten or nobody is left alive in the match area, which includes everyone disconnecting
+    fn match_over(&self, match_area_id: AreaId) -> bool {
+
+        if self.network_io.clients.is_empty() {
+            return true;
+        }
+
+        let Some(area) = self.world.areas.iter().find(|area| area.id == match_area_id) else {
+            return true;
+        };
+
+        if area.wave_director.is_finished() {
+            return true;
+        }
+
+        !area.players.iter().any(|player| !player.despawn && player.health > 0)
+    }
+
+    fn end_match(&mut self, match_area_id: AreaId) {
+
+        self.move_all_players(match_area_id, self.spawn_area);
+
+        // put the match area back how it was so the next match starts fresh
+        if let Some(match_area) = &self.match_area {
+            let fresh_area = Area::from_save(match_area.save.clone(), Some(match_area.id), &self.prefabs, (&self.assets.textures).into());
+
+            if let Some(area) = self.world.areas.iter_mut().find(|area| area.id == match_area_id) {
+                *area = fresh_area;
+            }
+        }
+
+        self.set_phase(GamePhase::Lobby);
+    }
+
+    fn move_all_players(&mut self, from: AreaId, to: AreaId) {
+
+        let Some(area) = self.world.areas.iter().find(|area| area.id == from) else {
+            return;
+        };
+
+        let transfers: Vec<AreaTransfer> = area.players.iter()
+            .filter(|player| !player.despawn)
+            .filter_map(|player| {
+                match player.owner {
+                    Owner::ClientId(client_id) => Some(
+                        AreaTransfer {
+                            client_id,
+                            player_id: player.id,
+                            from,
+                            to,
+                            destination_point: None,
+                        }
+                    ),
+                    _ => None
+                }
+            })
+            .collect();
+
+        for transfer in transfers {
+            self.transfer_player(transfer);
+        }
+    }
+
+    fn transfer_players_through_portals(&mut self) {
+
+        let mut transfers = Vec::new();
+
+        for area in &self.world.areas {
+            for player in &area.players {
+
+                if player.despawn {
+                    continue;
+                }
+
+                let Owner::ClientId(client_id) = player.owner else {
+                    continue;
+                };
+
+                if let Some(last_transfer) = self.portal_cooldowns.get(&client_id) {
+                    if last_transfer.elapsed() < PORTAL_COOLDOWN {
+                        continue;
+                    }
                 }
+
+                let Some(portal) = area.find_portal(player) else {
+                    continue;
+                };
+
+                let Some(destination) = self.area_paths.get(&portal.destination) else {
+                    log::warn!("Portal in area {:?} leads to {} which isnt loaded", area.id, portal.destination);
+                    continue;
+                };
+
+                transfers.push(
+                    AreaTransfer {
+                        client_id,
+                        player_id: player.id,
+                        from: area.id,
+                        to: *destination,
+                        destination_point: portal.destination_point,
+                    }
+                );
             }
         }
+
+        for transfer in transfers {
+            self.transfer_player(transfer);
+        }
+    }
+
+    /// Move a player into another area. The client gets the whole destination area and throws away the one it was in
+    fn transfer_player(&mut self, transfer: AreaTransfer) {
+
+        log::info!("Moving client {:?} from area {:?} to {:?}", transfer.client_id, transfer.from, transfer.to);
+
+        self.portal_cooldowns.insert(transfer.client_id, web_time::Instant::now());
+
+        let Ok(source) = find_area(&mut self.world.areas, transfer.from) else {
+            return;
+        };
+
+        let Ok(player) = find_player(&mut source.players, transfer.from, transfer.player_id) else {
+            return;
+        };
+
+        let player_save = player.save(&source.space);
+
+        player.mark_despawn();
+
+        self.network_io.send_all_except(
+            PlayerDespawnUpdate {
+                area_id: transfer.from,
+                player_id: transfer.player_id,
+            }.into(),
+            transfer.client_id
+        );
+
+        let Ok(destination) = find_area(&mut self.world.areas, transfer.to) else {
+            return;
+        };
+
+        let mut player = Player::from_save(player_save, &mut destination.space, (&self.assets.textures).into());
+
+        let destination_point = transfer.destination_point.unwrap_or(destination.spawn_point);
+
+        player.set_pos(glamx::Pose2::new(destination_point, 0.), &mut destination.space);
+
+        let new_player = NewPlayer {
+            player: player.save(&destination.space),
+            area_id: destination.id,
+        };
+
+        destination.players.push(player);
+
+        self.network_io.send_all_except(new_player.into(), transfer.client_id);
+
+        self.network_io.send_client(
+            transfer.client_id,
+            LoadArea {
+                area: destination.save(),
+                id: destination.id,
+            }.into()
+        );
+
+        self.network_io.set_client_area(transfer.client_id, destination.id);
     }
 
     pub fn get_connected_clients_vector(&self) -> Vec<ClientId> {
@@ -91,65 +498,72 @@ pub fn handle_new_client(&mut self, new_client: ClientId) {
     pub fn handle_disconnected_client(&mut self, client_id: ClientId) {
 
         log::info!("handling disconnect for client: {:?}", client_id);
-        for area in &mut self.world.areas {
-            let disconnected_player = area.players.iter_mut().find(
-                |player|
-                {
-                    player.owner == Owner::ClientId(client_id)
-                }
-            );
 
-            if let Some(player) = disconnected_player {
-                player.mark_despawn();
+        // we wait a bit before despawning the player in case they reconnect
+        self.disconnected_clients.insert(client_id, web_time::Instant::now());
 
-                self.network_io.send_all_clients(
-                    PlayerDespawnUpdate {
-                        area_id: area.id,
-                        player_id: player.id,
-                    }.into()
-                );
-            }
+        if self.match_area.is_some() {
+            self.ready_clients.remove(&client_id);
 
-            let new_owner = area.players
-                .iter()
-                .find(|player| player.owner != Owner::ClientId(client_id))
-                .map(|player| {player.owner});
+            self.network_io.send_all_clients(PlayerLeftLobby { client_id }.into());
+        }
 
+        self.network_io.send_all_clients(ChatMessage::system(None, format!("{} left", self.client_name(client_id))).into());
 
+        self.network_io.client_names.remove(&client_id);
+        self.chat_rate_limiters.remove(&client_id);
+        self.client_latencies.remove(&client_id);
+    }
 
 
-            area.props
-                .iter_mut()
-                .filter(|prop| prop.owner() == Some(Owner::ClientId(client_id)))
-                .for_each(|prop| {
-                    *prop.owner_mut() = new_owner;
+    /// Despawn the players of clients that didn't reconnect in time
+    pub fn despawn_abandoned_players(&mut self) {
 
-                    self.network_io.send_all_except(
-                        PropUpdateOwner {
-                            owner: prop.owner(),
-                            id: prop.id(),
-                            area_id: area.id,
-                        }.into(),
-                        client_id
-                    );
-                }
-            );
+        let abandoned_clients: Vec<ClientId> = self.disconnected_clients
+            .iter()
+            .filter(|(_, disconnected_at)| disconnected_at.elapsed() > RECONNECT_GRACE_PERIOD)
+            .map(|(client_id, _)| *client_id)
+            .collect();
 
+        for client_id in abandoned_clients {
 
-        }
+            log::info!("Client {:?} did not reconnect, despawning their player", client_id);
 
-        // if self.network_io.clients.keys().len() == 0 {
+            self.disconnected_clients.remove(&client_id);
+            self.portal_cooldowns.remove(&client_id);
 
-        //     let lobby: AreaSave = serde_json::from_str(&read_to_string("areas/newoffice.json").unwrap()).unwrap();
-        //     self.world.areas[0] = Area::from_save(lobby, Some(AreaId::new()), &self.prefabs, (&self.assets.textures).into());
-        //     self.world.areas[0].generate_terrain(0);
-        // }
-    }
+            for area in &mut self.world.areas {
+                let abandoned_player = area.players.iter_mut().find(
+                    |player|
+                    {
+                        player.owner == Owner::ClientId(client_id)
+                    }
+                );
 
+                if let Some(player) = abandoned_player {
+                    player.mark_despawn();
+
+                    self.network_io.send_all_clients(
+                        PlayerDespawnUpdate {
+                            area_id: area.id,
+                            player_id: player.id,
+                        }.into()
+                    );
+                }
+            }
+        }
+    }
 
     pub fn run(&mut self) {
         loop {
 
+            for line in self.console.poll() {
+                match self.run_admin_command(&line) {
+                    Ok(output) => println!("{}", output),
+                    Err(error) => println!("error: {}", error),
+                }
+            }
+
             let mut disconnected_clients = Vec::new();
 
             for client_id in &self.previous_tick_connected_clients {
@@ -165,14 +579,13 @@ pub fn handle_new_client(&mut self, new_client: ClientId) {
 
             self.previous_tick_connected_clients = self.get_connected_clients_vector();
 
-            // only tick every 8 ms
-            if self.last_tick.elapsed().as_millis() > 8 {
+            self.despawn_abandoned_players();
+
+            for _ in 0..self.timestep.advance() {
                 self.tick();
             }
 
-            let new_client = self.network_io.accept_new_client();
-
-            if let Some(new_client) = new_client {
+            for new_client in self.network_io.accept_new_clients() {
                 self.handle_new_client(new_client);
             }
 
@@ -189,483 +602,506 @@ pub fn handle_new_client(&mut self, new_client: ClientId) {
         network_packets: Vec<(ClientId, NetworkPacket)>,
     ) {
 
+        let mut kicked_clients: Vec<ClientId> = Vec::new();
+
         for (client_id, network_packet) in network_packets {
-            match &network_packet {
 
-                NetworkPacket::UpdatePropVoxels(update) => {
+            // ignore the rest of the packets from clients we already kicked
+            if kicked_clients.contains(&client_id) {
+                continue;
+            }
 
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
-                    let prop = area.props.iter_mut().find(|prop| {prop.id() == update.prop_id});
+            if let Err(error) = self.handle_packet(client_id, network_packet) {
 
-                    let Some(prop) = prop else {
+                if !error.should_kick() {
+                    log::debug!("Ignoring packet from client {:?}: {}", client_id, error);
+                    continue;
+                }
 
-                        continue;
-                    };
+                log::warn!("Kicking client {:?}: {}", client_id, error);
 
-                    let collider = area.space.collider_set
-                        .get_mut(prop.collider_handle())
-                        .unwrap();
+                // same as an admin kick, they dont get to just reconnect with the same id
+                self.network_io.kicked_clients.insert(client_id);
 
-                    let cos = collider.rotation().cos();
-                    let sin = collider.rotation().sin();
-                    
-                    let body = area.space.rigid_body_set.get(prop.rigid_body_handle()).unwrap();
+                self.network_io.disconnect_client(client_id);
 
-                    let body_rotation = body.rotation().clone();
-                    let body_vels = body.vels().clone();
+                kicked_clients.push(client_id);
+            }
+        }
 
-                    let voxel_collider = collider.shape().as_voxels().unwrap();
+    }
 
-                    let current_voxels: Vec<VoxelData> = voxel_collider.voxels()
-                        .filter(|x| !x.state.is_empty())
-                        .collect();
+    fn handle_packet(&mut self, client_id: ClientId, network_packet: NetworkPacket) -> Result<(), ServerError> {
+        match &network_packet {
 
+            NetworkPacket::PropVoxelDiffs(update) => {
 
-                    let removed_voxels_positions: Vec<glamx::Vec2> = current_voxels
-                        .iter()
-                        .filter(|voxel| !update.new_voxels.contains(&voxel.grid_coords))
-                        .map(
-                            |voxel|
-                            {
-                                let rotated_x = voxel.center.x * cos - voxel.center.y * sin;
-                                let rotated_y = voxel.center.x * sin + voxel.center.y * cos;
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                                let world_x = rotated_x + collider.translation().x;
-                                let world_y = rotated_y + collider.translation().y;
+                for diff in &update.diffs {
+                    // the prop might have been despawned while this was in flight
+                    if !area.apply_prop_voxel_diff(diff)? {
+                        log::debug!("Skipping voxel diff for unknown prop {:?} in area {:?}", diff.prop_id, update.area_id);
 
-                                glamx::Vec2::new(world_x, world_y)
-                            }
-                        )
-                        .collect();
+                        continue;
+                    }
 
-                    collider.set_shape(
-                        SharedShape::voxels(glamx::vec2(8., 8.), &update.new_voxels)
-                    );
-                    
+                    // blowing chunks off something counts as messing with it
+                    self.prop_ownership.record_interaction(diff.prop_id, client_id);
+                }
 
+                self.network_io.send_all_except(
+                    network_packet,
+                    client_id
+                );
+            }
+            NetworkPacket::MasterUpdate(_update) => {
+                return Err(ServerError::ClientBoundPacket("MasterUpdate"));
+            },
+            NetworkPacket::Ping(_ping) => {
 
-                    for removed_voxel in removed_voxels_positions {
 
+                self.network_io.send_client(client_id, network_packet.clone());
 
-                        area.dissolved_pixels.push(
-                            DissolvedPixel::new(
-                                Pose2::new(removed_voxel, body_rotation.angle()), 
-                                &mut area.space, 
-                                WHITE, 
-                                8., 
-                                Some(10.), 
-                                Some(body_vels)
-                            )
-                        );
-                    }
+            },
+            NetworkPacket::PropVelocityUpdate(update) => {
 
-                    *prop.removed_voxels_mut() = update.removed_voxels.clone();
-                    *prop.voxels_modified_mut() = true;
 
-                    self.network_io.send_all_except(
-                        network_packet,
-                        client_id
-                    );
-                }
-                NetworkPacket::SetPropVoxel(update) => {
-                    panic!();
-                    // just forward it for now
-                    self.network_io.send_all_except(
-                        network_packet,
-                        client_id
-                    );
+                let area = find_area(&mut self.world.areas, update.area_id)?;
+
+                let prop = area.props.iter_mut()
+                    .find(|prop| {prop.id() == update.id})
+                    .ok_or(ServerError::UnknownProp { area_id: update.area_id, prop_id: update.id })?;
+
+                // ownership can move on while this was in flight
+                if prop.owner() != Some(Owner::ClientId(client_id)) {
+                    return Ok(());
                 }
-                NetworkPacket::MasterUpdate(_update) => {
-                    panic!("received client bound update");
-                },
-                NetworkPacket::Ping(_ping) => {
 
+                let body = area.space.rigid_body_set.get_mut(prop.rigid_body_handle()).unwrap();
 
-                    self.network_io.send_client(client_id, network_packet.clone());
+                body.set_vels(update.velocity, true);
 
-                },
-                NetworkPacket::PropVelocityUpdate(update) => {
+                let position = body.position().translation;
 
+                self.network_io.send_all_near(network_packet, position, Some(client_id));
 
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+            },
+            NetworkPacket::NewProp(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    let mut prop = area.props.iter_mut().find(|prop| {prop.id() == update.id});
+                area.props.push(
+                    update.prop.load(&mut area.space, (&self.assets.textures).into())
+                );
 
-                    // if prop.is_none() {
-                    //     if let Some(computer) = &mut area.computer {
-                    //         if computer.prop.id == update.id {
-                    //             prop = Some(&mut computer.prop);
-                    //         }
-                    //     }
-                    // }
+                self.network_io.send_all_except(network_packet, client_id);
 
-                    if let Some(prop) = prop {
+            },
 
-                        let body = area.space.rigid_body_set.get_mut(prop.rigid_body_handle()).unwrap();
+            NetworkPacket::NewDroppedItemUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                        body.set_vels(update.velocity, true);
+                area.dropped_items.push(
+                    DroppedItem::from_save(update.dropped_item.clone(), &mut area.space, &self.prefabs, (&self.assets.textures).into())
+                );
 
-                        self.network_io.send_all_except(network_packet, client_id);
-                    }
+                self.network_io.send_all_except(network_packet, client_id);
+            },
+            NetworkPacket::RemoveDroppedItemUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
+                let dropped_item = area.dropped_items.iter_mut()
+                    .find(|dropped_item| dropped_item.id == update.dropped_item_id)
+                    .ok_or(ServerError::UnknownDroppedItem { area_id: update.area_id, dropped_item_id: update.dropped_item_id.clone() })?;
 
+                dropped_item.mark_despawn();
 
-                },
-                NetworkPacket::NewProp(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                self.network_io.send_all_except(network_packet, client_id);
+            }
+            NetworkPacket::NewPlayer(update) => {
 
-                    area.props.push(
-                        update.prop.load(&mut area.space, (&self.assets.textures).into())
-                    );
+                if update.player.owner != Owner::ClientId(client_id) {
+                    return Err(ServerError::NotPlayerOwner { area_id: update.area_id, player_id: update.player.id });
+                }
 
-                    self.network_io.send_all_except(network_packet, client_id);
+                let already_spawned = self.world.areas.iter().any(|area| {
+                    area.players.iter().any(|player| player.owner == Owner::ClientId(client_id) && !player.despawn)
+                });
 
-                },
+                if already_spawned {
+                    return Err(ServerError::DuplicatePlayer { area_id: update.area_id, player_id: update.player.id });
+                }
 
-                NetworkPacket::NewDroppedItemUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                let name = self.client_name(client_id);
 
-                    area.dropped_items.push(
-                        DroppedItem::from_save(update.dropped_item.clone(), &mut area.space, &self.prefabs, (&self.assets.textures).into())
-                    );
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::RemoveDroppedItemUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                // dont trust the name the client put on it
+                let mut update = update.clone();
+                update.player.name = name;
 
-                    let dropped_item = area.dropped_items.iter_mut().find(|dropped_item| dropped_item.id == update.dropped_item_id).unwrap();
+                let mut player = Player::from_save(update.player.clone(), &mut area.space, (&self.assets.textures).into());
 
-                    dropped_item.mark_despawn();
+                // or where it says it spawned
+                player.set_pos(glamx::Pose2::new(area.spawn_point, 0.), &mut area.space);
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                }
-                NetworkPacket::NewPlayer(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                update.player = player.save(&area.space);
 
-                    area.players.push(Player::from_save(update.player.clone(), &mut area.space, (&self.assets.textures).into()));
+                area.players.push(player);
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::PlayerVelocityUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                self.network_io.send_all_except(update.into(), client_id);
+            },
+            NetworkPacket::PlayerInputUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.id}).unwrap();
+                let player = find_player(&mut area.players, update.area_id, update.player_id)?;
 
-                    player.set_velocity(update.velocity, &mut area.space);
+                check_player_owner(player, client_id, update.area_id)?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::PlayerHealthUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                // this gets simulated on the next tick
+                player.movement.queue_input(update.input);
+            },
+            NetworkPacket::PlayerStateUpdate(_update) => {
+                return Err(ServerError::ClientBoundPacket("PlayerStateUpdate"));
+            },
+            NetworkPacket::PlayerHealthUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
+
+                let player = find_player(&mut area.players, update.area_id, update.player_id)?;
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
+                check_player_owner(player, client_id, update.area_id)?;
 
-                    player.health = update.health;
+                let died = player.health > 0 && update.health <= 0;
 
-                    self.network_io.send_all_except(network_packet, client_id);
+                player.health = update.health;
+
+                if died {
+                    let death_message = ChatMessage::system(Some(update.area_id), format!("{} died", player.name));
+
+                    self.network_io.send_all_clients(death_message.into());
                 }
-                NetworkPacket::DroppedItemVelocityUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
 
-                    let dropped_item = area.dropped_items.iter_mut().find(|dropped_item| {dropped_item.id == update.id}).unwrap();
+                self.network_io.send_all_except(network_packet, client_id);
+            }
+            NetworkPacket::DroppedItemVelocityUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    dropped_item.set_velocity(&mut area.space, update.velocity);
+                let dropped_item = area.dropped_items.iter_mut()
+                    .find(|dropped_item| {dropped_item.id == update.id})
+                    .ok_or(ServerError::UnknownDroppedItem { area_id: update.area_id, dropped_item_id: update.id.clone() })?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::PlayerCursorUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                dropped_item.set_velocity(&mut area.space, update.velocity);
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.id}).unwrap();
+                let position = area.space.rigid_body_set.get(dropped_item.body).unwrap().position().translation;
 
-                    player.set_cursor_pos(update.pos);
+                self.network_io.send_all_near(network_packet, position, Some(client_id));
+            },
+            NetworkPacket::PlayerCursorUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::PlayerFacingUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                let player = find_player(&mut area.players, update.area_id, update.id)?;
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.id}).unwrap();
+                check_player_owner(player, client_id, update.area_id)?;
 
-                    player.facing = update.facing;
+                player.set_cursor_pos(update.pos);
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::SpawnBulletTrail(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                self.network_io.send_all_except(network_packet, client_id);
+            },
+            NetworkPacket::PlayerFacingUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    area.bullet_trails.push(
-                        BulletTrail::from_save(update.save)
-                    );
+                let player = find_player(&mut area.players, update.area_id, update.id)?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::PlayerPositionUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                check_player_owner(player, client_id, update.area_id)?;
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
+                player.facing = update.facing;
 
-                    let _current_pos = area.space.rigid_body_set.get(player.body.body_handle).unwrap().position();
+                self.network_io.send_all_except(network_packet, client_id);
+            },
+            NetworkPacket::SpawnBulletTrail(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    player.set_pos(update.pos, &mut area.space);
+                area.bullet_trails.push(
+                    BulletTrail::from_save(update.save)
+                );
 
-                    self.network_io.send_all_except(network_packet, client_id);
+                self.network_io.send_all_except(network_packet, client_id);
+            },
+            NetworkPacket::PropPositionUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
+                let prop = area.props.iter_mut()
+                    .find(|prop| {prop.id()} == update.prop_id)
+                    .ok_or(ServerError::UnknownProp { area_id: update.area_id, prop_id: update.prop_id })?;
 
-                },
-                NetworkPacket::PropPositionUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                // ownership can move on while this was in flight
+                if prop.owner() != Some(Owner::ClientId(client_id)) {
+                    return Ok(());
+                }
 
-                    let prop = match area.props.iter_mut().find(|prop| {prop.id()} == update.prop_id) {
-                        Some(prop) => prop,
-                        None => {
-                            log::warn!("Received bad prop position update");
+                prop.set_pos(update.pos, &mut area.space);
 
-                            continue;
-                        },
-                    };
+                self.network_io.send_all_except(network_packet, client_id);
 
-                    prop.set_pos(update.pos, &mut area.space);
+            },
+            NetworkPacket::LoadArea(_update) => {
+                return Err(ServerError::ClientBoundPacket("LoadArea"));
+            },
+            NetworkPacket::ClientViewUpdate(update) => {
+                self.network_io.set_client_view(client_id, update.view);
+            },
+            NetworkPacket::PlayerReadyUpdate(update) => {
 
-                    self.network_io.send_all_except(network_packet, client_id);
+                // readying up mid match doesnt mean anything
+                if self.phase == GamePhase::InMatch {
+                    return Ok(());
+                }
 
-                },
-                NetworkPacket::LoadArea(_update) => {
-                    panic!("server received client bound load area update");
-                },
-                NetworkPacket::PropUpdateOwner(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                match update.ready {
+                    true => self.ready_clients.insert(client_id),
+                    false => self.ready_clients.remove(&client_id),
+                };
 
-                    let Some(prop) = area.props.iter_mut().find(|prop| {prop.id()} == update.id) else {
-                        log::warn!("client: {:?} sent an invalid prop update", client_id);
-                        return;
-                    };
+                // dont trust the id the client sent
+                self.network_io.send_all_clients(PlayerReadyUpdate { client_id, ready: update.ready }.into());
+            },
+            NetworkPacket::GamePhaseUpdate(_update) => {
+                return Err(ServerError::ClientBoundPacket("GamePhaseUpdate"));
+            },
+            NetworkPacket::PlayerLeftLobby(_update) => {
+                return Err(ServerError::ClientBoundPacket("PlayerLeftLobby"));
+            },
+            NetworkPacket::WaveUpdate(_update) => {
+                return Err(ServerError::ClientBoundPacket("WaveUpdate"));
+            },
+            NetworkPacket::TickRateUpdate(_update) => {
+                return Err(ServerError::ClientBoundPacket("TickRateUpdate"));
+            },
+            NetworkPacket::ChatMessage(message) => {
+                self.relay_chat_message(client_id, message);
+            },
+            NetworkPacket::LatencyUpdate(update) => {
+                self.client_latencies.insert(client_id, update.latency);
+            },
+            NetworkPacket::PropUpdateOwner(_update) => {
+                // the server decides who owns what
+                return Err(ServerError::ClientBoundPacket("PropUpdateOwner"));
+            },
+            NetworkPacket::PropInteraction(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
+
+                if !area.props.iter().any(|prop| prop.id() == update.prop_id) {
+                    return Err(ServerError::UnknownProp { area_id: update.area_id, prop_id: update.prop_id });
+                }
 
-                    *prop.last_ownership_change_mut() = web_time::Instant::now();
+                self.prop_ownership.record_interaction(update.prop_id, client_id);
+            },
+            NetworkPacket::NoiseUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    *prop.owner_mut() = update.owner;
+                // going through new so the radius gets capped
+                area.noises.push(Noise::new(update.noise.position, update.noise.radius, update.noise.player));
+            },
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
+            NetworkPacket::DissolveProp(update) => {
 
-                NetworkPacket::DissolveProp(_update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    // we can just pass this along to the other clients because the server doesnt really care about the physics of the dissolved props :)))
+                let prop = find_prop(&mut area.props, update.area_id, update.prop_id)?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                }
-                NetworkPacket::RemovePropUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                check_prop_owner(prop.as_ref(), client_id, update.area_id)?;
 
-                    if let Some(prop) = area.props.iter_mut().find(|prop|{prop.id() == update.prop_id}) {
+                // we can just pass this along to the other clients because the server doesnt really care about the physics of the dissolved props :)))
 
-                        prop.mark_despawn();
-                    }
+                self.network_io.send_all_except(network_packet, client_id);
+            }
+            NetworkPacket::RemovePropUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
+                let prop = find_prop(&mut area.props, update.area_id, update.prop_id)?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
+                check_prop_owner(prop.as_ref(), client_id, update.area_id)?;
 
-                },
-                NetworkPacket::ActiveItemSlotUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                prop.mark_despawn();
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
+                self.network_io.send_all_except(network_packet, client_id);
 
-                    player.selected_item = update.active_item_slot as usize;
+            },
+            NetworkPacket::ActiveItemSlotUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::ItemSlotQuantityUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
+                let player = find_player(&mut area.players, update.area_id, update.player_id)?;
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
+                check_player_owner(player, client_id, update.area_id)?;
 
-                    match &mut player.inventory.items[update.inventory_index] {
-                        Some(item_slot) => {
-                            item_slot.quantity = update.quantity;
-                        },
-                        None => {
-                            log::warn!("Received quantity update for invalid item index");
+                if update.active_item_slot as usize >= player.inventory.items.len() {
+                    return Err(ServerError::InvalidInventoryIndex(update.active_item_slot as usize));
+                }
 
-                            continue;
-                        },
-                    }
+                player.selected_item = update.active_item_slot as usize;
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::ItemSlotUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                self.network_io.send_all_except(network_packet, client_id);
+            },
+            NetworkPacket::ItemSlotQuantityUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
+                let player = find_player(&mut area.players, update.area_id, update.player_id)?;
 
-                    player.inventory.items[update.inventory_index] = match &update.item_slot {
-                        Some(item_slot_save) => {
-                            Some(
-                                ItemSlot::from_save(item_slot_save.clone(), &mut area.space, (&self.assets.textures).into())
-                            )
-                        },
-                        None => None,
-                    };
+                check_player_owner(player, client_id, update.area_id)?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
+                match player.inventory.items.get_mut(update.inventory_index) {
+                    Some(Some(item_slot)) => {
+                        item_slot.quantity = update.quantity;
+                    },
+                    _ => {
+                        return Err(ServerError::InvalidInventoryIndex(update.inventory_index));
+                    },
+                }
 
-                NetworkPacket::NewEnemyUpdate(update) => {
+                self.network_io.send_all_except(network_packet, client_id);
+            },
+            NetworkPacket::ItemSlotUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                let player = find_player(&mut area.players, update.area_id, update.player_id)?;
 
-                    let enemy = Enemy::from_save(update.enemy.clone(), &mut area.space);
-                    area.enemies.push(enemy);
+                check_player_owner(player, client_id, update.area_id)?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
+                if update.inventory_index >= player.inventory.items.len() {
+                    return Err(ServerError::InvalidInventoryIndex(update.inventory_index));
                 }
-                NetworkPacket::EnemyPositionUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
 
+                player.inventory.items[update.inventory_index] = match &update.item_slot {
+                    Some(item_slot_save) => {
+                        Some(
+                            ItemSlot::from_save(item_slot_save.clone(), &mut area.space, (&self.assets.textures).into())
+                        )
+                    },
+                    None => None,
+                };
 
-                    let enemy = area.enemies.iter().find(|enemy| {enemy.id == update.enemy_id}).unwrap();
+                self.network_io.send_all_except(network_packet, client_id);
+            },
 
-                    area.space.rigid_body_set.get_mut(enemy.body.body_handle).unwrap().set_position(update.position, true);
+            NetworkPacket::NewEnemyUpdate(_update) => {
+                // enemies are only ever spawned by the server
+                return Err(ServerError::ClientBoundPacket("NewEnemyUpdate"));
+            }
+            NetworkPacket::EnemyPositionUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
+                let enemy = find_enemy(&mut area.enemies, update.area_id, update.enemy_id)?;
 
-                },
-                NetworkPacket::EnemyVelocityUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                check_enemy_owner(enemy, client_id, update.area_id, "EnemyPositionUpdate")?;
 
-                    let enemy = area.enemies.iter().find(|enemy| {enemy.id == update.enemy_id}).unwrap();
+                area.space.rigid_body_set.get_mut(enemy.body.body_handle).unwrap().set_position(update.position, true);
 
-                    area.space.rigid_body_set.get_mut(enemy.body.body_handle).unwrap().set_vels(update.velocity, true);
+                self.network_io.send_all_except(network_packet, client_id);
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::EnemyWeaponUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+            },
+            NetworkPacket::EnemyVelocityUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    let enemy = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}).unwrap();
+                let enemy = find_enemy(&mut area.enemies, update.area_id, update.enemy_id)?;
 
+                check_enemy_owner(enemy, client_id, update.area_id, "EnemyVelocityUpdate")?;
 
-                    enemy.item = Some(
-                        update.item.load()
-                    );
+                let body = area.space.rigid_body_set.get_mut(enemy.body.body_handle).unwrap();
 
-                    self.network_io.send_all_except(network_packet, client_id);
+                body.set_vels(update.velocity, true);
 
-                },
-                NetworkPacket::EnemyHealthUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                let position = body.position().translation;
 
-                    if let Some(enemy) = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}) {
-                        enemy.health = update.health;
-                    }
+                self.network_io.send_all_near(network_packet, position, Some(client_id));
+            },
+            NetworkPacket::EnemyWeaponUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::EnemyDespawnUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(
-                        |area| {
-                            area.id == update.area_id
-                        }
-                    ).unwrap();
+                let enemy = find_enemy(&mut area.enemies, update.area_id, update.enemy_id)?;
 
-                    let enemy = area.enemies.iter_mut().find(|enemy| {enemy.id == update.enemy_id}).unwrap();
+                check_enemy_owner(enemy, client_id, update.area_id, "EnemyWeaponUpdate")?;
 
-                    enemy.mark_despawn();
+                enemy.item = Some(
+                    update.item.load()
+                );
 
-                    self.network_io.send_all_except(network_packet, client_id);
+                self.network_io.send_all_except(network_packet, client_id);
 
+            },
+            NetworkPacket::EnemyHealthUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
-                },
+                let enemy = find_enemy(&mut area.enemies, update.area_id, update.enemy_id)?;
 
-                NetworkPacket::PlayerDespawnUpdate(update) => {
-                    let area = self.world.areas.iter_mut().find(|area| {area.id == update.area_id}).unwrap();
-                    let player = area.players.iter_mut().find(|player| {player.id == update.player_id}).unwrap();
+                // whoever shoots the enemy sends this, so it usually isn't from the owner. everyone else can only hurt it though.
+                // two people shooting it at once can both send a higher health than we have, so that just gets ignored instead of kicking them
+                match enemy.owner == Owner::ClientId(client_id) {
+                    true => enemy.health = update.health,
+                    false => enemy.health = enemy.health.min(update.health),
+                }
 
-                    player.mark_despawn();
+                // first death wins
+                if enemy.death.is_none() && enemy.health <= 0 {
+                    enemy.death = Some(update.death.unwrap_or_default());
+                }
 
-                    self.network_io.send_all_except(network_packet, client_id);
-                },
-                NetworkPacket::StupidDissolvedPixelVelocityUpdate(_update) => {
+                let mut update = update.clone();
+                update.health = enemy.health;
+                update.death = enemy.death;
 
-                    // server doesnt care about this!
-                    self.network_io.send_all_except(network_packet, client_id);
+                // clients wait for us to pick the death, including whoever sent this
+                match update.death.is_some() {
+                    true => self.network_io.send_all_clients(update.into()),
+                    false => self.network_io.send_all_except(update.into(), client_id),
+                }
+            },
+            NetworkPacket::EnemyDespawnUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
 
+                let enemy = find_enemy(&mut area.enemies, update.area_id, update.enemy_id)?;
 
+                check_enemy_owner(enemy, client_id, update.area_id, "EnemyDespawnUpdate")?;
+
+                enemy.mark_despawn();
+
+                self.network_io.send_all_except(network_packet, client_id);
+
+
+            },
+
+            NetworkPacket::PlayerDespawnUpdate(update) => {
+                let area = find_area(&mut self.world.areas, update.area_id)?;
+
+                let player = find_player(&mut area.players, update.area_id, update.player_id)?;
+
+                check_player_owner(player, client_id, update.area_id)?;
+
+                player.mark_despawn();
+
+                self.network_io.send_all_except(network_packet, client_id);
+            },
+            NetworkPacket::StupidDissolvedPixelVelocityUpdate(update) => {
+
+                find_area(&mut self.world.areas, update.area_id)?;
+
+                // server doesnt care about this!
+                self.network_io.send_all_except(network_packet, client_id);
 
-                }
 
 
             }
+
+
         }
 
+        Ok(())
     }
 
     pub fn receive_packets(&mut self) -> Vec<(ClientId, NetworkPacket)>{
         // we should really just return HashMap<ClientId, Vec<NetworkPacket>> but i dont feel like rewriting the handle packets function
 
-        let mut disconnected_clients: Vec<ClientId> = Vec::default();
-        let mut packets: Vec<(ClientId, NetworkPacket)> = Vec::new();
+        let mut disconnected_clients: Vec<(ClientId, Option<ServerError>)> = Vec::default();
+        let mut frames: Vec<(ClientId, Vec<u8>)> = Vec::new();
 
         'client_loop: for (client_id, client) in &mut self.network_io.clients  {
 
@@ -678,12 +1114,12 @@ pub fn handle_new_client(&mut self, new_client: ClientId) {
                             },
                             Message::Close(_close_message) => {
                                 log::info!("Client {:?} disconnected", client_id);
-                                disconnected_clients.push(*client_id);
+                                disconnected_clients.push((*client_id, None));
                                 continue 'client_loop;
                             },
                             _ => {
                                 log::warn!("Client {:?} tried to send non binary message. Disconnecting them.", client_id);
-                                disconnected_clients.push(*client_id);
+                                disconnected_clients.push((*client_id, None));
                                 continue 'client_loop;
                             }
                         }
@@ -700,64 +1136,442 @@ pub fn handle_new_client(&mut self, new_client: ClientId) {
                                     },
                                     std::io::ErrorKind::ConnectionReset => {
                                         log::info!("Client {:?} disconnected", client_id);
-                                        disconnected_clients.push(*client_id);
+                                        disconnected_clients.push((*client_id, None));
                                         continue 'client_loop;
                                     }
                                     _ => {
-                                        log::error!("Unhandled io error: {}", io_error);
-                                        exit(1);
+                                        disconnected_clients.push((*client_id, Some(ServerError::Io(io_error))));
+                                        continue 'client_loop;
                                     },
                                 }
                             },
 
                             tungstenite::Error::Protocol(error) => {
                                 log::info!("Client {:?} disconnected due to a protocol error: {:?}", client_id, error);
-                                disconnected_clients.push(*client_id);
+                                disconnected_clients.push((*client_id, None));
                                 continue 'client_loop;
                             },
 
                             _ => {
-
-                                log::error!("Unhandled websocket message read error: {}", error.to_string());
-                                exit(1);
+                                disconnected_clients.push((*client_id, Some(ServerError::WebSocket(error))));
+                                continue 'client_loop;
                             }
                         }
                     },
                 };
 
-                let update_json = String::from_utf8(update_bytes).unwrap();
+                frames.push((*client_id, update_bytes));
 
-                let client_packets: Vec<NetworkPacket> = serde_json::from_str(&update_json).unwrap();
+            }
+        }
 
-                // we should really just return HashMap<ClientId, Vec<NetworkPacket>>
-                for packet in client_packets {
-                    packets.push((*client_id, packet));
-                }
+        let mut received: Vec<(ClientId, NetworkPacket)> = Vec::new();
+
+        for (client_id, update_bytes) in frames {
 
+            let client_packets = match codec::decode_batch(&update_bytes) {
+                Ok((_codec, client_packets)) => client_packets,
+                Err(error) => {
+                    disconnected_clients.push((client_id, Some(error.into())));
+                    continue;
+                },
+            };
+
+            // we should really just return HashMap<ClientId, Vec<NetworkPacket>>
+            for packet in client_packets {
+                received.push((client_id, packet));
             }
         }
 
-        for disconnected_client in disconnected_clients {
-            self.network_io.clients.remove(&disconnected_client);
+        let mut packets = self.network_io.network_simulator.incoming(received);
+
+        // they might have left while this was held up in the simulator
+        packets.retain(|(client_id, _)| self.network_io.clients.contains_key(client_id));
+
+        for (disconnected_client, error) in disconnected_clients {
+
+            if let Some(error) = error {
+                log::warn!("Kicking client {:?}: {}", disconnected_client, error);
+            }
+
+            // drop anything they sent this tick along with them
+            packets.retain(|(client_id, _)| *client_id != disconnected_client);
+
+            self.network_io.disconnect_client(disconnected_client);
         }
         packets
     }
 
 
 
+    fn arbitrate_prop_ownership(&mut self) {
+        if !self.prop_ownership.due() {
+            return;
+        }
+
+        for area in &mut self.world.areas {
+            // players waiting to reconnect cant simulate anything
+            self.prop_ownership.arbitrate(area, &mut self.network_io, |client_id| !self.disconnected_clients.contains_key(&client_id));
+        }
+    }
+
     pub fn tick(&mut self) {
 
         let _megabits = self.total_bits_sent as f32 / 1000000 as f32;
 
         let ctx = ServerTickContext {
             network_io: &mut self.network_io,
-            last_tick_duration: self.last_tick_duration,
+            last_tick_duration: self.timestep.step(),
+            prefabs: &self.prefabs,
         };
 
         self.world.tick(&mut TickContext::Server(ctx));
 
+        self.arbitrate_prop_ownership();
+
+        self.transfer_players_through_portals();
+
+        self.tick_lobby();
+    }
+}
+
+/// Admin commands. Everything goes through run_admin_command so it doesn't matter where the command came from
+impl Server {
+
+    pub fn run_admin_command(&mut self, line: &str) -> Result<String, String> {
+
+        let command = AdminCommand::parse(line)?;
+
+        log::info!("Running admin command {:?}", command);
+
+        match command {
+            AdminCommand::Help => Ok(HELP.to_string()),
+            AdminCommand::Clients => Ok(self.describe_clients()),
+            AdminCommand::Areas => Ok(self.describe_areas()),
+            AdminCommand::Kick { client } => {
+                let client_id = self.find_client(&client)?;
+
+                self.network_io.kicked_clients.insert(client_id);
+
+                // the next loop sees they are gone and cleans up after them like any other disconnect
+                self.network_io.disconnect_client(client_id);
+
+                Ok(format!("kicked {}", self.client_name(client_id)))
+            },
+            AdminCommand::Move { client, area } => {
+                let client_id = self.find_client(&client)?;
+                let to = self.find_area_id(&area)?;
+
+                self.move_client(client_id, to)
+            },
+            AdminCommand::Reload { area } => {
+                let area_id = self.find_area_id(&area)?;
+
+                self.reload_area(area_id)
+            },
+            AdminCommand::SpawnEnemy { area, pos, archetype } => {
+                let area_id = self.find_area_id(&area)?;
+
+                let area = find_area(&mut self.world.areas, area_id).map_err(|error| error.to_string())?;
+
+                if self.prefabs.try_get_prefab_data(&archetype).is_none() {
+                    return Err(format!("no enemy archetype at {}", archetype));
+                }
+
+                let enemy = Enemy::spawn(glamx::Pose2::new(pos, 0.), Owner::Server, &mut area.space, &self.prefabs, &archetype);
+
+                self.network_io.send_all_clients(
+                    NewEnemyUpdate {
+                        area_id,
+                        enemy: enemy.save(&area.space),
+                    }.into()
+                );
+
+                area.enemies.push(enemy);
+
+                Ok(format!("spawned a {} at {}", archetype, pos))
+            },
+            AdminCommand::SpawnProp { area, prefab, pos } => {
+                let area_id = self.find_area_id(&area)?;
+
+                let prefab_json = self.prefabs.try_get_prefab_data(&prefab)
+                    .ok_or(format!("no prefab at {}", prefab))?;
+
+                let prop_save: BasePropSave = serde_json::from_str(&prefab_json)
+                    .map_err(|error| format!("{} isn't a prop: {}", prefab, error))?;
+
+                let area = find_area(&mut self.world.areas, area_id).map_err(|error| error.to_string())?;
+
+                let mut prop = prop_save.load(&mut area.space, (&self.assets.textures).into());
+
+                prop.set_pos(glamx::Pose2::new(pos, 0.), &mut area.space);
+
+                self.network_io.send_all_clients(
+                    NewProp {
+                        prop: prop.save(&area.space),
+                        area_id,
+                    }.into()
+                );
+
+                area.props.push(prop);
+
+                Ok(format!("spawned {} at {}", prefab, pos))
+            },
+            AdminCommand::StartWave { area } => {
+                let area_id = self.find_area_id(&area)?;
+
+                let area = find_area(&mut self.world.areas, area_id).map_err(|error| error.to_string())?;
+
+                area.wave_director.start_next_wave()?;
+
+                Ok("starting the next wave".to_string())
+            },
+            AdminCommand::SkipWave { area } => {
+                let area_id = self.find_area_id(&area)?;
+
+                let area = find_area(&mut self.world.areas, area_id).map_err(|error| error.to_string())?;
+
+                area.wave_director.cancel_pending_spawns()?;
+
+                // the director ends the wave on its own once nothing is left
+                let mut despawned = 0;
+
+                for enemy in area.enemies.iter_mut().filter(|enemy| !enemy.despawn) {
+                    enemy.mark_despawn();
+
+                    self.network_io.send_all_clients(
+                        EnemyDespawnUpdate {
+                            area_id,
+                            enemy_id: enemy.id,
+                        }.into()
+                    );
+
+                    despawned += 1;
+                }
+
+                Ok(format!("skipped the wave, despawned {} enemies", despawned))
+            },
+            AdminCommand::TickRate(tick_rate) => {
+                self.timestep.set_tick_rate(tick_rate);
+
+                self.network_io.send_all_clients(TickRateUpdate { tick_rate }.into());
+
+                Ok(format!("ticking {} times a second", tick_rate))
+            },
+            AdminCommand::NetworkConditions(conditions) => {
+                self.network_io.network_simulator.conditions = conditions;
+
+                match conditions.is_active() {
+                    true => Ok(format!("simulating {}", conditions)),
+                    false => Ok("stopped simulating network conditions".to_string()),
+                }
+            },
+        }
+    }
+
+    /// By id or by name
+    fn find_client(&self, query: &str) -> Result<ClientId, String> {
+
+        if let Some(client_id) = self.network_io.clients.keys().find(|client_id| client_id.to_string() == query) {
+            return Ok(*client_id);
+        }
+
+        let matches: Vec<ClientId> = self.network_io.clients.keys()
+            .filter(|client_id| self.client_name(**client_id).eq_ignore_ascii_case(query))
+            .copied()
+            .collect();
+
+        match matches.as_slice() {
+            [client_id] => Ok(*client_id),
+            [] => Err(format!("no connected client called {}", query)),
+            _ => Err(format!("more than one client is called {}, use their id", query)),
+        }
+    }
+
+    /// By index in the areas list or by the path it was loaded from
+    fn find_area_id(&self, query: &str) -> Result<AreaId, String> {
+
+        if let Ok(index) = query.parse::<usize>() {
+            return self.world.areas.get(index)
+                .map(|area| area.id)
+                .ok_or(format!("there are only {} areas", self.world.areas.len()));
+        }
+
+        self.area_paths.get(query)
+            .copied()
+            .ok_or(format!("no area loaded from {}", query))
+    }
+
+    fn area_path(&self, area_id: AreaId) -> Option<&String> {
+        self.area_paths.iter()
+            .find(|(_, id)| **id == area_id)
+            .map(|(path, _)| path)
+    }
+
+    fn describe_clients(&self) -> String {
+
+        if self.network_io.clients.is_empty() {
+            return "no clients connected".to_string();
+        }
+
+        let mut clients: Vec<ClientId> = self.network_io.clients.keys().copied().collect();
+
+        clients.sort_by_key(|client_id| self.client_name(*client_id).to_lowercase());
+
+        clients.iter()
+            .map(|client_id| {
+                let area = self.network_io.client_interest.get(client_id)
+                    .and_then(|interest| interest.area_id)
+                    .and_then(|area_id| self.world.areas.iter().position(|area| area.id == area_id))
+                    .map(|index| index.to_string())
+                    .unwrap_or("-".to_string());
+
+                let latency = match self.client_latencies.get(client_id) {
+                    Some(latency) => format!("{}ms", latency.as_millis()),
+                    None => "?".to_string(),
+                };
+
+                format!("{}  {}  area {}  ping {}", client_id, self.client_name(*client_id), area, latency)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn describe_areas(&self) -> String {
+        self.world.areas.iter()
+            .enumerate()
+            .map(|(index, area)| {
+                format!(
+                    "{}  {}  {} players  {} enemies  {} props",
+                    index,
+                    self.area_path(area.id).map(|path| path.as_str()).unwrap_or("-"),
+                    area.players.iter().filter(|player| !player.despawn).count(),
+                    area.enemies.iter().filter(|enemy| !enemy.despawn).count(),
+                    area.props.len()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn move_client(&mut self, client_id: ClientId, to: AreaId) -> Result<String, String> {
+
+        let (from, player_id) = self.world.areas.iter()
+            .find_map(|area| {
+                area.players.iter()
+                    .find(|player| player.owner == Owner::ClientId(client_id) && !player.despawn)
+                    .map(|player| (area.id, player.id))
+            })
+            .ok_or("they haven't spawned a player yet".to_string())?;
+
+        if from == to {
+            return Err("they are already in that area".to_string());
+        }
+
+        self.transfer_player(
+            AreaTransfer {
+                client_id,
+                player_id,
+                from,
+                to,
+                destination_point: None,
+            }
+        );
+
+        Ok(format!("moved {}", self.client_name(client_id)))
+    }
+
+    /// Load the area's file again and swap it in. Players in there are carried over so nobody gets kicked out
+    fn reload_area(&mut self, area_id: AreaId) -> Result<String, String> {
+
+        let path = self.area_path(area_id)
+            .cloned()
+            .ok_or("that area wasn't loaded from a file".to_string())?;
+
+        let area_json = read_to_string(&path)
+            .map_err(|error| format!("failed to read area {}: {}", path, error))?;
+
+        let area_save: AreaSave = serde_json::from_str(&area_json)
+            .map_err(|error| format!("failed to parse area {}: {}", path, error))?;
+
+        // the next match should start from the new version too
+        if let Some(match_area) = &mut self.match_area && match_area.id == area_id {
+            match_area.save = area_save.clone();
+        }
+
+        let mut fresh_area = Area::from_save(area_save, Some(area_id), &self.prefabs, (&self.assets.textures).into());
+
+        let area = find_area(&mut self.world.areas, area_id).map_err(|error| error.to_string())?;
+
+        for player in area.players.iter().filter(|player| !player.despawn) {
+            fresh_area.players.push(
+                Player::from_save(player.save(&area.space), &mut fresh_area.space, (&self.assets.textures).into())
+            );
+        }
+
+        *area = fresh_area;
+
+        let load_area = LoadArea {
+            area: area.save(),
+            id: area_id,
+        };
+
+        let clients_in_area: Vec<ClientId> = self.network_io.client_interest.iter()
+            .filter(|(_, interest)| interest.area_id == Some(area_id))
+            .map(|(client_id, _)| *client_id)
+            .collect();
+
+        for client_id in &clients_in_area {
+            self.network_io.send_client(*client_id, load_area.clone().into());
+        }
+
+        Ok(format!("reloaded {}, sent it to {} clients", path, clients_in_area.len()))
+    }
+}
+
+fn find_area(areas: &mut Vec<Area>, area_id: AreaId) -> Result<&mut Area, ServerError> {
+    areas.iter_mut()
+        .find(|area| area.id == area_id)
+        .ok_or(ServerError::UnknownArea(area_id))
+}
+
+fn find_player(players: &mut Vec<Player>, area_id: AreaId, player_id: PlayerId) -> Result<&mut Player, ServerError> {
+    players.iter_mut()
+        .find(|player| player.id == player_id)
+        .ok_or(ServerError::UnknownPlayer { area_id, player_id })
+}
+
+fn find_enemy(enemies: &mut Vec<Enemy>, area_id: AreaId, enemy_id: EnemyId) -> Result<&mut Enemy, ServerError> {
+    enemies.iter_mut()
+        .find(|enemy| enemy.id == enemy_id)
+        .ok_or(ServerError::UnknownEnemy { area_id, enemy_id })
+}
+
+fn find_prop(props: &mut Vec<Box<dyn Prop>>, area_id: AreaId, prop_id: PropId) -> Result<&mut Box<dyn Prop>, ServerError> {
+    props.iter_mut()
+        .find(|prop| prop.id() == prop_id)
+        .ok_or(ServerError::UnknownProp { area_id, prop_id })
+}
+
+fn check_player_owner(player: &Player, client_id: ClientId, area_id: AreaId) -> Result<(), ServerError> {
+    match player.owner == Owner::ClientId(client_id) {
+        true => Ok(()),
+        false => Err(ServerError::NotPlayerOwner { area_id, player_id: player.id }),
+    }
+}
+
+/// We run the enemies we own ourselves, so a client sending updates for one of those is sending them the wrong way
+fn check_enemy_owner(enemy: &Enemy, client_id: ClientId, area_id: AreaId, packet: &'static str) -> Result<(), ServerError> {
+    match enemy.owner {
+        Owner::Server => Err(ServerError::ClientBoundPacket(packet)),
+        owner if owner == Owner::ClientId(client_id) => Ok(()),
+        _ => Err(ServerError::NotEnemyOwner { area_id, enemy_id: enemy.id }),
+    }
+}
 
-        self.last_tick_duration = self.last_tick.elapsed();
-        self.last_tick = web_time::Instant::now();
+fn check_prop_owner(prop: &dyn Prop, client_id: ClientId, area_id: AreaId) -> Result<(), ServerError> {
+    match prop.owner() == Some(Owner::ClientId(client_id)) {
+        true => Ok(()),
+        false => Err(ServerError::NotPropOwner { area_id, prop_id: prop.id() }),
     }
 }
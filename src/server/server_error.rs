@@ -0,0 +1,101 @@
+use std::fmt::Display;
+
+use interceptors_lib::{area::AreaId, base_prop::PropId, codec::CodecError, dropped_item::DroppedItemId, enemy::EnemyId, player::PlayerId, voxel_diff::VoxelDiffError};
+
+#[derive(Debug)]
+pub enum ServerError {
+    UnknownArea(AreaId),
+    UnknownProp {
+        area_id: AreaId,
+        prop_id: PropId
+    },
+    UnknownPlayer {
+        area_id: AreaId,
+        player_id: PlayerId
+    },
+    UnknownEnemy {
+        area_id: AreaId,
+        enemy_id: EnemyId
+    },
+    UnknownDroppedItem {
+        area_id: AreaId,
+        dropped_item_id: DroppedItemId
+    },
+    InvalidInventoryIndex(usize),
+    /// The client tried to control a player that isnt theirs
+    NotPlayerOwner {
+        area_id: AreaId,
+        player_id: PlayerId
+    },
+    /// The client tried to change a prop that isnt theirs
+    NotPropOwner {
+        area_id: AreaId,
+        prop_id: PropId
+    },
+    /// The client tried to control an enemy that isnt theirs
+    NotEnemyOwner {
+        area_id: AreaId,
+        enemy_id: EnemyId
+    },
+    /// The client tried to spawn a player while it already has one
+    DuplicatePlayer {
+        area_id: AreaId,
+        player_id: PlayerId
+    },
+    /// Bigger than the prop it is for or just garbage
+    InvalidVoxelDiff(VoxelDiffError),
+    /// The client sent us a packet that only the server is supposed to send
+    ClientBoundPacket(&'static str),
+    Decode(CodecError),
+    Io(std::io::Error),
+    WebSocket(tungstenite::Error)
+}
+
+impl ServerError {
+    /// Entities can get despawned while a client still has updates for them in flight, so those errors are expected and the client is not kicked for them.
+    /// Same for props changing owner
+    pub fn should_kick(&self) -> bool {
+        match self {
+            ServerError::NotPropOwner { .. } |
+            ServerError::UnknownProp { .. } |
+            ServerError::UnknownPlayer { .. } |
+            ServerError::UnknownEnemy { .. } |
+            ServerError::UnknownDroppedItem { .. } => false,
+            _ => true
+        }
+    }
+}
+
+impl Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerError::UnknownArea(area_id) => write!(f, "unknown area {:?}", area_id),
+            ServerError::UnknownProp { area_id, prop_id } => write!(f, "unknown prop {:?} in area {:?}", prop_id, area_id),
+            ServerError::UnknownPlayer { area_id, player_id } => write!(f, "unknown player {:?} in area {:?}", player_id, area_id),
+            ServerError::UnknownEnemy { area_id, enemy_id } => write!(f, "unknown enemy {:?} in area {:?}", enemy_id, area_id),
+            ServerError::UnknownDroppedItem { area_id, dropped_item_id } => write!(f, "unknown dropped item {:?} in area {:?}", dropped_item_id, area_id),
+            ServerError::InvalidInventoryIndex(index) => write!(f, "invalid inventory index {}", index),
+            ServerError::NotPlayerOwner { area_id, player_id } => write!(f, "tried to control player {:?} in area {:?} without owning it", player_id, area_id),
+            ServerError::NotPropOwner { area_id, prop_id } => write!(f, "tried to change prop {:?} in area {:?} without owning it", prop_id, area_id),
+            ServerError::NotEnemyOwner { area_id, enemy_id } => write!(f, "tried to control enemy {:?} in area {:?} without owning it", enemy_id, area_id),
+            ServerError::DuplicatePlayer { area_id, player_id } => write!(f, "tried to spawn player {:?} in area {:?} while already having one", player_id, area_id),
+            ServerError::InvalidVoxelDiff(error) => write!(f, "invalid voxel diff: {}", error),
+            ServerError::ClientBoundPacket(packet) => write!(f, "client sent client bound packet {}", packet),
+            ServerError::Decode(error) => write!(f, "failed to decode packets: {}", error),
+            ServerError::Io(error) => write!(f, "io error: {}", error),
+            ServerError::WebSocket(error) => write!(f, "websocket error: {}", error),
+        }
+    }
+}
+
+impl From<VoxelDiffError> for ServerError {
+    fn from(error: VoxelDiffError) -> Self {
+        ServerError::InvalidVoxelDiff(error)
+    }
+}
+
+impl From<CodecError> for ServerError {
+    fn from(error: CodecError) -> Self {
+        ServerError::Decode(error)
+    }
+}
@@ -1,5 +1,8 @@
 
-use rapier2d::prelude::{CCDSolver, ColliderSet, DefaultBroadPhase, ImpulseJointSet, IntegrationParameters, IslandManager, MultibodyJointSet, NarrowPhase, PhysicsHooks, PhysicsPipeline, RigidBodySet, SolverFlags};
+use glamx::Pose2;
+use rapier2d::prelude::{CCDSolver, RigidBodyHandle, ColliderSet, DefaultBroadPhase, ImpulseJointSet, IntegrationParameters, IslandManager, MultibodyJointSet, NarrowPhase, PhysicsHooks, PhysicsPipeline, RigidBodySet, SolverFlags};
+
+use crate::interpolation::lerp_pose;
 
 pub struct MyPhysicsHooks;
 
@@ -29,13 +32,25 @@ pub struct Space {
     pub narrow_phase: NarrowPhase,
     pub impulse_joint_set: ImpulseJointSet,
     pub multibody_joint_set: MultibodyJointSet,
-    pub ccd_solver: CCDSolver
+    pub ccd_solver: CCDSolver,
+    /// Where the moving bodies were before the last step, so the client can draw them in between steps
+    previous_poses: Vec<(RigidBodyHandle, Pose2)>,
+    /// Where the bodies actually are while they are moved for drawing
+    simulated_poses: Vec<(RigidBodyHandle, Pose2)>
 }
 
 impl Space {
     pub fn step(&mut self, dt: web_time::Duration) {
 
         self.integration_parameters.dt = dt.as_secs_f32();
+
+        self.previous_poses.clear();
+
+        for (handle, body) in self.rigid_body_set.iter() {
+            if body.is_dynamic() && !body.is_sleeping() {
+                self.previous_poses.push((handle, *body.position()));
+            }
+        }
         
 
         self.physics_pipeline.step(
@@ -66,7 +81,34 @@ impl Space {
             narrow_phase: NarrowPhase::new(),
             impulse_joint_set: ImpulseJointSet::new(),
             multibody_joint_set: MultibodyJointSet::new(),
-            ccd_solver: CCDSolver::new()
+            ccd_solver: CCDSolver::new(),
+            previous_poses: Vec::new(),
+            simulated_poses: Vec::new()
+        }
+    }
+
+    /// Move bodies alpha of the way between their previous and current step so drawing is smooth when the frame rate and tick rate dont match.
+    /// Has to be undone with restore_simulated_poses before the next step
+    pub fn apply_render_interpolation(&mut self, alpha: f32) {
+
+        for (handle, previous_pose) in &self.previous_poses {
+            let Some(body) = self.rigid_body_set.get_mut(*handle) else {
+                continue;
+            };
+
+            let simulated_pose = *body.position();
+
+            self.simulated_poses.push((*handle, simulated_pose));
+
+            body.set_position(lerp_pose(*previous_pose, simulated_pose, alpha), false);
+        }
+    }
+
+    pub fn restore_simulated_poses(&mut self) {
+        for (handle, simulated_pose) in self.simulated_poses.drain(..) {
+            if let Some(body) = self.rigid_body_set.get_mut(handle) {
+                body.set_position(simulated_pose, false);
+            }
         }
     }
 }
\ No newline at end of file
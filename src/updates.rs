@@ -1,4 +1,4 @@
-use crate::{ClientId, area::{AreaId, AreaSave}, bullet_trail::SpawnBulletTrail, dropped_item::{DroppedItemVelocityUpdate, NewDroppedItemUpdate, RemoveDroppedItemUpdate}, enemy::{EnemyDespawnUpdate, EnemyHealthUpdate, EnemyPositionUpdate, EnemyVelocityUpdate, EnemyItemUpdate, NewEnemyUpdate}, player::{ActiveItemSlotUpdate, ItemSlotQuantityUpdate, ItemSlotUpdate, NewPlayer, PlayerCursorUpdate, PlayerFacingUpdate, PlayerHealthUpdate, PlayerId, PlayerPositionUpdate, PlayerVelocityUpdate}, base_prop::{DissolveProp, NewProp, PropPositionUpdate, PropUpdateOwner, PropVelocityUpdate, RemovePropUpdate, SetPropVoxel, StupidDissolvedPixelVelocityUpdate, UpdatePropVoxels}, uuid_u64};
+use crate::{ClientId, area::{AreaId, AreaSave}, bullet_trail::SpawnBulletTrail, chat::ChatMessage, dropped_item::{DroppedItemVelocityUpdate, NewDroppedItemUpdate, RemoveDroppedItemUpdate}, enemy::{EnemyDespawnUpdate, EnemyHealthUpdate, EnemyPositionUpdate, EnemyVelocityUpdate, EnemyItemUpdate, NewEnemyUpdate}, player::{ActiveItemSlotUpdate, ItemSlotQuantityUpdate, ItemSlotUpdate, NewPlayer, PlayerCursorUpdate, PlayerFacingUpdate, PlayerHealthUpdate, PlayerId}, player_movement::{PlayerInputUpdate, PlayerStateUpdate}, base_prop::{DissolveProp, NewProp, PropPositionUpdate, PropUpdateOwner, PropVelocityUpdate, RemovePropUpdate, StupidDissolvedPixelVelocityUpdate}, uuid_u64, interest::{ClientViewUpdate, PacketRelevance}, prop_ownership::PropInteraction, enemy_perception::NoiseUpdate, lobby::{GamePhaseUpdate, PlayerLeftLobby, PlayerReadyUpdate}, fixed_timestep::TickRateUpdate, voxel_diff::PropVoxelDiffs, wave_director::WaveUpdate};
 use derive_more::From;
 use serde::{Deserialize, Serialize};
 
@@ -9,12 +9,10 @@ pub enum NetworkPacket {
     PropVelocityUpdate(PropVelocityUpdate),
     PropUpdateOwner(PropUpdateOwner),
     NewProp(NewProp),
-    PlayerVelocityUpdate(PlayerVelocityUpdate),
     NewPlayer(NewPlayer),
     PlayerCursorUpdate(PlayerCursorUpdate),
     PlayerFacingUpdate(PlayerFacingUpdate),
     SpawnBulletTrail(SpawnBulletTrail),
-    PlayerPositionUpdate(PlayerPositionUpdate),
     PropPositionUpdate(PropPositionUpdate),
     RemovePropUpdate(RemovePropUpdate),
     DissolveProp(DissolveProp),
@@ -34,8 +32,177 @@ pub enum NetworkPacket {
     MasterUpdate(MasterUpdate),
     PlayerDespawnUpdate(PlayerDespawnUpdate),
     StupidDissolvedPixelVelocityUpdate(StupidDissolvedPixelVelocityUpdate),
-    UpdatePropVoxels(UpdatePropVoxels),
-    SetPropVoxel(SetPropVoxel)
+    PropVoxelDiffs(PropVoxelDiffs),
+    PlayerInputUpdate(PlayerInputUpdate),
+    PlayerStateUpdate(PlayerStateUpdate),
+    ClientViewUpdate(ClientViewUpdate),
+    PlayerReadyUpdate(PlayerReadyUpdate),
+    PlayerLeftLobby(PlayerLeftLobby),
+    GamePhaseUpdate(GamePhaseUpdate),
+    WaveUpdate(WaveUpdate),
+    ChatMessage(ChatMessage),
+    LatencyUpdate(LatencyUpdate),
+    PropInteraction(PropInteraction),
+    NoiseUpdate(NoiseUpdate),
+    TickRateUpdate(TickRateUpdate)
+}
+
+impl NetworkPacket {
+    /// Packets that carry typetag saves (props, items) can't be decoded without a self describing format like json
+    pub fn requires_self_describing(&self) -> bool {
+        match self {
+            NetworkPacket::LoadArea(_) |
+            NetworkPacket::NewProp(_) |
+            NetworkPacket::NewPlayer(_) |
+            NetworkPacket::NewDroppedItemUpdate(_) |
+            NetworkPacket::ItemSlotUpdate(_) |
+            NetworkPacket::NewEnemyUpdate(_) |
+            NetworkPacket::EnemyWeaponUpdate(_) => true,
+            NetworkPacket::Ping(_) |
+            NetworkPacket::PropVelocityUpdate(_) |
+            NetworkPacket::PropUpdateOwner(_) |
+            NetworkPacket::PlayerCursorUpdate(_) |
+            NetworkPacket::PlayerFacingUpdate(_) |
+            NetworkPacket::SpawnBulletTrail(_) |
+            NetworkPacket::PropPositionUpdate(_) |
+            NetworkPacket::RemovePropUpdate(_) |
+            NetworkPacket::DissolveProp(_) |
+            NetworkPacket::DroppedItemVelocityUpdate(_) |
+            NetworkPacket::RemoveDroppedItemUpdate(_) |
+            NetworkPacket::ItemSlotQuantityUpdate(_) |
+            NetworkPacket::ActiveItemSlotUpdate(_) |
+            NetworkPacket::EnemyVelocityUpdate(_) |
+            NetworkPacket::EnemyPositionUpdate(_) |
+            NetworkPacket::EnemyDespawnUpdate(_) |
+            NetworkPacket::PlayerHealthUpdate(_) |
+            NetworkPacket::EnemyHealthUpdate(_) |
+            NetworkPacket::MasterUpdate(_) |
+            NetworkPacket::PlayerDespawnUpdate(_) |
+            NetworkPacket::StupidDissolvedPixelVelocityUpdate(_) |
+            NetworkPacket::PropVoxelDiffs(_) |
+            NetworkPacket::PlayerInputUpdate(_) |
+            NetworkPacket::PlayerStateUpdate(_) |
+            NetworkPacket::ClientViewUpdate(_) |
+            NetworkPacket::PlayerReadyUpdate(_) |
+            NetworkPacket::PlayerLeftLobby(_) |
+            NetworkPacket::GamePhaseUpdate(_) |
+            NetworkPacket::WaveUpdate(_) |
+            NetworkPacket::ChatMessage(_) |
+            NetworkPacket::LatencyUpdate(_) |
+            NetworkPacket::PropInteraction(_) |
+            NetworkPacket::NoiseUpdate(_) |
+            NetworkPacket::TickRateUpdate(_) => false
+        }
+    }
+
+    /// State that gets sent over and over, so losing one or getting them out of order only matters until the next one arrives
+    pub fn is_unreliable(&self) -> bool {
+        match self {
+            NetworkPacket::PropVelocityUpdate(_) |
+            NetworkPacket::PropPositionUpdate(_) |
+            NetworkPacket::PlayerCursorUpdate(_) |
+            NetworkPacket::DroppedItemVelocityUpdate(_) |
+            NetworkPacket::EnemyVelocityUpdate(_) |
+            NetworkPacket::EnemyPositionUpdate(_) |
+            NetworkPacket::StupidDissolvedPixelVelocityUpdate(_) |
+            NetworkPacket::PlayerStateUpdate(_) => true,
+            NetworkPacket::Ping(_) |
+            NetworkPacket::LoadArea(_) |
+            NetworkPacket::PropUpdateOwner(_) |
+            NetworkPacket::NewProp(_) |
+            NetworkPacket::NewPlayer(_) |
+            NetworkPacket::PlayerFacingUpdate(_) |
+            NetworkPacket::SpawnBulletTrail(_) |
+            NetworkPacket::RemovePropUpdate(_) |
+            NetworkPacket::DissolveProp(_) |
+            NetworkPacket::NewDroppedItemUpdate(_) |
+            NetworkPacket::RemoveDroppedItemUpdate(_) |
+            NetworkPacket::ItemSlotQuantityUpdate(_) |
+            NetworkPacket::ActiveItemSlotUpdate(_) |
+            NetworkPacket::ItemSlotUpdate(_) |
+            NetworkPacket::NewEnemyUpdate(_) |
+            NetworkPacket::EnemyWeaponUpdate(_) |
+            NetworkPacket::EnemyDespawnUpdate(_) |
+            NetworkPacket::PlayerHealthUpdate(_) |
+            NetworkPacket::EnemyHealthUpdate(_) |
+            NetworkPacket::MasterUpdate(_) |
+            NetworkPacket::PlayerDespawnUpdate(_) |
+            NetworkPacket::PropVoxelDiffs(_) |
+            NetworkPacket::PlayerInputUpdate(_) |
+            NetworkPacket::ClientViewUpdate(_) |
+            NetworkPacket::PlayerReadyUpdate(_) |
+            NetworkPacket::PlayerLeftLobby(_) |
+            NetworkPacket::GamePhaseUpdate(_) |
+            NetworkPacket::WaveUpdate(_) |
+            NetworkPacket::ChatMessage(_) |
+            NetworkPacket::LatencyUpdate(_) |
+            NetworkPacket::PropInteraction(_) |
+            NetworkPacket::NoiseUpdate(_) |
+            NetworkPacket::TickRateUpdate(_) => false
+        }
+    }
+
+    /// Used by the server to skip sending packets to clients that are in another area or too far away to care
+    pub fn relevance(&self) -> PacketRelevance {
+        let area_id = match self {
+            NetworkPacket::Ping(_) |
+            NetworkPacket::LatencyUpdate(_) |
+            NetworkPacket::LoadArea(_) |
+            NetworkPacket::ClientViewUpdate(_) |
+            NetworkPacket::PlayerReadyUpdate(_) |
+            NetworkPacket::PlayerLeftLobby(_) |
+            NetworkPacket::GamePhaseUpdate(_) |
+            NetworkPacket::TickRateUpdate(_) => None,
+            NetworkPacket::PropVelocityUpdate(update) => Some(update.area_id),
+            NetworkPacket::PropUpdateOwner(update) => Some(update.area_id),
+            NetworkPacket::PropInteraction(update) => Some(update.area_id),
+            NetworkPacket::NoiseUpdate(update) => Some(update.area_id),
+            NetworkPacket::NewProp(update) => Some(update.area_id),
+            NetworkPacket::NewPlayer(update) => Some(update.area_id),
+            NetworkPacket::PlayerCursorUpdate(update) => Some(update.area_id),
+            NetworkPacket::PlayerFacingUpdate(update) => Some(update.area_id),
+            NetworkPacket::SpawnBulletTrail(update) => Some(update.area_id),
+            NetworkPacket::PropPositionUpdate(update) => Some(update.area_id),
+            NetworkPacket::RemovePropUpdate(update) => Some(update.area_id),
+            NetworkPacket::DissolveProp(update) => Some(update.area_id),
+            NetworkPacket::DroppedItemVelocityUpdate(update) => Some(update.area_id),
+            NetworkPacket::NewDroppedItemUpdate(update) => Some(update.area_id),
+            NetworkPacket::RemoveDroppedItemUpdate(update) => Some(update.area_id),
+            NetworkPacket::ItemSlotQuantityUpdate(update) => Some(update.area_id),
+            NetworkPacket::ActiveItemSlotUpdate(update) => Some(update.area_id),
+            NetworkPacket::ItemSlotUpdate(update) => Some(update.area_id),
+            NetworkPacket::NewEnemyUpdate(update) => Some(update.area_id),
+            NetworkPacket::EnemyVelocityUpdate(update) => Some(update.area_id),
+            NetworkPacket::EnemyPositionUpdate(update) => Some(update.area_id),
+            NetworkPacket::EnemyWeaponUpdate(update) => Some(update.area_id),
+            NetworkPacket::EnemyDespawnUpdate(update) => Some(update.area_id),
+            NetworkPacket::PlayerHealthUpdate(update) => Some(update.area_id),
+            NetworkPacket::EnemyHealthUpdate(update) => Some(update.area_id),
+            NetworkPacket::MasterUpdate(update) => Some(update.area_id),
+            NetworkPacket::PlayerDespawnUpdate(update) => Some(update.area_id),
+            NetworkPacket::StupidDissolvedPixelVelocityUpdate(update) => Some(update.area_id),
+            NetworkPacket::PropVoxelDiffs(update) => Some(update.area_id),
+            NetworkPacket::WaveUpdate(update) => Some(update.area_id),
+            NetworkPacket::ChatMessage(message) => message.area_id,
+            NetworkPacket::PlayerInputUpdate(update) => Some(update.area_id),
+            NetworkPacket::PlayerStateUpdate(update) => Some(update.area_id),
+        };
+
+        // only things that happen a lot and only matter up close get filtered by distance
+        let position = match self {
+            NetworkPacket::PropPositionUpdate(update) => Some(update.pos.translation),
+            NetworkPacket::EnemyPositionUpdate(update) => Some(update.position.translation),
+            NetworkPacket::PlayerStateUpdate(update) => Some(update.pos.translation),
+            NetworkPacket::SpawnBulletTrail(update) => Some(update.save.start()),
+            NetworkPacket::StupidDissolvedPixelVelocityUpdate(update) => Some(update.weapon_pos),
+            _ => None
+        };
+
+        PacketRelevance {
+            area_id,
+            position,
+        }
+    }
 }
 
 
@@ -71,6 +238,12 @@ impl Ping {
     }
 }
 
+/// The client tells the server the round trip time it measured with its pings so admins can see it
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct LatencyUpdate {
+    pub latency: web_time::Duration
+}
+
 #[derive(Serialize, Deserialize,  Clone)]
 pub struct LoadArea {
     pub area: AreaSave,
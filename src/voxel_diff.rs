@@ -0,0 +1,192 @@
+use std::{collections::HashSet, fmt::Display};
+
+use glamx::IVec2;
+use serde::{Deserialize, Serialize};
+
+use crate::{area::AreaId, base_prop::PropId};
+
+/// A set of voxel cells, run length encoded across their bounding box.
+/// Damage tends to be clumped together so this ends up way smaller than a list of coords
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct VoxelDiff {
+    origin: IVec2,
+    width: u32,
+    /// Alternating runs of untouched and changed cells, row by row, always starting with untouched
+    runs: Vec<u32>
+}
+
+impl VoxelDiff {
+    pub fn from_cells(cells: &[IVec2]) -> Self {
+
+        if cells.is_empty() {
+            return Self::default();
+        }
+
+        let min = cells.iter().fold(cells[0], |min, cell| min.min(*cell));
+        let max = cells.iter().fold(cells[0], |max, cell| max.max(*cell));
+
+        let width = (max.x - min.x + 1) as u32;
+
+        let cells: HashSet<IVec2> = cells.iter().cloned().collect();
+
+        let mut runs = Vec::new();
+        let mut current_run = 0;
+        let mut in_changed_run = false;
+
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let changed = cells.contains(&IVec2::new(x, y));
+
+                if changed != in_changed_run {
+                    runs.push(current_run);
+                    current_run = 0;
+                    in_changed_run = changed;
+                }
+
+                current_run += 1;
+            }
+        }
+
+        // the last run is always a changed one because max is a changed cell
+        runs.push(current_run);
+
+        Self {
+            origin: min,
+            width,
+            runs,
+        }
+    }
+
+    /// Turn the runs back into cells. The diff comes off the network so it gets checked against how many voxels the prop could possibly have
+    pub fn cells(&self, max_cells: usize) -> Result<Vec<IVec2>, VoxelDiffError> {
+        let mut cells = Vec::new();
+
+        if self.width == 0 {
+            return Ok(cells);
+        }
+
+        let mut index: u32 = 0;
+
+        for (run_index, run) in self.runs.iter().enumerate() {
+
+            let run_end = index.checked_add(*run).ok_or(VoxelDiffError::Overflow)?;
+
+            // odd runs are the changed cells
+            if run_index % 2 == 1 {
+
+                if cells.len().saturating_add(*run as usize) > max_cells {
+                    return Err(VoxelDiffError::TooManyCells { max_cells });
+                }
+
+                for cell_index in index..run_end {
+                    let x = offset(self.origin.x, cell_index % self.width)?;
+                    let y = offset(self.origin.y, cell_index / self.width)?;
+
+                    cells.push(IVec2::new(x, y));
+                }
+            }
+
+            index = run_end;
+        }
+
+        Ok(cells)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.len() < 2
+    }
+}
+
+fn offset(origin: i32, offset: u32) -> Result<i32, VoxelDiffError> {
+    i32::try_from(offset).ok()
+        .and_then(|offset| origin.checked_add(offset))
+        .ok_or(VoxelDiffError::Overflow)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VoxelDiffError {
+    /// The runs or coords don't fit in an integer, so someone made this up
+    Overflow,
+    /// More cells than the prop has voxels
+    TooManyCells {
+        max_cells: usize
+    }
+}
+
+impl Display for VoxelDiffError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VoxelDiffError::Overflow => write!(f, "voxel diff overflowed"),
+            VoxelDiffError::TooManyCells { max_cells } => write!(f, "voxel diff has more than {} cells", max_cells),
+        }
+    }
+}
+
+/// Voxels removed from a single prop since the last diff
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PropVoxelDiff {
+    pub prop_id: PropId,
+    pub removed: VoxelDiff
+}
+
+/// Every prop voxel change in an area for a single tick
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PropVoxelDiffs {
+    pub area_id: AreaId,
+    pub diffs: Vec<PropVoxelDiff>
+}
+
+#[cfg(test)]
+mod tests {
+    use glamx::IVec2;
+
+    use super::{VoxelDiff, VoxelDiffError};
+
+    fn sorted(mut cells: Vec<IVec2>) -> Vec<(i32, i32)> {
+        cells.sort_by_key(|cell| (cell.y, cell.x));
+        cells.into_iter().map(|cell| (cell.x, cell.y)).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let cells = vec![
+            IVec2::new(3, -2),
+            IVec2::new(4, -2),
+            IVec2::new(-1, 0),
+            IVec2::new(7, 5),
+        ];
+
+        let diff = VoxelDiff::from_cells(&cells);
+
+        assert_eq!(sorted(diff.cells(64).unwrap()), sorted(cells));
+    }
+
+    #[test]
+    fn empty_round_trip() {
+        let diff = VoxelDiff::from_cells(&[]);
+
+        assert!(diff.is_empty());
+        assert_eq!(diff.cells(0).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn rejects_more_cells_than_the_prop_has() {
+        let cells: Vec<IVec2> = (0..10).map(|x| IVec2::new(x, 0)).collect();
+
+        let diff = VoxelDiff::from_cells(&cells);
+
+        assert_eq!(diff.cells(10).unwrap().len(), 10);
+        assert_eq!(diff.cells(9), Err(VoxelDiffError::TooManyCells { max_cells: 9 }));
+    }
+
+    #[test]
+    fn rejects_overflowing_runs() {
+        let diff = VoxelDiff {
+            origin: IVec2::ZERO,
+            width: 1,
+            runs: vec![u32::MAX, 1],
+        };
+
+        assert_eq!(diff.cells(usize::MAX), Err(VoxelDiffError::Overflow));
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::VecDeque;
+
+use glamx::{Pose2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::{Owner, TickContext, area::AreaId, chat::ChatMessage, enemy::{Enemy, NewEnemyUpdate}, enemy_archetype::DEFAULT_ENEMY_ARCHETYPE, space::Space};
+
+fn default_wave_delay() -> f32 {
+    5.
+}
+
+fn default_spawn_interval() -> f32 {
+    1.
+}
+
+fn default_archetype() -> String {
+    DEFAULT_ENEMY_ARCHETYPE.to_string()
+}
+
+/// A bunch of the same enemy spawned one after another
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WaveGroup {
+    /// Path to the archetype in prefabs/enemies
+    #[serde(default = "default_archetype")]
+    pub archetype: String,
+    pub count: u32,
+    /// Path to a behaviour tree in prefabs/ai if this group should act differently to the usual for its archetype
+    #[serde(default)]
+    pub ai_profile: Option<String>,
+    /// Seconds between each enemy in this group
+    #[serde(default = "default_spawn_interval")]
+    pub interval: f32,
+    /// Seconds after the wave starts before this group starts spawning
+    #[serde(default)]
+    pub delay: f32
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Wave {
+    /// Seconds of downtime before this wave starts
+    #[serde(default = "default_wave_delay")]
+    pub delay: f32,
+    pub groups: Vec<WaveGroup>
+}
+
+impl Wave {
+    pub fn enemy_count(&self) -> u32 {
+        self.groups.iter().map(|group| group.count).sum()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum WaveEvent {
+    Started {
+        wave: u32,
+        total_waves: u32,
+        enemies: u32
+    },
+    Ended {
+        wave: u32
+    },
+    EnemiesRemaining(u32),
+    /// Every wave in the area has been beaten
+    Finished
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct WaveUpdate {
+    pub area_id: AreaId,
+    pub event: WaveEvent
+}
+
+struct PendingSpawn {
+    at: web_time::Instant,
+    archetype: String,
+    ai_profile: Option<String>
+}
+
+enum DirectorState {
+    /// Nobody is in the area so there is no point starting
+    Idle,
+    Break {
+        until: web_time::Instant
+    },
+    Wave {
+        spawns: VecDeque<PendingSpawn>
+    },
+    Finished
+}
+
+/// Runs the waves for an area. Only the server ticks this, clients just get told what happened
+pub struct WaveDirector {
+    waves: Vec<Wave>,
+    pub spawn_points: Vec<Vec2>,
+    state: DirectorState,
+    /// Index of the next wave to start
+    next_wave: usize,
+    next_spawn_point: usize,
+    last_remaining: u32
+}
+
+impl WaveDirector {
+    pub fn new(waves: Vec<Wave>, spawn_points: Vec<Vec2>) -> Self {
+        Self {
+            waves,
+            spawn_points,
+            state: DirectorState::Idle,
+            next_wave: 0,
+            next_spawn_point: 0,
+            last_remaining: 0,
+        }
+    }
+
+    pub fn waves(&self) -> &Vec<Wave> {
+        &self.waves
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, DirectorState::Finished)
+    }
+
+    /// Cut the break short so the next wave starts on the next tick
+    pub fn start_next_wave(&mut self) -> Result<(), String> {
+        if self.waves.is_empty() || self.spawn_points.is_empty() {
+            return Err("area has no waves".to_string());
+        }
+
+        match self.state {
+            DirectorState::Idle | DirectorState::Break { .. } => {
+                self.state = DirectorState::Break { until: web_time::Instant::now() };
+
+                Ok(())
+            },
+            DirectorState::Wave { .. } => Err("a wave is already running".to_string()),
+            DirectorState::Finished => Err("every wave has already been beaten".to_string()),
+        }
+    }
+
+    /// Stop spawning the rest of the current wave. The wave ends once the enemies that are already out are gone
+    pub fn cancel_pending_spawns(&mut self) -> Result<(), String> {
+        match &mut self.state {
+            DirectorState::Wave { spawns } => {
+                spawns.clear();
+
+                Ok(())
+            },
+            _ => Err("no wave is running".to_string()),
+        }
+    }
+
+    pub fn tick(
+        &mut self,
+        ctx: &mut TickContext,
+        area_id: AreaId,
+        enemies: &mut Vec<Enemy>,
+        space: &mut Space,
+        players_present: bool
+    ) {
+
+        if self.waves.is_empty() || self.spawn_points.is_empty() {
+            return;
+        }
+
+        let now = web_time::Instant::now();
+
+        match &mut self.state {
+            DirectorState::Idle => {
+                if players_present {
+                    self.start_break();
+                }
+            },
+            DirectorState::Break { until } => {
+                if now >= *until {
+                    self.start_wave(ctx, area_id);
+                }
+            },
+            DirectorState::Wave { spawns } => {
+
+                while spawns.front().is_some_and(|spawn| spawn.at <= now) {
+                    let spawn = spawns.pop_front().unwrap();
+
+                    let spawn_point = self.spawn_points[self.next_spawn_point % self.spawn_points.len()];
+
+                    self.next_spawn_point += 1;
+
+                    spawn_enemy(ctx, area_id, enemies, space, spawn_point, &spawn);
+                }
+
+                let pending = spawns.len() as u32;
+
+                let remaining = pending + enemies.iter().filter(|enemy| !enemy.despawn && enemy.health > 0).count() as u32;
+
+                if remaining != self.last_remaining {
+                    self.last_remaining = remaining;
+
+                    ctx.send_network_packet(WaveUpdate { area_id, event: WaveEvent::EnemiesRemaining(remaining) }.into());
+                }
+
+                if remaining == 0 {
+                    ctx.send_network_packet(WaveUpdate { area_id, event: WaveEvent::Ended { wave: self.next_wave as u32 } }.into());
+
+                    ctx.send_network_packet(ChatMessage::system(Some(area_id), format!("Wave {} cleared", self.next_wave)).into());
+
+                    if self.next_wave >= self.waves.len() {
+                        self.state = DirectorState::Finished;
+
+                        ctx.send_network_packet(WaveUpdate { area_id, event: WaveEvent::Finished }.into());
+
+                        return;
+                    }
+
+                    self.start_break();
+                }
+            },
+            DirectorState::Finished => {},
+        }
+    }
+
+    fn start_break(&mut self) {
+        let delay = self.waves[self.next_wave].delay;
+
+        self.state = DirectorState::Break {
+            until: web_time::Instant::now() + web_time::Duration::from_secs_f32(delay.max(0.)),
+        };
+    }
+
+    fn start_wave(&mut self, ctx: &mut TickContext, area_id: AreaId) {
+
+        let wave = &self.waves[self.next_wave];
+
+        let start = web_time::Instant::now();
+
+        let mut spawns = Vec::new();
+
+        for group in &wave.groups {
+            for index in 0..group.count {
+                spawns.push(
+                    PendingSpawn {
+                        at: start + web_time::Duration::from_secs_f32((group.delay + group.interval * index as f32).max(0.)),
+                        archetype: group.archetype.clone(),
+                        ai_profile: group.ai_profile.clone(),
+                    }
+                );
+            }
+        }
+
+        spawns.sort_by_key(|spawn| spawn.at);
+
+        self.next_wave += 1;
+        self.last_remaining = wave.enemy_count();
+
+        log::info!("Starting wave {} in area {:?}", self.next_wave, area_id);
+
+        ctx.send_network_packet(
+            WaveUpdate {
+                area_id,
+                event: WaveEvent::Started {
+                    wave: self.next_wave as u32,
+                    total_waves: self.waves.len() as u32,
+                    enemies: wave.enemy_count(),
+                },
+            }.into()
+        );
+
+        ctx.send_network_packet(
+            ChatMessage::system(Some(area_id), format!("Wave {}/{} started", self.next_wave, self.waves.len())).into()
+        );
+
+        self.state = DirectorState::Wave { spawns: spawns.into() };
+    }
+}
+
+fn spawn_enemy(ctx: &mut TickContext, area_id: AreaId, enemies: &mut Vec<Enemy>, space: &mut Space, spawn_point: Vec2, spawn: &PendingSpawn) {
+
+    let Some(prefabs) = ctx.prefabs() else {
+        return;
+    };
+
+    let mut enemy = Enemy::spawn(
+        Pose2::new(spawn_point, 0.),
+        Owner::Server,
+        space,
+        prefabs,
+        &spawn.archetype
+    );
+
+    if let Some(ai_profile) = &spawn.ai_profile {
+        enemy.ai_profile = ai_profile.clone();
+    }
+
+    ctx.send_network_packet(
+        NewEnemyUpdate {
+            area_id,
+            enemy: enemy.save(space),
+        }.into()
+    );
+
+    enemies.push(enemy);
+}
+
+/// What the client knows about the waves so the hud can show it
+#[derive(Default)]
+pub struct WaveStatus {
+    pub wave: u32,
+    pub total_waves: u32,
+    pub enemies_remaining: u32,
+    pub in_progress: bool,
+    pub finished: bool
+}
+
+impl WaveStatus {
+    pub fn apply(&mut self, event: WaveEvent) {
+        match event {
+            WaveEvent::Started { wave, total_waves, enemies } => {
+                self.wave = wave;
+                self.total_waves = total_waves;
+                self.enemies_remaining = enemies;
+                self.in_progress = true;
+                self.finished = false;
+            },
+            WaveEvent::Ended { wave } => {
+                self.wave = wave;
+                self.enemies_remaining = 0;
+                self.in_progress = false;
+            },
+            WaveEvent::EnemiesRemaining(remaining) => {
+                self.enemies_remaining = remaining;
+            },
+            WaveEvent::Finished => {
+                self.in_progress = false;
+                self.finished = true;
+            },
+        }
+    }
+}
@@ -2,11 +2,11 @@ use std::path::PathBuf;
 
 use derive_more::From;
 use glamx::Pose2;
-use macroquad::{audio::{PlaySoundParams, play_sound}, color::Color, input::{is_mouse_button_down, is_mouse_button_released}, math::Vec2, models::draw_mesh, rand::RandomRange};
+use macroquad::{audio::{PlaySoundParams, play_sound}, color::Color, input::is_mouse_button_down, math::Vec2, models::draw_mesh, rand::RandomRange};
 use rapier2d::{math::Vector, prelude::{ColliderHandle, ImpulseJointHandle, InteractionGroups, RevoluteJointBuilder, RigidBodyBuilder, RigidBodyHandle}};
 use serde::{Deserialize, Serialize};
 
-use crate::{ClientId, ClientTickContext, Owner, SwapIter, TickContext, area::{self, AreaContext, AreaId}, base_prop::StupidDissolvedPixelVelocityUpdate, bullet_trail::{BulletTrail, SpawnBulletTrail}, collider_from_texture_size, draw_preview, draw_texture_onto_physics_body, drawable::{DrawContext, Drawable}, enemy::EnemyId, get_intersections, get_preview_resolution, items::{ConsumedStatus, Item, item_save::ItemSave}, player::{Facing, PlayerContext, PlayerId}, space::Space, texture_loader::ClientTextureLoader, weapons::{Weapon, ItemOwnerContext, bullet_impact_data::BulletImpactData, weapon::weapon_save::WeaponSave, weapon_fire_context::WeaponFireContext, weapon_type::ShooterContext}};
+use crate::{ClientId, ClientTickContext, Owner, SwapIter, TickContext, area::{self, AreaContext, AreaId}, base_prop::StupidDissolvedPixelVelocityUpdate, bullet_trail::{BulletTrail, SpawnBulletTrail}, collider_from_texture_size, draw_preview, draw_texture_onto_physics_body, drawable::{DrawContext, Drawable}, enemy::EnemyId, enemy_perception::{GUNFIRE_NOISE_RADIUS, Noise, make_noise}, get_intersections, get_preview_resolution, items::{ConsumedStatus, Item, item_save::ItemSave}, player::{Facing, PlayerContext, PlayerId}, space::Space, texture_loader::ClientTextureLoader, weapons::{Weapon, ItemOwnerContext, bullet_impact_data::BulletImpactData, weapon::weapon_save::WeaponSave, weapon_fire_context::WeaponFireContext, weapon_type::ShooterContext}};
 
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize, From)]
@@ -156,7 +156,7 @@ impl BaseWeapon {
             self.reload();
 
             if let TickContext::Client(ctx) = ctx {
-                if is_mouse_button_released(macroquad::input::MouseButton::Left) {
+                if ctx.input.is_mouse_button_released(macroquad::input::MouseButton::Left) {
                     let sound = ctx.sounds.get(PathBuf::from("assets\\sounds\\pistol_dry_fire.wav"));
                     play_sound(sound, PlaySoundParams {
                         looped: false,
@@ -348,6 +348,16 @@ impl BaseWeapon {
         );
         self.send_stupid_updates(&bullet_vectors, ctx, area_context);
 
+        // everyone nearby hears it, and it gives away where a player is
+        let noise_player = match weapon_owner_context {
+            ItemOwnerContext::Player(player_context) => Some(*player_context.id),
+            ItemOwnerContext::Enemy(_) => None,
+        };
+
+        let weapon_pos = area_context.space.rigid_body_set.get(self.rigid_body.unwrap()).unwrap().translation();
+
+        make_noise(ctx, area_context.noises, *area_context.id, Noise::new(weapon_pos, GUNFIRE_NOISE_RADIUS, noise_player));
+
         let bullet_impacts = self.get_bullet_impacts(
             ctx, 
             area_context, 